@@ -3,27 +3,38 @@
 in vec2 uv;
 in vec3 normal;
 in vec3 frag_pos;
+in vec4 tangent;
 
 out vec4 o_colour;
 
 uniform vec3 u_camera_pos;
 
 uniform float u_opacity = 1.0;
+uniform bool u_unlit = false;
 
 struct Material {
     sampler2D diffuse;
     sampler2D roughness;
     sampler2D metallic;
     sampler2D normal;
+    bool has_maps; // simple textures only have a diffuse map
 };
 
 uniform Material u_material;
 
-// point light
+// point, directional or spot, see LightKind
+#define LIGHT_POINT 0
+#define LIGHT_DIRECTIONAL 1
+#define LIGHT_SPOT 2
+
 struct Light {
     vec3 position;
     vec3 colour;
     float intensity;
+    int kind;
+    vec3 direction; // directional and spot lights
+    float cos_angle; // of the edge of a spot light's cone
+    int shadow_layer; // the first layer of its shadow map, -1 if it hasn't got one
 };
 
 #define MAX_LIGHTS 100
@@ -31,46 +42,163 @@ struct Light {
 uniform Light u_lights[MAX_LIGHTS];
 uniform int u_light_count;
 
+#define MAX_SHADOW_LAYERS 12
+
+uniform sampler2DArray u_shadow_maps;
+uniform mat4 u_shadow_matrices[MAX_SHADOW_LAYERS];
+
+// which face of a point light's cube something in this direction from the light is on (+x -x +y -y +z -z)
+int cube_face(vec3 direction) {
+    vec3 a = abs(direction);
+    if (a.x >= a.y && a.x >= a.z) {
+        return direction.x > 0.0 ? 0 : 1;
+    }
+    if (a.y >= a.z) {
+        return direction.y > 0.0 ? 2 : 3;
+    }
+    return direction.z > 0.0 ? 4 : 5;
+}
+
+// how much of the light reaches frag_pos, from 0 (in shadow) to 1, averaged over the 3x3 texels around it so that
+// the edges of shadows aren't jagged
+float calculate_shadow(Light light, vec3 normal, vec3 frag_pos, vec3 light_dir) {
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+    int layer = light.shadow_layer;
+    if (light.kind == LIGHT_POINT) {
+        layer += cube_face(frag_pos - light.position);
+    }
+    vec4 light_space = u_shadow_matrices[layer] * vec4(frag_pos, 1.0);
+    vec3 coords = light_space.xyz / light_space.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    // surfaces facing away from the light need more bias to not shadow themselves
+    float bias = max(0.002 * (1.0 - dot(normal, light_dir)), 0.0002);
+    vec2 texel_size = 1.0 / vec2(textureSize(u_shadow_maps, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float depth = texture(u_shadow_maps, vec3(coords.xy + vec2(x, y) * texel_size, float(layer))).r;
+            lit += coords.z - bias > depth ? 0.0 : 1.0;
+        }
+    }
+    return lit / 9.0;
+}
+
+// the direction from frag_pos towards the light
+vec3 light_direction(Light light, vec3 frag_pos) {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        return normalize(-light.direction);
+    }
+    return normalize(light.position - frag_pos);
+}
+
+// 1 inside a spot light's cone, fading to 0 at the edge, and always 1 for other lights
+float calculate_cone(Light light, vec3 light_dir) {
+    if (light.kind != LIGHT_SPOT) {
+        return 1.0;
+    }
+    float theta = dot(light_dir, normalize(-light.direction));
+    return smoothstep(light.cos_angle, mix(light.cos_angle, 1.0, 0.1), theta);
+}
+
+// metallic/roughness pbr, cook-torrance with a ggx distribution like most engines use
+#define PI 3.14159265359
+
+// what the material looks like without the normal, metallic and roughness maps
+#define DEFAULT_ROUGHNESS 0.5
+
 vec3 calculate_ambient(float strength, vec3 colour) {
     return strength * colour;
 }
 
-vec3 calculate_light(Light light, Material material, vec2 uv, vec3 normal, vec3 frag_pos, vec3 view_dir) {
-    vec3 light_dir = normalize(light.position - frag_pos);
+// how many of the surface's microfacets face halfway between the light and the eye
+float distribution_ggx(vec3 normal, vec3 halfway_dir, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float n_dot_h = max(dot(normal, halfway_dir), 0.0);
+    float denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denominator * denominator);
+}
+
+// how much of the surface the microfacets shadow or hide from one direction
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// from both the eye and the light
+float geometry_smith(vec3 normal, vec3 view_dir, vec3 light_dir, float roughness) {
+    return geometry_schlick_ggx(max(dot(normal, view_dir), 0.0), roughness) * geometry_schlick_ggx(max(dot(normal, light_dir), 0.0), roughness);
+}
+
+// how much light is reflected rather than refracted, more so at grazing angles
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// surface_normal is the one without the normal map, which the shadows use
+vec3 calculate_light(Light light, vec3 albedo, float metallic, float roughness, vec3 normal, vec3 surface_normal, vec3 frag_pos, vec3 view_dir) {
+    vec3 light_dir = light_direction(light, frag_pos);
     vec3 halfway_dir = normalize(light_dir + view_dir);
 
-    float diff = max(dot(normal, light_dir), 0.0);
+    // non-metals reflect about 4% of light head on, metals reflect their colour
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+
+    float ndf = distribution_ggx(normal, halfway_dir, roughness);
+    float geometry = geometry_smith(normal, view_dir, light_dir, roughness);
+    vec3 fresnel = fresnel_schlick(max(dot(halfway_dir, view_dir), 0.0), f0);
 
-    vec3 reflect_dir = reflect(-light_dir, normal);
+    float n_dot_l = max(dot(normal, light_dir), 0.0);
+    vec3 specular = (ndf * geometry * fresnel) / (4.0 * max(dot(normal, view_dir), 0.0) * n_dot_l + 0.0001);
+    // whatever isn't reflected is diffused, except by metals which absorb it
+    vec3 diffuse = (vec3(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
 
-    float shininess = 256.0;
-    float spec = pow(max(dot(normal, halfway_dir), 0.0), shininess);
+    float visibility = calculate_cone(light, light_dir) * calculate_shadow(light, surface_normal, frag_pos, light_dir);
 
-    return light.intensity * (diff * light.colour + spec * light.colour);
+    return visibility * light.intensity * light.colour * (diffuse + specular) * n_dot_l;
+}
+
+// the normal from the normal map, which is in tangent space
+vec3 calculate_normal(vec3 surface_normal) {
+    // meshes drawn without tangents can't be normal mapped
+    vec3 t = tangent.xyz - surface_normal * dot(surface_normal, tangent.xyz);
+    if (!u_material.has_maps || length(t) < 0.0001) {
+        return surface_normal;
+    }
+    t = normalize(t);
+    vec3 b = cross(surface_normal, t) * tangent.w;
+    vec3 mapped = texture(u_material.normal, uv).rgb * 2.0 - 1.0;
+    return normalize(mat3(t, b, surface_normal) * mapped);
 }
 
 void main() {
-    float specular_strength = 0.5;
+    vec3 colour = texture(u_material.diffuse, uv).rgb;
+    if (u_unlit) {
+        o_colour = vec4(colour, u_opacity);
+        return;
+    }
 
-    vec3 norm = normalize(normal);
+    float metallic = u_material.has_maps ? texture(u_material.metallic, uv).r : 0.0;
+    // perfectly smooth surfaces would make the highlights infinitely small
+    float roughness = clamp(u_material.has_maps ? texture(u_material.roughness, uv).r : DEFAULT_ROUGHNESS, 0.04, 1.0);
+
+    vec3 surface_normal = normalize(normal);
+    vec3 norm = calculate_normal(surface_normal);
 
     vec3 view_dir = normalize(u_camera_pos - frag_pos);
 
     // calculate ambient
-    vec3 ambient = calculate_ambient(0.1, vec3(1.0, 1.0, 1.0));
+    vec3 ambient = calculate_ambient(0.1, vec3(1.0, 1.0, 1.0)) * colour;
 
-    // calculate lights (point lights)
+    // calculate lights
     vec3 result = vec3(0.0, 0.0, 0.0);
     for (int i = 0; i < u_light_count; i++) {
-        result += calculate_light(u_lights[i], u_material, uv, norm, frag_pos, view_dir);
+        result += calculate_light(u_lights[i], colour, metallic, roughness, norm, surface_normal, frag_pos, view_dir);
     }
 
-    vec3 colour = texture(u_material.diffuse, uv).rgb;
-    vec3 metallic = texture(u_material.metallic, uv).rgb;
-    vec3 roughness = texture(u_material.roughness, uv).rgb;
-    vec3 normal = texture(u_material.normal, uv).rgb;
-
-    vec3 final_colour = (ambient + result) * colour;
-
-    o_colour = vec4(final_colour, u_opacity);
+    o_colour = vec4(ambient + result, u_opacity);
 }
\ No newline at end of file
@@ -3,10 +3,12 @@
 layout(location = 0) in vec3 in_pos;
 layout(location = 1) in vec2 in_uv;
 layout(location = 2) in vec3 in_normal;
+layout(location = 3) in vec4 in_tangent; // w is which way the bitangent points
 
 out vec2 uv;
 out vec3 normal;
 out vec3 frag_pos;
+out vec4 tangent;
 
 uniform mat4 u_mvp;
 uniform mat4 u_model;
@@ -19,4 +21,5 @@ void main()
     uv = in_uv;
     mat3 normal_mat = transpose(inverse(mat3(u_model)));
     normal = normal_mat * in_normal;
+    tangent = vec4(mat3(u_model) * in_tangent.xyz, in_tangent.w);
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+#version 330 core
+
+in vec3 colour;
+
+out vec4 o_colour;
+
+void main() {
+    o_colour = vec4(colour, 1.0);
+}
@@ -0,0 +1,9 @@
+#version 330
+
+out vec4 o_colour;
+
+uniform vec4 u_colour;
+
+void main() {
+    o_colour = u_colour;
+}
@@ -0,0 +1,11 @@
+#version 330
+
+layout (location = 0) in vec3 in_pos;
+
+uniform mat4 u_mvp;
+uniform vec2 u_offset; // in normalised device coordinates
+
+void main() {
+    gl_Position = u_mvp * vec4(in_pos, 1.0);
+    gl_Position.xy += u_offset * gl_Position.w;
+}
@@ -3,7 +3,8 @@
 out uvec3 o_colour;
 
 uniform uint u_entity_id;
+uniform uint u_component_id;
 
 void main() {
-    o_colour = uvec3(u_entity_id, 0, 0);
-}
\ No newline at end of file
+    o_colour = uvec3(u_entity_id, u_component_id, 0);
+}
@@ -0,0 +1,5 @@
+#version 330
+
+// only the depth is wanted, which gets written anyway
+void main() {
+}
\ No newline at end of file
@@ -15,12 +15,21 @@ uniform sampler2D tex3; // a
 uniform float scale = 1;
 
 uniform vec3 u_camera_pos;
+uniform bool u_unlit = false;
+
+// point, directional or spot, see LightKind
+#define LIGHT_POINT 0
+#define LIGHT_DIRECTIONAL 1
+#define LIGHT_SPOT 2
 
-// point light
 struct Light {
     vec3 position;
     vec3 colour;
     float intensity;
+    int kind;
+    vec3 direction; // directional and spot lights
+    float cos_angle; // of the edge of a spot light's cone
+    int shadow_layer; // the first layer of its shadow map, -1 if it hasn't got one
 };
 
 #define MAX_LIGHTS 100
@@ -28,12 +37,74 @@ struct Light {
 uniform Light u_lights[MAX_LIGHTS];
 uniform int u_light_count;
 
+#define MAX_SHADOW_LAYERS 12
+
+uniform sampler2DArray u_shadow_maps;
+uniform mat4 u_shadow_matrices[MAX_SHADOW_LAYERS];
+
+// which face of a point light's cube something in this direction from the light is on (+x -x +y -y +z -z)
+int cube_face(vec3 direction) {
+    vec3 a = abs(direction);
+    if (a.x >= a.y && a.x >= a.z) {
+        return direction.x > 0.0 ? 0 : 1;
+    }
+    if (a.y >= a.z) {
+        return direction.y > 0.0 ? 2 : 3;
+    }
+    return direction.z > 0.0 ? 4 : 5;
+}
+
+// how much of the light reaches frag_pos, from 0 (in shadow) to 1, averaged over the 3x3 texels around it so that
+// the edges of shadows aren't jagged
+float calculate_shadow(Light light, vec3 normal, vec3 frag_pos, vec3 light_dir) {
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+    int layer = light.shadow_layer;
+    if (light.kind == LIGHT_POINT) {
+        layer += cube_face(frag_pos - light.position);
+    }
+    vec4 light_space = u_shadow_matrices[layer] * vec4(frag_pos, 1.0);
+    vec3 coords = light_space.xyz / light_space.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    // surfaces facing away from the light need more bias to not shadow themselves
+    float bias = max(0.002 * (1.0 - dot(normal, light_dir)), 0.0002);
+    vec2 texel_size = 1.0 / vec2(textureSize(u_shadow_maps, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float depth = texture(u_shadow_maps, vec3(coords.xy + vec2(x, y) * texel_size, float(layer))).r;
+            lit += coords.z - bias > depth ? 0.0 : 1.0;
+        }
+    }
+    return lit / 9.0;
+}
+
+// the direction from frag_pos towards the light
+vec3 light_direction(Light light, vec3 frag_pos) {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        return normalize(-light.direction);
+    }
+    return normalize(light.position - frag_pos);
+}
+
+// 1 inside a spot light's cone, fading to 0 at the edge, and always 1 for other lights
+float calculate_cone(Light light, vec3 light_dir) {
+    if (light.kind != LIGHT_SPOT) {
+        return 1.0;
+    }
+    float theta = dot(light_dir, normalize(-light.direction));
+    return smoothstep(light.cos_angle, mix(light.cos_angle, 1.0, 0.1), theta);
+}
+
 vec3 calculate_ambient(float strength, vec3 colour) {
     return strength * colour;
 }
 
 vec3 calculate_light(Light light, float shiny, vec3 normal, vec3 frag_pos, vec3 view_dir) {
-    vec3 light_dir = normalize(light.position - frag_pos);
+    vec3 light_dir = light_direction(light, frag_pos);
     vec3 halfway_dir = normalize(light_dir + view_dir);
 
     float diff = max(dot(normal, light_dir), 0.0);
@@ -42,7 +113,9 @@ vec3 calculate_light(Light light, float shiny, vec3 normal, vec3 frag_pos, vec3
 
     float spec = pow(max(dot(normal, halfway_dir), 0.0), shiny);
 
-    return light.intensity * (diff * light.colour + spec * light.colour);
+    float visibility = calculate_cone(light, light_dir) * calculate_shadow(light, normal, frag_pos, light_dir);
+
+    return visibility * light.intensity * (diff * light.colour + spec * light.colour);
 }
 
 // uses the mixmap to blend between the 4 textures
@@ -77,5 +150,6 @@ void main() {
     }
 
     // apply the lighting
-    o_colour = vec4(a * (ambient + result), 1);
+    vec3 lighting = u_unlit ? vec3(1.0) : ambient + result;
+    o_colour = vec4(a * lighting, 1);
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex};
+use glib::subclass::InitializingObject;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{glib, Button, CompositeTemplate, Entry, FlowBox};
+use crate::gio::glib::clone;
+use crate::h2eck_window::thumbnail_grid;
+use crate::h2eck_window::thumbnail_grid::ThumbnailImages;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::thumbnails::{ThumbnailReady, ThumbnailSource};
+use crate::worldmachine::WorldMachine;
+
+
+#[derive(CompositeTemplate, Default)]
+#[template(resource = "/com/realmicrosoft/h2eck/asset_browser.ui")]
+pub struct AssetBrowser {
+    pub worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>,
+    pub renderer: Arc<Mutex<Arc<Mutex<H2eckRenderer>>>>,
+
+    // buttons
+    #[template_child]
+    pub close_button: TemplateChild<Button>,
+    #[template_child]
+    pub add_button: TemplateChild<Button>,
+
+    #[template_child]
+    pub search_entry: TemplateChild<Entry>,
+    #[template_child]
+    pub mesh_grid: TemplateChild<FlowBox>,
+
+    pub meshes: Arc<Mutex<Vec<(String, String)>>>, // (name, path) of every model in the data folder
+    pub thumbnail_images: ThumbnailImages,
+    pub thumbnail_sender: Arc<Mutex<Option<glib::Sender<ThumbnailReady>>>>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for AssetBrowser {
+    const NAME: &'static str = "h2eckAssetBrowser";
+    type Type = super::AssetBrowser;
+    type ParentType = gtk::ApplicationWindow;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl ObjectImpl for AssetBrowser {
+    fn constructed(&self, obj: &Self::Type) {
+        // call "constructed" on parent
+        self.parent_constructed(obj);
+        self.setup(obj);
+    }
+}
+
+pub fn add_mesh(name: &str, worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>, renderer: Arc<Mutex<Arc<Mutex<H2eckRenderer>>>>) {
+    let worldmachine = worldmachine.lock().unwrap();
+    let worldmachine = worldmachine.as_ref().unwrap();
+    let mut worldmachine = worldmachine.lock().unwrap();
+    let renderer = renderer.lock().unwrap();
+    let renderer = renderer.as_ref();
+    let renderer = renderer.lock().unwrap();
+
+    let position = worldmachine.placement_position(&renderer);
+    worldmachine.add_mesh_entity(name, position);
+}
+
+impl AssetBrowser {
+    pub fn setup(&self, obj: &<AssetBrowser as ObjectSubclass>::Type) {
+        *self.thumbnail_sender.lock().unwrap() = Some(thumbnail_grid::thumbnail_sender(&self.thumbnail_images));
+
+        self.search_entry.connect_changed(clone!(@weak obj => move |_| {
+            obj.imp().show_thumbnails();
+        }));
+
+        self.close_button.connect_clicked(clone!(@weak obj => move |_| {
+            obj.close();
+        }));
+
+        // unlike the entity picker this stays open, so that a few models can be dropped in at once
+        let worldmachine = self.worldmachine.clone();
+        let renderer = self.renderer.clone();
+        let mesh_grid = self.mesh_grid.get();
+        self.add_button.connect_clicked(move |_| {
+            if let Some(name) = thumbnail_grid::selected_tile(&mesh_grid) {
+                add_mesh(&name, worldmachine.clone(), renderer.clone());
+            }
+        });
+        let worldmachine = self.worldmachine.clone();
+        let renderer = self.renderer.clone();
+        self.mesh_grid.connect_child_activated(move |_, child| {
+            add_mesh(&thumbnail_grid::tile_name(child), worldmachine.clone(), renderer.clone());
+        });
+    }
+
+    pub fn populate_meshes(&self) {
+        let worldmachine = self.worldmachine.lock().unwrap();
+        let worldmachine = worldmachine.as_ref().unwrap();
+        let worldmachine = worldmachine.lock().unwrap();
+
+        *self.meshes.lock().unwrap() = worldmachine.list_meshes();
+        drop(worldmachine);
+        self.show_thumbnails();
+    }
+
+    pub fn show_thumbnails(&self) {
+        let search = self.search_entry.text().trim().to_lowercase();
+        let grid = self.mesh_grid.get();
+        thumbnail_grid::clear(&grid, &self.thumbnail_images);
+        let shown = self.meshes.lock().unwrap().iter()
+            .filter(|(name, _)| search.is_empty() || name.to_lowercase().contains(&search))
+            .cloned()
+            .collect::<Vec<_>>();
+        for (name, path) in shown.iter() {
+            thumbnail_grid::add_tile(&grid, &self.thumbnail_images, name, name, path);
+        }
+
+        let sender = self.thumbnail_sender.lock().unwrap();
+        let sender = match sender.as_ref() {
+            Some(sender) => sender,
+            None => return,
+        };
+        let renderer = self.renderer.lock().unwrap();
+        let mut renderer = renderer.lock().unwrap();
+        for (name, path) in shown {
+            renderer.request_thumbnail(ThumbnailSource::Mesh { name, path }, sender.clone());
+        }
+    }
+}
+
+impl WidgetImpl for AssetBrowser {}
+impl WindowImpl for AssetBrowser {}
+impl ApplicationWindowImpl for AssetBrowser {}
@@ -1,6 +1,7 @@
 use std::any::Any;
 use std::ops::Deref;
 use std::path::Path;
+use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use gfx_maths::{Quaternion, Vec3};
@@ -10,15 +11,30 @@ use gtk::subclass::prelude::*;
 use gtk::{glib, Button, CompositeTemplate, PopoverMenuBar, Inhibit, GLArea, gdk, pango, MessageDialog, DialogFlags, MessageType, ButtonsType, ResponseType, TreePath};
 use gtk::gdk::ffi::GdkGLContext;
 use gtk::gio::Menu;
-use gtk::glib::{Type, Value};
+use gtk::glib::{Continue, Type, Value};
 use crate::gio;
 use crate::gio::glib::clone;
 use crate::gio::SimpleAction;
 use crate::h2eck_window::component_picker::ComponentPicker;
+use crate::h2eck_window::editor::session;
+use crate::h2eck_window::editor::session::MapSession;
+use crate::h2eck_window::entity_exporter::EntityExporter;
 use crate::h2eck_window::entity_namer::EntityNamer;
+use crate::h2eck_window::asset_browser::AssetBrowser;
 use crate::h2eck_window::entity_picker::EntityPicker;
+use crate::h2eck_window::show_error_dialog;
+use crate::playtest::{Playtest, PLAYTEST_MAP_NAME, PlaytestMessage};
 use crate::renderer::H2eckRenderer;
+use crate::renderer::camera::{CameraMode, CameraView};
+use crate::renderer::gizmo::{GizmoMode, GizmoSpace};
+use crate::renderer::viewports::{ShadingMode, ViewportLayout};
+use crate::settings;
+use crate::settings::MapCompiler;
 use crate::worldmachine::{World, WorldMachine};
+use crate::worldmachine::bookmarks::CameraBookmark;
+use crate::worldmachine::compiler;
+use crate::worldmachine::compiler::{CompilerMessage, Diagnostic, Severity};
+use crate::worldmachine::errors::WorldFileError;
 use crate::worldmachine::ecs::{Component, COMPONENT_TYPES, ParameterValue};
 
 
@@ -56,6 +72,18 @@ pub struct Editor {
     pub save_as: TemplateChild<gtk::Button>,
     #[template_child]
     pub bake_and_export: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub compile_progress: TemplateChild<gtk::ProgressBar>,
+    #[template_child]
+    pub play: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub stop_playtest: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub restart_playtest: TemplateChild<gtk::Button>,
+
+    // log panel
+    #[template_child]
+    pub log_view: TemplateChild<gtk::TextView>,
 
     // inspector buttons
     #[template_child]
@@ -63,23 +91,85 @@ pub struct Editor {
     #[template_child]
     pub remove_component: TemplateChild<gtk::Button>,
 
+    // bookmarks
+    #[template_child]
+    pub bookmark_list: TemplateChild<gtk::TreeView>,
+    #[template_child]
+    pub bookmark_key_column: TemplateChild<gtk::TreeViewColumn>,
+    #[template_child]
+    pub bookmark_name_column: TemplateChild<gtk::TreeViewColumn>,
+    #[template_child]
+    pub bookmark_key_renderer: TemplateChild<gtk::CellRendererText>,
+    #[template_child]
+    pub bookmark_name_renderer: TemplateChild<gtk::CellRendererText>,
+    #[template_child]
+    pub add_bookmark: TemplateChild<gtk::Button>,
+    #[template_child]
+    pub remove_bookmark: TemplateChild<gtk::Button>,
+
     // sb buttons
     #[template_child]
     pub add_entity: TemplateChild<gtk::Button>,
     #[template_child]
+    pub browse_assets: TemplateChild<gtk::Button>,
+    #[template_child]
     pub remove_entity: TemplateChild<gtk::Button>,
     #[template_child]
     pub rename_entity: TemplateChild<gtk::Button>,
     #[template_child]
     pub export_entity: TemplateChild<gtk::Button>,
 
+    // gizmo buttons
+    #[template_child]
+    pub gizmo_translate: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub gizmo_rotate: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub gizmo_scale: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub gizmo_local: TemplateChild<gtk::ToggleButton>,
+
+    // grid buttons
+    #[template_child]
+    pub grid_visible: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub grid_snap: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub camera_orbit: TemplateChild<gtk::ToggleButton>,
+
+    // view buttons
+    #[template_child]
+    pub view_perspective: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub view_top: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub view_front: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub view_side: TemplateChild<gtk::ToggleButton>,
+
+    // viewport buttons
+    #[template_child]
+    pub layout_single: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub layout_split: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub layout_quad: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub shading_lit: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub shading_unlit: TemplateChild<gtk::ToggleButton>,
+    #[template_child]
+    pub shading_wireframe: TemplateChild<gtk::ToggleButton>,
+
     pub sb_treestore: Arc<Mutex<Option<gtk::TreeStore>>>,
     pub it_treestore: Arc<Mutex<Option<gtk::TreeStore>>>,
+    pub bookmark_liststore: Arc<Mutex<Option<gtk::ListStore>>>,
     pub worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>,
     pub window: Arc<Mutex<Option<gtk::ApplicationWindow>>>,
     pub current_entity_id: Arc<Mutex<Option<u64>>>,
     pub current_component_name: Arc<Mutex<Option<String>>>,
     pub current_world_path: Arc<Mutex<Option<String>>>,
+    pub playtest: Arc<Mutex<Option<Playtest>>>,
 }
 
 #[glib::object_subclass]
@@ -212,7 +302,21 @@ pub fn get_component_from_sb_treepath(sb_treestore: Arc<Mutex<Option<gtk::TreeSt
     component
 }
 
-pub fn saveas(predetermined_path: Option<String>, window: Arc<Mutex<Option<gtk::ApplicationWindow>>>, worldmachine: Arc<Mutex<WorldMachine>>) {
+// saves the world to path, telling the user why if it couldn't be. returns whether it was saved
+pub fn save_world(path: &str, parent: Option<&gtk::Window>, worldmachine: Arc<Mutex<WorldMachine>>) -> bool {
+    let result = worldmachine.lock().unwrap().save_state_to_file(path);
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            error!("failed to save {}: {}", path, e);
+            show_error_dialog(parent, e.title(), &e.to_string());
+            false
+        }
+    }
+}
+
+// after_save is called once the world has actually been saved, not if the dialog is cancelled or saving fails
+pub fn saveas(predetermined_path: Option<String>, window: Arc<Mutex<Option<gtk::ApplicationWindow>>>, worldmachine: Arc<Mutex<WorldMachine>>, after_save: Option<Rc<dyn Fn()>>) {
     let window = window.lock().unwrap().as_ref().unwrap().clone();
     let dialog = gtk::FileChooserDialog::new(Some("Save World"), Some(&window), gtk::FileChooserAction::Save, &[("Cancel", gtk::ResponseType::Cancel), ("Save", gtk::ResponseType::Accept)]);
     if let Some(predetermined_path) = predetermined_path {
@@ -220,9 +324,12 @@ pub fn saveas(predetermined_path: Option<String>, window: Arc<Mutex<Option<gtk::
     }
     dialog.connect_response(move |dialog, response| {
         if response == gtk::ResponseType::Accept {
-            let mut worldmachine = worldmachine.lock().unwrap();
             let path = dialog.file().unwrap().path().unwrap();
-            worldmachine.save_state_to_file(&path.to_str().unwrap());
+            if save_world(path.to_str().unwrap(), Some(window.upcast_ref()), worldmachine.clone()) {
+                if let Some(after_save) = after_save.as_ref() {
+                    after_save();
+                }
+            }
         }
         dialog.close();
     });
@@ -237,15 +344,292 @@ pub fn open(predetermined_path: Option<String>, window: Arc<Mutex<Option<gtk::Ap
     }
     dialog.connect_response(move |dialog, response| {
         if response == gtk::ResponseType::Accept {
-            let mut worldmachine = worldmachine.lock().unwrap();
             let path = dialog.file().unwrap().path().unwrap();
-            worldmachine.load_state_from_file(&path.to_str().unwrap());
+            open_world(path.to_str().unwrap(), worldmachine.clone());
         }
         dialog.close();
     });
     dialog.show();
 }
 
+// loads a map, remembers it as recent and puts the editor back how it was when the map was last closed
+// if the map can't be loaded the user is told why, and the current world stays as it is
+pub fn open_world(path: &str, worldmachine: Arc<Mutex<WorldMachine>>) {
+    let (result, editor) = {
+        let mut worldmachine = worldmachine.lock().unwrap();
+        (worldmachine.load_state_from_file(path), worldmachine.editor.clone())
+    };
+    let editor = editor.lock().unwrap();
+    let editor = match editor.as_ref() {
+        Some(editor) => editor,
+        None => return,
+    };
+    match result {
+        Ok(_) => {
+            settings::add_recent_map(path);
+            editor.imp().restore_session(path);
+        }
+        Err(e) => {
+            error!("failed to open {}: {}", path, e);
+            if let WorldFileError::NotFound(_) = e {
+                settings::remove_recent_map(path);
+            }
+            let window = editor.imp().window.lock().unwrap().clone();
+            show_error_dialog(window.as_ref().map(|w| w.upcast_ref::<gtk::Window>()), e.title(), &e.to_string());
+        }
+    }
+}
+
+// if the world has unsaved changes, asks whether to save them first. on_continue is called unless the user cancels
+pub fn prompt_unsaved_changes<F: Fn() + 'static>(window: Arc<Mutex<Option<gtk::ApplicationWindow>>>, worldmachine: Arc<Mutex<WorldMachine>>, current_world_path: Arc<Mutex<Option<String>>>, on_continue: F) {
+    if !worldmachine.lock().unwrap().is_dirty() {
+        on_continue();
+        return;
+    }
+    let on_continue: Rc<dyn Fn()> = Rc::new(on_continue);
+    let parent = window.lock().unwrap().as_ref().unwrap().clone();
+    let dialog = MessageDialog::new(Some(&parent), DialogFlags::MODAL, MessageType::Question, ButtonsType::None, "The current world has unsaved changes, would you like to save them?");
+    dialog.set_title(Some("Save Changes?"));
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Discard", ResponseType::Reject);
+    dialog.add_button("Save", ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Accept);
+    dialog.connect_response(move |dialog, response| {
+        dialog.destroy();
+        match response {
+            ResponseType::Accept => {
+                let current_world_path = current_world_path.lock().unwrap().clone();
+                if let Some(current_world_path) = current_world_path {
+                    if save_world(&current_world_path, Some(parent.upcast_ref()), worldmachine.clone()) {
+                        on_continue();
+                    }
+                } else {
+                    saveas(None, window.clone(), worldmachine.clone(), Some(on_continue.clone()));
+                }
+            }
+            ResponseType::Reject => {
+                on_continue();
+            }
+            _ => {}
+        }
+    });
+    dialog.show();
+}
+
+// appends a line to the log panel and scrolls down to it
+pub fn append_to_log(log_view: &gtk::TextView, text: &str, tag: Option<&str>) {
+    let buffer = log_view.buffer();
+    let mut end = buffer.end_iter();
+    if let Some(tag) = tag {
+        buffer.insert_with_tags_by_name(&mut end, text, &[tag]);
+    } else {
+        buffer.insert(&mut end, text);
+    }
+    buffer.insert(&mut end, "\n");
+    let mark = buffer.create_mark(None, &buffer.end_iter(), false);
+    log_view.scroll_mark_onscreen(&mark);
+    buffer.delete_mark(&mark);
+}
+
+#[derive(Clone)]
+pub struct PlaytestControls {
+    pub log_view: gtk::TextView,
+    pub play: gtk::Button,
+    pub stop: gtk::Button,
+    pub restart: gtk::Button,
+    pub progress: gtk::ProgressBar,
+}
+
+impl PlaytestControls {
+    fn set_running(&self, running: bool) {
+        self.play.set_sensitive(!running);
+        self.stop.set_sensitive(running);
+        self.restart.set_sensitive(running);
+    }
+}
+
+// compiles the current world to a temporary map and starts the engine with it, stopping any running playtest first.
+// the map is compiled with whichever compiler the bake & export button uses
+pub fn start_playtest(worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>, playtest: Arc<Mutex<Option<Playtest>>>, controls: PlaytestControls) {
+    if let Some(mut old) = playtest.lock().unwrap().take() {
+        old.stop();
+    }
+
+    let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+    match settings::compiler() {
+        MapCompiler::BuiltIn => {
+            let compiled = worldmachine.lock().unwrap().compile_map(PLAYTEST_MAP_NAME);
+            if let Err(e) = compiled {
+                append_to_log(&controls.log_view, &format!("[h2eck] failed to compile map for playtest: {}", e), Some("error"));
+                return;
+            }
+            launch_playtest(worldmachine, playtest, controls);
+        }
+        MapCompiler::External => {
+            let map_path = worldmachine.lock().unwrap().current_world_path().unwrap_or_else(|| format!("{}.map", PLAYTEST_MAP_NAME));
+            // mapc runs in the background, don't let the playtest be started twice meanwhile
+            controls.play.set_sensitive(false);
+            let log_view = controls.log_view.clone();
+            let progress = controls.progress.clone();
+            compile_with_mapc(&map_path, PLAYTEST_MAP_NAME, worldmachine.clone(), log_view, progress, Some(Box::new(move |succeeded| {
+                if succeeded {
+                    launch_playtest(worldmachine.clone(), playtest.clone(), controls.clone());
+                } else {
+                    append_to_log(&controls.log_view, "[h2eck] failed to compile map for playtest", Some("error"));
+                    controls.set_running(false);
+                }
+            })));
+        }
+    }
+}
+
+// starts the engine on the compiled playtest map
+fn launch_playtest(worldmachine: Arc<Mutex<WorldMachine>>, playtest: Arc<Mutex<Option<Playtest>>>, controls: PlaytestControls) {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let new_playtest = Playtest::start(PLAYTEST_MAP_NAME, sender);
+    let new_playtest = match new_playtest {
+        Ok(new_playtest) => new_playtest,
+        Err(e) => {
+            append_to_log(&controls.log_view, &format!("[h2eck] {}", e), Some("error"));
+            controls.set_running(false);
+            return;
+        }
+    };
+    append_to_log(&controls.log_view, "[h2eck] playtest started", None);
+    *playtest.lock().unwrap() = Some(new_playtest);
+    controls.set_running(true);
+
+    let playtest = playtest.clone();
+    receiver.attach(None, move |message| {
+        match message {
+            PlaytestMessage::Stdout(line) => {
+                append_to_log(&controls.log_view, &line, None);
+            }
+            PlaytestMessage::Stderr(line) => {
+                append_to_log(&controls.log_view, &line, Some("error"));
+            }
+            PlaytestMessage::Exited(code) => {
+                if let Some(code) = code {
+                    append_to_log(&controls.log_view, &format!("[h2eck] engine exited with code {}", code), None);
+                } else {
+                    append_to_log(&controls.log_view, "[h2eck] engine stopped", None);
+                }
+                // if we were restarted, a newer playtest owns the buttons and the map now
+                let still_running = playtest.lock().unwrap().as_ref().map(|p| p.is_running()).unwrap_or(false);
+                if !still_running {
+                    playtest.lock().unwrap().take();
+                    controls.set_running(false);
+                    worldmachine.lock().unwrap().remove_compiled_map(PLAYTEST_MAP_NAME);
+                }
+                return Continue(false);
+            }
+        }
+        Continue(true)
+    });
+}
+
+// e.g. "maps/test.map:12: warning: entity 'door' (4): missing collider"
+fn format_diagnostic(diagnostic: &Diagnostic, default_file: &str, worldmachine: &Arc<Mutex<WorldMachine>>) -> String {
+    let mut text = diagnostic.file.clone().unwrap_or_else(|| default_file.to_string());
+    if let Some(line) = diagnostic.line {
+        text += &format!(":{}", line);
+    }
+    if let Some(column) = diagnostic.column {
+        text += &format!(":{}", column);
+    }
+    text += &format!(": {}: ", diagnostic.severity.as_str());
+    if let Some(uid) = diagnostic.entity {
+        let name = {
+            let worldmachine = worldmachine.lock().unwrap();
+            worldmachine.get_entity(uid).map(|entity| entity.lock().unwrap().name.clone())
+        };
+        if let Some(name) = name {
+            text += &format!("entity '{}' ({}): ", name, uid);
+        } else {
+            text += &format!("entity {}: ", uid);
+        }
+    }
+    text + &diagnostic.message
+}
+
+// runs mapc on a temporary copy of the world, reporting progress and diagnostics in the editor. map_path is the map
+// the diagnostics are reported against, and on_finished is called with whether mapc succeeded
+pub fn compile_with_mapc(map_path: &str, world_name: &str, worldmachine: Arc<Mutex<WorldMachine>>, log_view: gtk::TextView, progress: gtk::ProgressBar, on_finished: Option<Box<dyn Fn(bool)>>) {
+    // mapc works on a file on disk, so give it one that matches what's in the editor without touching the map itself
+    let file_name = Path::new(map_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| format!("{}.map", world_name));
+    let (input, output_dir, unsaved) = {
+        let mut worldmachine = worldmachine.lock().unwrap();
+        let unsaved = worldmachine.is_dirty() || !Path::new(map_path).exists();
+        (worldmachine.write_mapc_input(world_name, &file_name), format!("{}/maps/{}", worldmachine.game_data_path, world_name), unsaved)
+    };
+    // the copy is laid out like the saved map, so line numbers only match it if there's nothing unsaved
+    let map_path = if unsaved { format!("{} (unsaved copy)", map_path) } else { map_path.to_string() };
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            append_to_log(&log_view, &format!("[h2eck] {}", e), Some("error"));
+            if let Some(on_finished) = on_finished.as_ref() {
+                on_finished(false);
+            }
+            return;
+        }
+    };
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    if let Err(e) = compiler::run_mapc(&input, &output_dir, sender) {
+        append_to_log(&log_view, &format!("[h2eck] {}", e), Some("error"));
+        let _ = std::fs::remove_file(&input);
+        if let Some(on_finished) = on_finished.as_ref() {
+            on_finished(false);
+        }
+        return;
+    }
+    append_to_log(&log_view, &format!("[h2eck] compiling {} with mapc", map_path), None);
+    if unsaved {
+        append_to_log(&log_view, "[h2eck] the map has unsaved changes, line numbers won't match the file on disk until it's saved", Some("warning"));
+    }
+    progress.set_fraction(0.0);
+    progress.set_text(Some("compiling"));
+    progress.set_visible(true);
+
+    receiver.attach(None, move |message| {
+        match message {
+            CompilerMessage::Progress { done, total, stage } => {
+                if total > 0 {
+                    progress.set_fraction(done as f64 / total as f64);
+                }
+                progress.set_text(Some(&format!("{} ({}/{})", stage, done, total)));
+            }
+            CompilerMessage::Diagnostic(mut diagnostic) => {
+                let tag = match diagnostic.severity {
+                    Severity::Error => Some("error"),
+                    Severity::Warning => Some("warning"),
+                    Severity::Info => None,
+                };
+                // mapc only knows about the temporary copy, the user wants to hear about their map
+                if diagnostic.file.as_deref() == Some(input.as_str()) {
+                    diagnostic.file = None;
+                }
+                append_to_log(&log_view, &format_diagnostic(&diagnostic, &map_path, &worldmachine), tag);
+            }
+            CompilerMessage::Finished(result) => {
+                progress.set_visible(false);
+                let _ = std::fs::remove_file(&input);
+                let succeeded = result.is_ok();
+                match result {
+                    Ok(_) => append_to_log(&log_view, "[h2eck] mapc finished", None),
+                    Err(e) => append_to_log(&log_view, &format!("[h2eck] compile failed: {}", e), Some("error")),
+                }
+                if let Some(on_finished) = on_finished.as_ref() {
+                    on_finished(succeeded);
+                }
+                return Continue(false);
+            }
+        }
+        Continue(true)
+    });
+}
+
 impl Editor {
     pub fn setup(&self, obj: &<Editor as ObjectSubclass>::Type) {
         // create a treemodel for the scene browser
@@ -360,6 +744,79 @@ impl Editor {
             }
         });
 
+        // the bookmarks list, the row number is the bookmark's index
+        let mut model = self.bookmark_liststore.lock().unwrap();
+        *model = Some(gtk::ListStore::new(&[Type::STRING, Type::STRING]));
+        self.bookmark_list.set_model(Some(model.as_ref().unwrap()));
+        drop(model);
+        self.bookmark_key_column.add_attribute(&self.bookmark_key_renderer.get(), "text", 0);
+        self.bookmark_name_column.add_attribute(&self.bookmark_name_renderer.get(), "text", 1);
+
+        // renaming
+        let bookmark_liststore = self.bookmark_liststore.clone();
+        let worldmachine = self.worldmachine.clone();
+        self.bookmark_name_renderer.connect_edited(move |_, path, new_text| {
+            let index = match path.indices().first() {
+                Some(index) => *index as usize,
+                None => return,
+            };
+            {
+                let model = bookmark_liststore.lock().unwrap();
+                let model = model.as_ref().unwrap();
+                if let Some(iter) = model.iter(&path) {
+                    model.set(&iter, &[(1, &Value::from(new_text))]);
+                }
+            }
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            worldmachine.lock().unwrap().rename_bookmark(index, new_text);
+        });
+
+        // double clicking goes to the bookmark
+        let worldmachine = self.worldmachine.clone();
+        self.bookmark_list.connect_row_activated(clone!(@weak obj => move |_, path, _| {
+            let index = match path.indices().first() {
+                Some(index) => *index as usize,
+                None => return,
+            };
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            let bookmark = worldmachine.lock().unwrap().get_bookmark(index);
+            if let Some(bookmark) = bookmark {
+                obj.imp().go_to_bookmark(&bookmark);
+            }
+        }));
+
+        // adding a bookmark saves where the camera is now, then lets you name it straight away
+        let worldmachine = self.worldmachine.clone();
+        let renderer = self.renderer.clone();
+        self.add_bookmark.connect_clicked(clone!(@weak obj => move |_| {
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            let name = format!("Bookmark {}", worldmachine.lock().unwrap().bookmarks().len() + 1);
+            let bookmark = {
+                let renderer = renderer.lock().unwrap().clone();
+                let renderer = renderer.lock().unwrap();
+                renderer.camera_bookmark(&name)
+            };
+            if let Some(bookmark) = bookmark {
+                let index = worldmachine.lock().unwrap().add_bookmark(bookmark);
+                let editor = obj.imp();
+                let path = TreePath::from_indicesv(&[index as i32]);
+                editor.bookmark_list.grab_focus();
+                editor.bookmark_list.set_cursor(&path, Some(&editor.bookmark_name_column.get()), true);
+            }
+        }));
+
+        let worldmachine = self.worldmachine.clone();
+        let bookmark_list = self.bookmark_list.get();
+        self.remove_bookmark.connect_clicked(move |_| {
+            let (paths, _) = bookmark_list.selection().selected_rows();
+            let index = match paths.first().and_then(|path| path.indices().first().copied()) {
+                Some(index) => index as usize,
+                None => return,
+            };
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            worldmachine.lock().unwrap().remove_bookmark(index);
+        });
+
         // setup the callback for clicking the save button
         let worldmachine = self.worldmachine.clone();
         let window = self.window.clone();
@@ -372,10 +829,10 @@ impl Editor {
                     current_world_path.lock().unwrap().clone()
                 };
             if let Some(current_world_path) = current_world_path.as_ref() {
-                let mut worldmachine = worldmachine.lock().unwrap();
-                worldmachine.save_state_to_file(current_world_path);
+                let parent = window.lock().unwrap().clone();
+                save_world(current_world_path, parent.as_ref().map(|w| w.upcast_ref::<gtk::Window>()), worldmachine);
             } else {
-                saveas(None, window, worldmachine);
+                saveas(None, window, worldmachine, None);
             }
         });
         // setup the callback for clicking the save as button
@@ -389,9 +846,9 @@ impl Editor {
                 current_world_path.lock().unwrap().clone()
             };
             if let Some(current_world_path) = current_world_path.as_ref() {
-                saveas(Some(current_world_path.clone()), window, worldmachine);
+                saveas(Some(current_world_path.clone()), window, worldmachine, None);
             } else {
-                saveas(None, window, worldmachine);
+                saveas(None, window, worldmachine, None);
             }
         });
 
@@ -399,67 +856,25 @@ impl Editor {
         let worldmachine = self.worldmachine.clone();
         let window = self.window.clone();
         let current_world_path = self.current_world_path.clone();
-        self.open.connect_clicked(move |_| {
+        self.open.connect_clicked(clone!(@weak obj => move |_| {
             let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
             let window = window.clone();
             let current_world_path = current_world_path.clone();
-            let current_world_path = current_world_path.lock().unwrap();
-            if let Some(current_world_path) = current_world_path.as_ref() {
-                open(Some(current_world_path.clone()), window, worldmachine);
-            } else {
-                open(None, window, worldmachine);
-            }
-        });
+            obj.confirm_discard_changes(move || {
+                let current_world_path = current_world_path.lock().unwrap().clone();
+                open(current_world_path, window.clone(), worldmachine.clone());
+            });
+        }));
 
         // setup the callback for clicking the new button
         let worldmachine = self.worldmachine.clone();
-        let window = self.window.clone();
-        let current_world_path = self.current_world_path.clone();
-        self.new.connect_clicked(move |_| {
-            let worldmachine = worldmachine.clone();
-            let window = window.clone();
-            let windowinner = window.clone();
-            let windowinner = windowinner.lock().unwrap();
-            let windowinner = windowinner.as_ref().unwrap();
-            let current_world_path = current_world_path.clone();
-            // prompt the user to save the current world
-            let dialog = MessageDialog::new(Some(windowinner), DialogFlags::MODAL, MessageType::Question, ButtonsType::YesNo, "Would you like to save the current world?");
-            dialog.set_title(Some("Save Current World?"));
-            dialog.connect_response(move |dialog, response| {
-                match response {
-                    ResponseType::Yes => {
-                        let worldmachine_clone = worldmachine.clone();
-                        let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
-                        let current_world_path = current_world_path.lock().unwrap();
-                        if let Some(current_world_path) = current_world_path.as_ref() {
-                            let worldmachine = worldmachine.clone();
-                            let mut worldmachine = worldmachine.lock().unwrap();
-                            worldmachine.save_state_to_file(current_world_path);
-                        } else {
-                            let window = window.clone();
-                            let windowinner = window.lock().unwrap();
-                            let windowinner = windowinner.as_ref().unwrap();
-                            saveas(None, Arc::new(Mutex::new(Some(windowinner.clone()))), worldmachine);
-                        }
-                        let worldmachine = worldmachine_clone;
-                        let worldmachine = worldmachine.lock().unwrap();
-                        let worldmachine = worldmachine.as_ref().unwrap();
-                        let mut worldmachine = worldmachine.lock().unwrap();
-                        worldmachine.blank_slate();
-                        dialog.destroy();
-                    },
-                    ResponseType::No => {
-                        let worldmachine = worldmachine.lock().unwrap();
-                        let worldmachine = worldmachine.as_ref().unwrap();
-                        let mut worldmachine = worldmachine.lock().unwrap();
-                        worldmachine.blank_slate();
-                        dialog.destroy();
-                    },
-                    _ => {}
-                }
+        self.new.connect_clicked(clone!(@weak obj => move |_| {
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            // only bother the user if there's something to lose
+            obj.confirm_discard_changes(move || {
+                worldmachine.lock().unwrap().blank_slate();
             });
-            dialog.show();
-        });
+        }));
 
         // setup the callback for clicking the add entity button
         let worldmachine = self.worldmachine.clone();
@@ -478,10 +893,109 @@ impl Editor {
             // set worldmachine
             *entity_picker.imp().worldmachine.clone().lock().unwrap() = Some(worldmachine);
             *entity_picker.imp().renderer.clone().lock().unwrap() = renderer.clone();
-            entity_picker.imp().populate_listbox();
+            entity_picker.imp().populate_library();
             entity_picker.show();
         });
 
+        // setup the callback for clicking the browse assets button
+        let worldmachine = self.worldmachine.clone();
+        let renderer = self.renderer.clone();
+        self.browse_assets.connect_clicked(move |_| {
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            let renderer = renderer.lock().unwrap();
+
+            let asset_browser = AssetBrowser::new();
+            *asset_browser.imp().worldmachine.clone().lock().unwrap() = Some(worldmachine);
+            *asset_browser.imp().renderer.clone().lock().unwrap() = renderer.clone();
+            asset_browser.imp().populate_meshes();
+            asset_browser.show();
+        });
+
+        // the gizmo buttons pick what dragging the gizmo does
+        for (button, mode) in [
+            (self.gizmo_translate.get(), GizmoMode::Translate),
+            (self.gizmo_rotate.get(), GizmoMode::Rotate),
+            (self.gizmo_scale.get(), GizmoMode::Scale),
+        ] {
+            let renderer = self.renderer.clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    let renderer = renderer.lock().unwrap().clone();
+                    renderer.lock().unwrap().gizmo.mode = mode;
+                }
+            });
+        }
+        let renderer = self.renderer.clone();
+        self.gizmo_local.connect_toggled(move |button| {
+            let renderer = renderer.lock().unwrap().clone();
+            renderer.lock().unwrap().gizmo.space = if button.is_active() { GizmoSpace::Local } else { GizmoSpace::World };
+        });
+
+        // the grid buttons
+        let renderer = self.renderer.clone();
+        self.grid_visible.connect_toggled(move |button| {
+            let renderer = renderer.lock().unwrap().clone();
+            renderer.lock().unwrap().grid.visible = button.is_active();
+        });
+        let renderer = self.renderer.clone();
+        self.grid_snap.connect_toggled(move |button| {
+            let renderer = renderer.lock().unwrap().clone();
+            renderer.lock().unwrap().grid.snap = button.is_active();
+        });
+
+        // the camera mode button
+        let renderer = self.renderer.clone();
+        self.camera_orbit.connect_toggled(move |button| {
+            let renderer = renderer.lock().unwrap().clone();
+            renderer.lock().unwrap().set_camera_mode(if button.is_active() { CameraMode::Orbit } else { CameraMode::Fly });
+        });
+
+        // the view buttons switch between perspective and the orthographic views
+        for (button, camera_view) in [
+            (self.view_perspective.get(), CameraView::Perspective),
+            (self.view_top.get(), CameraView::Top),
+            (self.view_front.get(), CameraView::Front),
+            (self.view_side.get(), CameraView::Side),
+        ] {
+            let renderer = self.renderer.clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    let renderer = renderer.lock().unwrap().clone();
+                    renderer.lock().unwrap().set_camera_view(camera_view);
+                }
+            });
+        }
+
+        // the layout buttons split the viewport into panes
+        for (button, layout) in [
+            (self.layout_single.get(), ViewportLayout::Single),
+            (self.layout_split.get(), ViewportLayout::Split),
+            (self.layout_quad.get(), ViewportLayout::Quad),
+        ] {
+            let renderer = self.renderer.clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    let renderer = renderer.lock().unwrap().clone();
+                    renderer.lock().unwrap().set_viewport_layout(layout);
+                }
+            });
+        }
+
+        // the shading buttons apply to the active pane
+        for (button, shading) in [
+            (self.shading_lit.get(), ShadingMode::Lit),
+            (self.shading_unlit.get(), ShadingMode::Unlit),
+            (self.shading_wireframe.get(), ShadingMode::Wireframe),
+        ] {
+            let renderer = self.renderer.clone();
+            button.connect_toggled(move |button| {
+                if button.is_active() {
+                    let renderer = renderer.lock().unwrap().clone();
+                    renderer.lock().unwrap().shading = shading;
+                }
+            });
+        }
+
         // setup the callback for clicking the remove entity button
         let worldmachine = self.worldmachine.clone();
         let current_entity_id = self.current_entity_id.clone();
@@ -557,12 +1071,24 @@ impl Editor {
         let current_entity_id = self.current_entity_id.clone();
         let window = self.window.clone();
         self.export_entity.connect_clicked(move |_| {
-            let worldmachine = worldmachine.lock().unwrap();
-            let worldmachine = worldmachine.as_ref().unwrap();
-            let mut worldmachine = worldmachine.lock().unwrap();
-            let current_entity_id = current_entity_id.lock().unwrap();
-            let current_entity_id = current_entity_id.as_ref().unwrap();
-            worldmachine.save_entity_def(*current_entity_id);
+            let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
+            let current_entity_id = match *current_entity_id.lock().unwrap() {
+                Some(id) => id,
+                None => return,
+            };
+            // ask where in the library it should go
+            let entity_exporter = EntityExporter::new();
+            entity_exporter.imp().populate_folders(&worldmachine.lock().unwrap().list_entity_def_folders());
+            entity_exporter.imp().ok_button.connect_clicked(clone!(@weak entity_exporter => move |_| {
+                let folder = entity_exporter.imp().folder();
+                let description = entity_exporter.imp().description_entry.text().to_string();
+                let result = worldmachine.lock().unwrap().save_entity_def(current_entity_id, &folder, &description);
+                match result {
+                    Ok(_) => entity_exporter.close(),
+                    Err(e) => show_error_dialog(Some(entity_exporter.upcast_ref::<gtk::Window>()), "Failed to Export Entity", &e),
+                }
+            }));
+            entity_exporter.show();
         });
 
         // setup the callback for clicking the add component button
@@ -628,13 +1154,15 @@ impl Editor {
         let worldmachine = self.worldmachine.clone();
         let current_world_path = self.current_world_path.clone();
         let window = self.window.clone();
+        let log_view = self.log_view.get();
+        let compile_progress = self.compile_progress.get();
         self.bake_and_export.connect_clicked(move |_| {
             let worldmachine = worldmachine.lock().unwrap().as_ref().unwrap().clone();
             let current_world_path = current_world_path.lock().unwrap().clone();
             let current_world_path = current_world_path.as_ref();
             if current_world_path.is_none() {
                 warn!("No world path set, cannot bake and export");
-                saveas(None, window.clone(), worldmachine.clone());
+                saveas(None, window.clone(), worldmachine.clone(), None);
                 let warning_dialog = MessageDialog::new(Some(window.lock().unwrap().as_ref().unwrap()), DialogFlags::MODAL, MessageType::Warning, ButtonsType::Ok, "no world path set, please save and try again");
                 warning_dialog.set_title(Some("No World Path Set"));
                 warning_dialog.connect_response(|dialog, _| {
@@ -649,30 +1177,278 @@ impl Editor {
             if let Some(world_name) = world_name {
                 let world_name = world_name.to_str().unwrap();
                 let world_name = world_name.to_string();
-                let mut worldmachine = worldmachine.lock().unwrap();
-                worldmachine.compile_map(world_name.as_str());
+                match settings::compiler() {
+                    MapCompiler::BuiltIn => {
+                        let mut worldmachine = worldmachine.lock().unwrap();
+                        match worldmachine.compile_map(world_name.as_str()) {
+                            Ok(_) => append_to_log(&log_view, &format!("[h2eck] compiled {}", world_name), None),
+                            Err(e) => append_to_log(&log_view, &format!("[h2eck] compile failed: {}", e), Some("error")),
+                        }
+                    }
+                    MapCompiler::External => {
+                        compile_with_mapc(current_world_path, &world_name, worldmachine.clone(), log_view.clone(), compile_progress.clone(), None);
+                    }
+                }
             } else {
                 error!("could not get world name from path: {}", current_world_path);
             }
         });
+
+        // log panel
+        let log_buffer = self.log_view.buffer();
+        log_buffer.create_tag(Some("error"), &[("foreground", &"red")]);
+        log_buffer.create_tag(Some("warning"), &[("foreground", &"orange")]);
+
+        // playtest buttons
+        let controls = PlaytestControls {
+            log_view: self.log_view.get(),
+            play: self.play.get(),
+            stop: self.stop_playtest.get(),
+            restart: self.restart_playtest.get(),
+            progress: self.compile_progress.get(),
+        };
+        let worldmachine = self.worldmachine.clone();
+        let playtest = self.playtest.clone();
+        self.play.connect_clicked(clone!(@strong controls => move |_| {
+            start_playtest(worldmachine.clone(), playtest.clone(), controls.clone());
+        }));
+        let playtest = self.playtest.clone();
+        self.stop_playtest.connect_clicked(move |_| {
+            if let Some(playtest) = playtest.lock().unwrap().as_mut() {
+                playtest.stop();
+            }
+        });
+        let worldmachine = self.worldmachine.clone();
+        let playtest = self.playtest.clone();
+        self.restart_playtest.connect_clicked(clone!(@strong controls => move |_| {
+            append_to_log(&controls.log_view, "[h2eck] restarting playtest", None);
+            start_playtest(worldmachine.clone(), playtest.clone(), controls.clone());
+        }));
     }
 
-    pub fn regen_model_from_world(&self, wm: &mut World) {
-        let mut model = self.sb_treestore.lock().unwrap();
+    // shows the map's file name in the window title, with a * if there are unsaved changes
+    pub fn update_title(&self, dirty: bool) {
+        let name = self.current_world_path.lock().unwrap().as_ref()
+            .and_then(|path| Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()))
+            .unwrap_or_else(|| String::from("untitled"));
+        let window = self.window.lock().unwrap();
+        if let Some(window) = window.as_ref() {
+            window.set_title(Some(&format!("h2eck - {}{}", name, if dirty { "*" } else { "" })));
+        }
+    }
+
+    // every entity row in the scene browser, along with its uid
+    fn entity_rows(&self) -> Vec<(u64, TreePath)> {
+        let model = self.sb_treestore.lock().unwrap();
         let model = model.as_ref().unwrap();
-        model.clear();
-        let root = model.append(None);
-        model.set(&root, &[(0, &Value::from("worldmachine"))]);
-        for entity in wm.clone().entities {
-            let entity_node = model.append(Some(&root));
-            model.set(&entity_node, &[(0, &Value::from(entity.get_name())), (1, &Value::from(entity.get_id().to_string().as_str()))]);
-            for component in entity.get_components() {
-                let component_node = model.append(Some(&entity_node));
-                model.set(&component_node, &[(0, &Value::from(component.get_name()))]);
+        let mut rows = Vec::new();
+        let root = match model.iter_first() {
+            Some(root) => root,
+            None => return rows,
+        };
+        if let Some(child) = model.iter_children(Some(&root)) {
+            loop {
+                if let Ok(uid) = model.get_value(&child, 1).get::<String>() {
+                    if let Ok(uid) = uid.parse::<u64>() {
+                        rows.push((uid, model.path(&child)));
+                    }
+                }
+                if !model.iter_next(&child) {
+                    break;
+                }
+            }
+        }
+        rows
+    }
+
+    pub fn capture_session(&self) -> Option<MapSession> {
+        let renderer = self.renderer.lock().unwrap().clone();
+        let renderer = renderer.lock().unwrap();
+        let camera = renderer.camera.as_ref()?;
+        let expanded_entities = self.entity_rows().into_iter()
+            .filter(|(_, path)| self.scene_browser.row_expanded(path))
+            .map(|(uid, _)| uid)
+            .collect();
+        Some(MapSession {
+            camera_position: camera.get_position(),
+            camera_rotation: camera.get_rotation(),
+            selected_entity: *self.current_entity_id.lock().unwrap(),
+            expanded_entities,
+        })
+    }
+
+    // writes the session file for the current map, does nothing for worlds that haven't been saved yet
+    pub fn save_session(&self) {
+        let current_world_path = self.current_world_path.lock().unwrap().clone();
+        if let Some(current_world_path) = current_world_path {
+            if let Some(session) = self.capture_session() {
+                session::save_session(&current_world_path, &session);
+            }
+        }
+    }
+
+    pub fn restore_session(&self, map_path: &str) {
+        let session = match session::load_session(map_path) {
+            Some(session) => session,
+            None => return,
+        };
+        {
+            let renderer = self.renderer.lock().unwrap().clone();
+            let mut renderer = renderer.lock().unwrap();
+            if let Some(camera) = renderer.camera.as_mut() {
+                camera.set_position(session.camera_position);
+                camera.set_rotation(session.camera_rotation);
+            }
+        }
+        let rows = self.entity_rows();
+        for (uid, path) in rows.iter() {
+            if session.expanded_entities.contains(uid) {
+                self.scene_browser.expand_row(path, false);
+            } else {
+                self.scene_browser.collapse_row(path);
+            }
+        }
+        inspector_blank_slate(self.it_treestore.clone());
+        *self.current_entity_id.lock().unwrap() = None;
+        if let Some(selected) = session.selected_entity {
+            if let Some((_, path)) = rows.iter().find(|(uid, _)| *uid == selected) {
+                self.scene_browser.selection().select_path(path);
+                *self.current_entity_id.lock().unwrap() = Some(selected);
+            }
+        }
+    }
+
+    // shows the active pane's view, camera mode and shading on the toolbar. the buttons' handlers set the same things
+    // again, so the renderer mustn't be locked when calling this
+    pub fn sync_viewport_buttons(&self, camera_view: CameraView, camera_mode: CameraMode, shading: ShadingMode) {
+        match camera_view {
+            CameraView::Perspective => self.view_perspective.set_active(true),
+            CameraView::Top => self.view_top.set_active(true),
+            CameraView::Front => self.view_front.set_active(true),
+            CameraView::Side => self.view_side.set_active(true),
+        }
+        self.camera_orbit.set_active(camera_mode == CameraMode::Orbit);
+        match shading {
+            ShadingMode::Lit => self.shading_lit.set_active(true),
+            ShadingMode::Unlit => self.shading_unlit.set_active(true),
+            ShadingMode::Wireframe => self.shading_wireframe.set_active(true),
+        }
+    }
+
+    // moves the active pane's camera to a bookmark. that switches it back to perspective, so the toolbar is updated
+    // to match. the renderer mustn't be locked when calling this
+    pub fn go_to_bookmark(&self, bookmark: &CameraBookmark) {
+        let state = {
+            let renderer = self.renderer.lock().unwrap().clone();
+            let mut renderer = renderer.lock().unwrap();
+            renderer.go_to_bookmark(bookmark);
+            renderer.active_viewport_state()
+        };
+        if let Some((camera_view, camera_mode, shading)) = state {
+            self.sync_viewport_buttons(camera_view, camera_mode, shading);
+        }
+    }
+
+    // copies the selected entity and selects the copy
+    pub fn duplicate_selected_entity(&self) {
+        let uid = *self.current_entity_id.lock().unwrap();
+        let uid = match uid {
+            Some(uid) => uid,
+            None => return,
+        };
+        let worldmachine = self.worldmachine.lock().unwrap().clone();
+        let worldmachine = match worldmachine {
+            Some(worldmachine) => worldmachine,
+            None => return,
+        };
+        let copy = worldmachine.lock().unwrap().duplicate_entity(uid);
+        if let Some(copy) = copy {
+            self.select_entity(Some((copy, String::from("Transform"))));
+        }
+    }
+
+    // selects an entity picked in the viewport, showing the component that was clicked on in the inspector.
+    // None deselects everything. the worldmachine mustn't be locked when calling this
+    pub fn select_entity(&self, picked: Option<(u64, String)>) {
+        let (uid, component_name) = match picked {
+            Some(picked) => picked,
+            None => {
+                self.scene_browser.selection().unselect_all();
+                inspector_blank_slate(self.it_treestore.clone());
+                *self.current_entity_id.lock().unwrap() = None;
+                *self.current_component_name.lock().unwrap() = None;
+                return;
+            }
+        };
+        let entity_path = match self.entity_rows().into_iter().find(|(row_uid, _)| *row_uid == uid) {
+            Some((_, path)) => path,
+            None => return,
+        };
+        let component_path = {
+            let model = self.sb_treestore.lock().unwrap();
+            let model = model.as_ref().unwrap();
+            let mut component_path = None;
+            if let Some(entity_row) = model.iter(&entity_path) {
+                if let Some(child) = model.iter_children(Some(&entity_row)) {
+                    loop {
+                        if model.get_value(&child, 0).get::<String>().ok().as_deref() == Some(component_name.as_str()) {
+                            component_path = Some(model.path(&child));
+                            break;
+                        }
+                        if !model.iter_next(&child) {
+                            break;
+                        }
+                    }
+                }
+            }
+            component_path
+        };
+        // activating the row fills in the inspector the same way clicking on it in the scene browser would
+        let path = component_path.unwrap_or(entity_path.clone());
+        self.scene_browser.expand_row(&entity_path, false);
+        self.scene_browser.selection().select_path(&path);
+        self.scene_browser.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+        self.scene_browser.row_activated(&path, &self.entity_column.get());
+    }
+
+    pub fn regen_model_from_world(&self, wm: &mut World) {
+        // remember which entities were collapsed so that editing doesn't undo it
+        let collapsed = self.entity_rows().into_iter()
+            .filter(|(_, path)| !self.scene_browser.row_expanded(path))
+            .map(|(uid, _)| uid)
+            .collect::<Vec<u64>>();
+        {
+            let mut model = self.sb_treestore.lock().unwrap();
+            let model = model.as_ref().unwrap();
+            model.clear();
+            let root = model.append(None);
+            model.set(&root, &[(0, &Value::from("worldmachine"))]);
+            for entity in wm.clone().entities {
+                let entity_node = model.append(Some(&root));
+                model.set(&entity_node, &[(0, &Value::from(entity.get_name())), (1, &Value::from(entity.get_id().to_string().as_str()))]);
+                for component in entity.get_components() {
+                    let component_node = model.append(Some(&entity_node));
+                    model.set(&component_node, &[(0, &Value::from(component.get_name()))]);
+                }
             }
         }
 
         self.scene_browser.expand_all();
+        for (uid, path) in self.entity_rows() {
+            if collapsed.contains(&uid) {
+                self.scene_browser.collapse_row(&path);
+            }
+        }
+
+        let model = self.bookmark_liststore.lock().unwrap();
+        if let Some(model) = model.as_ref() {
+            model.clear();
+            for (i, bookmark) in wm.bookmarks.iter().enumerate() {
+                // only the first nine have a key
+                let key = if i < 9 { (i + 1).to_string() } else { String::new() };
+                model.insert_with_values(None, &[(0, &key), (1, &bookmark.name)]);
+            }
+        }
     }
 }
 
@@ -1,4 +1,5 @@
 mod imp;
+pub mod session;
 
 use glib::Object;
 use gtk::{gio, glib, prelude::*, Application};
@@ -13,4 +14,23 @@ impl Editor {
     pub fn new() -> Self {
         Object::new(&[]).expect("failed to create editor box")
     }
+
+    // offers to save unsaved changes before on_continue throws them away
+    pub fn confirm_discard_changes<F: Fn() + 'static>(&self, on_continue: F) {
+        let imp = self.imp();
+        // we're leaving this map one way or another, remember where we were in it
+        imp.save_session();
+        let worldmachine = imp.worldmachine.lock().unwrap().clone();
+        match worldmachine {
+            Some(worldmachine) => imp::prompt_unsaved_changes(imp.window.clone(), worldmachine, imp.current_world_path.clone(), on_continue),
+            None => on_continue(),
+        }
+    }
+
+    pub fn open_world(&self, path: &str) {
+        let worldmachine = self.imp().worldmachine.lock().unwrap().clone();
+        if let Some(worldmachine) = worldmachine {
+            imp::open_world(path, worldmachine);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+use gfx_maths::{Quaternion, Vec3};
+use serde::{Deserialize, Serialize};
+
+// editor state that isn't part of the world itself, kept beside each map as <map>.session
+// so that reopening a map puts you back where you left off
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapSession {
+    pub camera_position: Vec3,
+    pub camera_rotation: Quaternion,
+    pub selected_entity: Option<u64>,
+    pub expanded_entities: Vec<u64>, // uids of the entities expanded in the scene browser
+}
+
+pub fn session_path(map_path: &str) -> String {
+    format!("{}.session", map_path)
+}
+
+pub fn load_session(map_path: &str) -> Option<MapSession> {
+    let path = session_path(map_path);
+    let contents = std::fs::read_to_string(&path).ok()?;
+    match serde_yaml::from_str(&contents) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("failed to parse session file {}: {}", path, e);
+            None
+        }
+    }
+}
+
+pub fn save_session(map_path: &str, session: &MapSession) {
+    let path = session_path(map_path);
+    let serialized = match serde_yaml::to_string(session) {
+        Ok(serialized) => serialized,
+        Err(e) => {
+            error!("failed to serialize session: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = std::fs::write(&path, serialized) {
+        error!("failed to write session file {}: {}", path, e);
+    }
+}
@@ -0,0 +1,69 @@
+use glib::subclass::InitializingObject;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{glib, Button, ComboBoxText, CompositeTemplate, Entry};
+use crate::gio::glib::clone;
+
+
+#[derive(CompositeTemplate, Default)]
+#[template(resource = "/com/realmicrosoft/h2eck/entity_exporter.ui")]
+pub struct EntityExporter {
+    // buttons
+    #[template_child]
+    pub cancel_button: TemplateChild<Button>,
+    #[template_child]
+    pub ok_button: TemplateChild<Button>,
+
+    // fields
+    #[template_child]
+    pub folder_combo: TemplateChild<ComboBoxText>,
+    #[template_child]
+    pub description_entry: TemplateChild<Entry>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for EntityExporter {
+    const NAME: &'static str = "h2eckEntityExporter";
+    type Type = super::EntityExporter;
+    type ParentType = gtk::ApplicationWindow;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl ObjectImpl for EntityExporter {
+    fn constructed(&self, obj: &Self::Type) {
+        // call "constructed" on parent
+        self.parent_constructed(obj);
+        self.setup(obj);
+    }
+}
+
+impl EntityExporter {
+    pub fn setup(&self, obj: &<EntityExporter as ObjectSubclass>::Type) {
+        self.cancel_button.connect_clicked(clone!(@weak obj => move |_| {
+            obj.close();
+        }));
+    }
+
+    pub fn populate_folders(&self, folders: &[String]) {
+        self.folder_combo.remove_all();
+        for folder in folders {
+            self.folder_combo.append_text(folder);
+        }
+    }
+
+    // whatever's typed into (or picked from) the folder box
+    pub fn folder(&self) -> String {
+        self.folder_combo.active_text().map(|f| f.to_string()).unwrap_or_default().trim().to_string()
+    }
+}
+
+impl WidgetImpl for EntityExporter {}
+impl WindowImpl for EntityExporter {}
+impl ApplicationWindowImpl for EntityExporter {}
@@ -0,0 +1,15 @@
+mod imp;
+
+use glib::Object;
+use gtk::{gio, glib, prelude::*, Application};
+
+glib::wrapper! {
+    pub struct EntityExporter(ObjectSubclass<imp::EntityExporter>)
+        @extends gtk::Widget, gtk::Window, gtk::ApplicationWindow, @implements gio::ActionMap, gio::ActionGroup;
+}
+
+impl EntityExporter {
+    pub fn new() -> Self {
+        Object::new(&[]).expect("failed to create entity exporter")
+    }
+}
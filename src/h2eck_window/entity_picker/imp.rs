@@ -1,15 +1,21 @@
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use glib::subclass::InitializingObject;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use gtk::{glib, Button, CompositeTemplate, PopoverMenuBar, ListBox};
+use gtk::{glib, Button, CompositeTemplate, PopoverMenuBar, ListBox, Entry, TreeView, TreeViewColumn, TreeStore};
+use gtk::glib::{Type, Value};
 use gtk::AccessibleRole::Label;
 use gtk::gio::Menu;
 use crate::gio::glib::clone;
 use crate::gio::SimpleAction;
 use crate::h2eck_window::entity_namer::EntityNamer;
-use crate::h2eck_window::entity_picker;
+use crate::h2eck_window::{entity_picker, show_error_dialog, thumbnail_grid};
+use crate::h2eck_window::thumbnail_grid::ThumbnailImages;
 use crate::renderer::H2eckRenderer;
+use crate::renderer::thumbnails::{ThumbnailReady, ThumbnailSource};
+use crate::worldmachine::library;
+use crate::worldmachine::library::EntityDefEntry;
 use crate::worldmachine::WorldMachine;
 
 
@@ -27,9 +33,82 @@ pub struct EntityPicker {
     #[template_child]
     pub ok_button: TemplateChild<Button>,
 
-    // list of entities
+    // search
     #[template_child]
-    pub list_box: TemplateChild<ListBox>,
+    pub search_entry: TemplateChild<Entry>,
+    #[template_child]
+    pub search_button: TemplateChild<Button>,
+
+    // the library, sorted into folders
+    #[template_child]
+    pub library_tree: TemplateChild<TreeView>,
+    #[template_child]
+    pub name_column: TemplateChild<TreeViewColumn>,
+    #[template_child]
+    pub description_column: TemplateChild<TreeViewColumn>,
+    #[template_child]
+    pub description_label: TemplateChild<gtk::Label>,
+
+    // thumbnails of whatever's in the selected folder, or of the search results
+    #[template_child]
+    pub thumbnail_grid: TemplateChild<gtk::FlowBox>,
+
+    pub library_store: Arc<Mutex<Option<TreeStore>>>,
+    pub entries: Arc<Mutex<Vec<EntityDefEntry>>>,
+    pub entities_dir: Arc<Mutex<String>>,
+    pub thumbnail_images: ThumbnailImages,
+    pub thumbnail_sender: Arc<Mutex<Option<glib::Sender<ThumbnailReady>>>>,
+}
+
+// library_store columns
+const COLUMN_DISPLAY_NAME: u32 = 0;
+const COLUMN_ENTITY_NAME: u32 = 1; // empty for folders
+const COLUMN_DESCRIPTION: u32 = 2;
+const COLUMN_FOLDER: u32 = 3; // the folder itself for folders, the containing folder for edefs
+
+// the full name of the selected edef, None if nothing or a folder is selected
+fn selected_entity(library_tree: &TreeView) -> Option<String> {
+    let (model, iter) = library_tree.selection().selected()?;
+    let name = model.get_value(&iter, COLUMN_ENTITY_NAME as i32).get::<String>().ok()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// rebuilds the tree from the entries that match the search, folders become parent rows
+fn fill_library_store(store: &TreeStore, entries: &[EntityDefEntry], search: &str) {
+    store.clear();
+    let mut folders: HashMap<String, gtk::TreeIter> = HashMap::new();
+    for entry in entries.iter().filter(|e| search.is_empty() || e.matches(search)) {
+        let mut parent: Option<gtk::TreeIter> = None;
+        let mut folder_path = String::new();
+        for folder in entry.category().split('/').filter(|f| !f.is_empty()) {
+            if !folder_path.is_empty() {
+                folder_path.push('/');
+            }
+            folder_path.push_str(folder);
+            let iter = folders.entry(folder_path.clone()).or_insert_with(|| {
+                let iter = store.append(parent.as_ref());
+                store.set(&iter, &[
+                    (COLUMN_DISPLAY_NAME, &Value::from(folder)),
+                    (COLUMN_ENTITY_NAME, &Value::from("")),
+                    (COLUMN_DESCRIPTION, &Value::from("")),
+                    (COLUMN_FOLDER, &Value::from(folder_path.as_str())),
+                ]);
+                iter
+            }).clone();
+            parent = Some(iter);
+        }
+        let iter = store.append(parent.as_ref());
+        store.set(&iter, &[
+            (COLUMN_DISPLAY_NAME, &Value::from(entry.short_name())),
+            (COLUMN_ENTITY_NAME, &Value::from(entry.name.as_str())),
+            (COLUMN_DESCRIPTION, &Value::from(entry.description.as_str())),
+            (COLUMN_FOLDER, &Value::from(entry.category())),
+        ]);
+    }
 }
 
 #[glib::object_subclass]
@@ -63,7 +142,11 @@ pub fn add_entity(name: &str, worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMach
     let renderer = renderer.as_ref();
     let renderer = renderer.lock().unwrap();
 
-    worldmachine.load_entity_def(name, renderer.camera.clone());
+    let position = worldmachine.placement_position(&renderer);
+    if let Err(e) = worldmachine.load_entity_def(name, position) {
+        error!("failed to load entity def {}: {}", name, e);
+        show_error_dialog(None, e.title(), &e.to_string());
+    }
 }
 
 pub fn create_entity(name: &str, worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>) {
@@ -76,6 +159,48 @@ pub fn create_entity(name: &str, worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldM
 
 impl EntityPicker {
     pub fn setup(&self, obj: &<EntityPicker as ObjectSubclass>::Type) {
+        // create a treemodel for the library
+        let store = TreeStore::new(&[Type::STRING, Type::STRING, Type::STRING, Type::STRING]);
+        self.library_tree.set_model(Some(&store));
+        *self.library_store.lock().unwrap() = Some(store);
+        let cell = gtk::CellRendererText::new();
+        self.name_column.pack_start(&cell, true);
+        self.name_column.add_attribute(&cell, "text", COLUMN_DISPLAY_NAME as i32);
+        let cell = gtk::CellRendererText::new();
+        cell.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        self.description_column.pack_start(&cell, true);
+        self.description_column.add_attribute(&cell, "text", COLUMN_DESCRIPTION as i32);
+        self.library_tree.set_tooltip_column(COLUMN_DESCRIPTION as i32);
+
+        *self.thumbnail_sender.lock().unwrap() = Some(thumbnail_grid::thumbnail_sender(&self.thumbnail_images));
+
+        // show the full description of whatever's selected, and the thumbnails of everything beside it
+        let description_label = self.description_label.get();
+        self.library_tree.selection().connect_changed(clone!(@weak obj => move |selection| {
+            let description = selection.selected()
+                .and_then(|(model, iter)| model.get_value(&iter, COLUMN_DESCRIPTION as i32).get::<String>().ok())
+                .unwrap_or_default();
+            description_label.set_text(&description);
+            obj.imp().show_thumbnails();
+        }));
+        let description_label = self.description_label.get();
+        let entries = self.entries.clone();
+        self.thumbnail_grid.connect_selected_children_changed(move |grid| {
+            if let Some(name) = thumbnail_grid::selected_tile(grid) {
+                let entries = entries.lock().unwrap();
+                let description = entries.iter().find(|e| e.name == name).map(|e| e.description.clone()).unwrap_or_default();
+                description_label.set_text(&description);
+            }
+        });
+
+        // search as you type, the button is there for people who expect one
+        self.search_entry.connect_changed(clone!(@weak obj => move |_| {
+            obj.imp().filter_library();
+        }));
+        self.search_button.connect_clicked(clone!(@weak obj => move |_| {
+            obj.imp().filter_library();
+        }));
+
         // cancel button
         self.cancel_button.connect_clicked(clone!(@weak obj => move |_| {
             obj.close();
@@ -84,34 +209,47 @@ impl EntityPicker {
         // ok button
         #[derive(Clone)]
         struct EntityPickerOkButtonData {
-            list_box: ListBox,
+            library_tree: TreeView,
+            thumbnail_grid: gtk::FlowBox,
             obj: entity_picker::EntityPicker,
             worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>,
             renderer: Arc<Mutex<Arc<Mutex<H2eckRenderer>>>>,
         }
         let data = EntityPickerOkButtonData {
-            list_box: self.list_box.clone(),
+            library_tree: self.library_tree.get(),
+            thumbnail_grid: self.thumbnail_grid.get(),
             obj: obj.clone(),
             worldmachine: self.worldmachine.clone(),
             renderer: self.renderer.clone(),
         };
-        self.ok_button.connect_clicked(move |_| {
-            let selected_row = data.list_box.selected_row();
-            if let Some(selected_row) = selected_row {
-                let selected_row = selected_row.clone();
-                let selected_row = selected_row.downcast::<gtk::ListBoxRow>().unwrap();
-                let selected_row = selected_row.child().unwrap();
-                let selected_row = selected_row.downcast::<gtk::Label>().unwrap();
-                let selected_row = selected_row.label();
-                debug!("selected row: {}", selected_row);
-                add_entity(&selected_row, data.worldmachine.clone(), data.renderer.clone());
+        // selecting an edef in the tree also selects it in the grid, so the grid has the final say
+        self.ok_button.connect_clicked(clone!(@strong data => move |_| {
+            if let Some(name) = thumbnail_grid::selected_tile(&data.thumbnail_grid).or_else(|| selected_entity(&data.library_tree)) {
+                debug!("selected entity def: {}", name);
+                add_entity(&name, data.worldmachine.clone(), data.renderer.clone());
+                data.obj.close();
             }
+        }));
+        // double clicking an edef adds it straight away, double clicking a folder opens it
+        self.library_tree.connect_row_activated(clone!(@strong data => move |tree, path, _| {
+            if let Some(name) = selected_entity(tree) {
+                add_entity(&name, data.worldmachine.clone(), data.renderer.clone());
+                data.obj.close();
+            } else if tree.row_expanded(path) {
+                tree.collapse_row(path);
+            } else {
+                tree.expand_row(path, false);
+            }
+        }));
+        self.thumbnail_grid.connect_child_activated(clone!(@strong data => move |_, child| {
+            add_entity(&thumbnail_grid::tile_name(child), data.worldmachine.clone(), data.renderer.clone());
             data.obj.close();
-        });
+        }));
 
         // new button
         let data = EntityPickerOkButtonData {
-            list_box: self.list_box.clone(),
+            library_tree: self.library_tree.get(),
+            thumbnail_grid: self.thumbnail_grid.get(),
             obj: obj.clone(),
             worldmachine: self.worldmachine.clone(),
             renderer: self.renderer.clone(),
@@ -140,18 +278,79 @@ impl EntityPicker {
         });
     }
 
-    pub fn populate_listbox(&self) {
+    pub fn populate_library(&self) {
         let worldmachine = self.worldmachine.lock().unwrap();
         let worldmachine = worldmachine.as_ref().unwrap();
         let worldmachine = worldmachine.lock().unwrap();
 
-        let entities = worldmachine.list_possible_entities();
+        *self.entries.lock().unwrap() = worldmachine.list_possible_entities();
+        *self.entities_dir.lock().unwrap() = worldmachine.entities_dir();
+        drop(worldmachine);
+        self.filter_library();
+    }
+
+    pub fn filter_library(&self) {
+        let search = self.search_entry.text().to_string();
+        let store = self.library_store.lock().unwrap();
+        let store = store.as_ref().unwrap();
+        // clearing the store changes the selection, which locks the entries to refresh the thumbnails
+        let entries = self.entries.lock().unwrap().clone();
+        fill_library_store(store, &entries, search.trim());
+        if search.trim().is_empty() {
+            // just show the categories until the user goes looking
+            self.library_tree.collapse_all();
+        } else {
+            self.library_tree.expand_all();
+        }
+        self.show_thumbnails();
+    }
+
+    // fills the grid with the search results, or with the folder that's selected in the tree
+    pub fn show_thumbnails(&self) {
+        let search = self.search_entry.text().trim().to_string();
+        let (folder, selected) = match self.library_tree.selection().selected() {
+            Some((model, iter)) => (
+                model.get_value(&iter, COLUMN_FOLDER as i32).get::<String>().unwrap_or_default(),
+                model.get_value(&iter, COLUMN_ENTITY_NAME as i32).get::<String>().unwrap_or_default(),
+            ),
+            None => (String::new(), String::new()),
+        };
 
-        for entity in entities {
-            let row = gtk::ListBoxRow::new();
-            let label = gtk::Label::new(Some(entity.as_str()));
-            row.set_child(Some(&label));
-            self.list_box.append(&row);
+        let grid = self.thumbnail_grid.get();
+        thumbnail_grid::clear(&grid, &self.thumbnail_images);
+        // cloned so the lock isn't held when selecting a tile below, which looks the description up
+        let shown = self.entries.lock().unwrap().iter()
+            .filter(|e| if search.is_empty() { e.category() == folder } else { e.matches(&search) })
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in shown.iter() {
+            thumbnail_grid::add_tile(&grid, &self.thumbnail_images, &entry.name, entry.short_name(), &entry.description);
+        }
+        if !selected.is_empty() {
+            let mut index = 0;
+            while let Some(child) = grid.child_at_index(index) {
+                if thumbnail_grid::tile_name(&child) == selected {
+                    grid.select_child(&child);
+                    break;
+                }
+                index += 1;
+            }
+        }
+
+        // cached thumbnails come straight back, the rest are rendered over the next few frames
+        let sender = self.thumbnail_sender.lock().unwrap();
+        let sender = match sender.as_ref() {
+            Some(sender) => sender,
+            None => return,
+        };
+        let entities_dir = self.entities_dir.lock().unwrap();
+        let renderer = self.renderer.lock().unwrap();
+        let mut renderer = renderer.lock().unwrap();
+        for entry in shown {
+            renderer.request_thumbnail(ThumbnailSource::EntityDef {
+                name: entry.name.clone(),
+                path: library::entity_def_path(&entities_dir, &entry.name),
+            }, sender.clone());
         }
     }
 }
@@ -1,22 +1,27 @@
 use std::ffi::CString;
 use std::ptr;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use glad_gl::gl;
 use glib::subclass::InitializingObject;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use gtk::{glib, Button, CompositeTemplate, PopoverMenuBar, GLArea, Inhibit};
+use gtk::{glib, Button, CompositeTemplate, PopoverMenuBar, GLArea, Inhibit, MessageDialog, DialogFlags, MessageType, ButtonsType, ResponseType};
 use gtk::ffi::*;
 use gtk::gdk::ffi::GdkFrameClock;
 use gtk::gio::Menu;
 use gtk::glib::translate::ToGlibPtr;
-use gtk::glib::{Type, Value};
+use gtk::glib::{Continue, Type, Value};
 use shared_library::dynamic_library::DynamicLibrary;
 use crate::gio::glib::clone;
 use crate::gio::SimpleAction;
 use crate::h2eck_window::editor::Editor;
+use crate::h2eck_window::show_error_dialog;
+use crate::keymap;
+use crate::keymap::KeyAction;
 use crate::renderer::H2eckRenderer;
-use crate::worldmachine::WorldMachine;
+use crate::settings;
+use crate::worldmachine::{recovery, WorldMachine};
 
 #[derive(CompositeTemplate, Default)]
 #[template(resource = "/com/realmicrosoft/h2eck/window.ui")]
@@ -77,6 +82,7 @@ impl h2eckWindow {
         // tell glib to redraw the editor every frame
         let worldmachine = obj.clone().imp().worldmachine.clone();
         let editor = self.editor.clone();
+        let window = obj.clone().upcast::<gtk::Window>();
         editor_obj.imp().main_view.connect_realize( move |a| {
 
             epoxy::load_with(|s| {
@@ -93,6 +99,8 @@ impl h2eckWindow {
             let mut inner_worldmachine = worldmachine.lock().unwrap();
             inner_worldmachine.initialise(editor.clone());
             debug!("initialised worldmachine");
+            drop(inner_worldmachine);
+            offer_recovery(&window, worldmachine.clone());
             let frame_clock = a.frame_clock().unwrap();
             frame_clock.connect_update(clone!(@weak a => move |_| {
                 a.queue_draw();
@@ -129,18 +137,92 @@ impl h2eckWindow {
         gesture.set_button(gtk::gdk::ffi::GDK_BUTTON_SECONDARY as u32);
         editor_obj.imp().main_view.add_controller(&gesture);
 
+        // middle drag pans the camera
+        let gesture = gtk::GestureDrag::new();
+        gesture.set_button(gtk::gdk::ffi::GDK_BUTTON_MIDDLE as u32);
+        let renderer = obj.clone().imp().renderer.clone();
+        let editor = self.editor.clone();
+        gesture.connect_drag_begin(move |_, _, _| {
+            editor.lock().unwrap().as_ref().unwrap().imp().main_view.grab_focus();
+            renderer.lock().unwrap().start_pan_camera();
+        });
+        let renderer = obj.clone().imp().renderer.clone();
+        gesture.connect_drag_update(move |_, offset_x, offset_y| {
+            renderer.lock().unwrap().pan_camera(offset_x as f32, offset_y as f32);
+        });
+        let renderer = obj.clone().imp().renderer.clone();
+        gesture.connect_drag_end(move |_, _, _| {
+            renderer.lock().unwrap().end_pan_camera();
+        });
+        editor_obj.imp().main_view.add_controller(&gesture);
+
+        // the scroll wheel zooms
+        let scroll = gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
+        let renderer = obj.clone().imp().renderer.clone();
+        scroll.connect_scroll(move |_, _, dy| {
+            renderer.lock().unwrap().zoom_camera(dy as f32);
+            Inhibit(true)
+        });
+        editor_obj.imp().main_view.add_controller(&scroll);
+
         // create an eventcontroller for keyboard input
         let event_controller = gtk::EventControllerKey::new();
         let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
         event_controller.connect_key_pressed(move |a, keyval, keycode, state| {
+            // only when the viewport has focus, so typing in the inspector doesn't move the camera about
+            let inner_editor = editor.lock().unwrap().as_ref().unwrap().clone();
+            let viewport_focused = inner_editor.imp().main_view.has_focus();
+            let action = keymap::action_for_key(keyval, state);
+            if viewport_focused {
+                // go through the view buttons so they stay in sync, the renderer can't be locked for this
+                let view_button = match action {
+                    Some(KeyAction::ViewPerspective) => Some(inner_editor.imp().view_perspective.get()),
+                    Some(KeyAction::ViewTop) => Some(inner_editor.imp().view_top.get()),
+                    Some(KeyAction::ViewFront) => Some(inner_editor.imp().view_front.get()),
+                    Some(KeyAction::ViewSide) => Some(inner_editor.imp().view_side.get()),
+                    _ => None,
+                };
+                if let Some(view_button) = view_button {
+                    view_button.set_active(true);
+                    return Inhibit(true);
+                }
+                // an empty slot leaves the key for anything else that wants it
+                if let Some(KeyAction::GoToBookmark(index)) = action {
+                    let bookmark = worldmachine.lock().unwrap().get_bookmark(index);
+                    if let Some(bookmark) = bookmark {
+                        inner_editor.imp().go_to_bookmark(&bookmark);
+                        return Inhibit(true);
+                    }
+                }
+            }
+            if viewport_focused && action == Some(KeyAction::DeleteEntity) {
+                // same as the button, which asks first
+                inner_editor.imp().remove_entity.emit_clicked();
+                return Inhibit(true);
+            }
             let mut inner_renderer = renderer.lock().unwrap();
-            inner_renderer.process_key(keyval, true);
+            if viewport_focused {
+                match action {
+                    Some(KeyAction::FocusSelection) => {
+                        inner_renderer.focus_selection(&worldmachine.lock().unwrap());
+                        return Inhibit(true);
+                    }
+                    Some(KeyAction::FrameAll) => {
+                        inner_renderer.frame_all(&worldmachine.lock().unwrap());
+                        return Inhibit(true);
+                    }
+                    _ => {}
+                }
+            }
+            inner_renderer.process_key(action, true);
             Inhibit(false)
         });
         let renderer = obj.clone().imp().renderer.clone();
         event_controller.connect_key_released(move |a, keyval, keycode, state| {
             let mut inner_renderer = renderer.lock().unwrap();
-            inner_renderer.process_key(keyval, false);
+            inner_renderer.process_key(keymap::held_action_for_key(keyval), false);
         });
         obj.add_controller(&event_controller);
 
@@ -156,6 +238,156 @@ impl h2eckWindow {
         });
         editor_obj.imp().main_view.add_controller(&gesture);
 
+        // clicking on something in the viewport selects it
+        let gesture = gtk::GestureClick::new();
+        gesture.set_button(gtk::gdk::ffi::GDK_BUTTON_PRIMARY as u32);
+        let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
+        gesture.connect_released(move |_, _, mouse_x, mouse_y| {
+            let inner_editor = editor.lock().unwrap().as_ref().unwrap().clone();
+            let main_view = inner_editor.imp().main_view.get();
+            main_view.grab_focus();
+            main_view.make_current();
+            if main_view.error().is_some() {
+                return;
+            }
+            let (x, y) = scene_coords(&main_view, mouse_x, mouse_y);
+            let picked = {
+                let mut inner_renderer = renderer.lock().unwrap();
+                let mut inner_worldmachine = worldmachine.lock().unwrap();
+                let (x, y) = inner_renderer.viewport_coords(x, y);
+                // clicking on the gizmo shouldn't select whatever's behind it
+                if inner_renderer.gizmo_under_cursor(&inner_worldmachine, x, y) {
+                    return;
+                }
+                inner_worldmachine.select(x, y, &mut inner_renderer)
+            };
+            inner_editor.imp().select_entity(picked);
+        });
+        editor_obj.imp().main_view.add_controller(&gesture);
+
+        // dragging the gizmo moves, rotates or scales the selected entity
+        let gesture = gtk::GestureDrag::new();
+        gesture.set_button(gtk::gdk::ffi::GDK_BUTTON_PRIMARY as u32);
+        let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
+        gesture.connect_drag_begin(move |gesture, mouse_x, mouse_y| {
+            let main_view = editor.lock().unwrap().as_ref().unwrap().imp().main_view.get();
+            let (x, y) = scene_coords(&main_view, mouse_x, mouse_y);
+            let mut inner_renderer = renderer.lock().unwrap();
+            let inner_worldmachine = worldmachine.lock().unwrap();
+            let (x, y) = inner_renderer.viewport_coords(x, y);
+            if inner_renderer.begin_gizmo_drag(&inner_worldmachine, x, y) {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+            } else {
+                gesture.set_state(gtk::EventSequenceState::Denied);
+            }
+        });
+        let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
+        gesture.connect_drag_update(move |gesture, offset_x, offset_y| {
+            let (start_x, start_y) = match gesture.start_point() {
+                Some(start) => start,
+                None => return,
+            };
+            let main_view = editor.lock().unwrap().as_ref().unwrap().imp().main_view.get();
+            let (x, y) = scene_coords(&main_view, start_x + offset_x, start_y + offset_y);
+            let mut inner_renderer = renderer.lock().unwrap();
+            let mut inner_worldmachine = worldmachine.lock().unwrap();
+            let (x, y) = inner_renderer.viewport_coords(x, y);
+            inner_renderer.drag_gizmo(&mut inner_worldmachine, x, y);
+        });
+        let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
+        gesture.connect_drag_end(move |gesture, offset_x, offset_y| {
+            let (start_x, start_y) = match gesture.start_point() {
+                Some(start) => start,
+                None => return,
+            };
+            let inner_editor = editor.lock().unwrap().as_ref().unwrap().clone();
+            let main_view = inner_editor.imp().main_view.get();
+            let (x, y) = scene_coords(&main_view, start_x + offset_x, start_y + offset_y);
+            let moved = {
+                let mut inner_renderer = renderer.lock().unwrap();
+                let mut inner_worldmachine = worldmachine.lock().unwrap();
+                let (x, y) = inner_renderer.viewport_coords(x, y);
+                inner_renderer.end_gizmo_drag(&mut inner_worldmachine, x, y)
+            };
+            // show the new values in the inspector
+            if let Some(uid) = moved {
+                inner_editor.imp().select_entity(Some((uid, String::from("Transform"))));
+            }
+        });
+        editor_obj.imp().main_view.add_controller(&gesture);
+
+        // highlight whatever's under the cursor
+        let motion = gtk::EventControllerMotion::new();
+        let renderer = obj.clone().imp().renderer.clone();
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let editor = self.editor.clone();
+        motion.connect_motion(move |_, mouse_x, mouse_y| {
+            let inner_editor = editor.lock().unwrap().as_ref().unwrap().clone();
+            let main_view = inner_editor.imp().main_view.get();
+            let (x, y) = scene_coords(&main_view, mouse_x, mouse_y);
+            // the pane under the mouse becomes the active one, and the toolbar shows its settings
+            let changed_pane = {
+                let mut inner_renderer = renderer.lock().unwrap();
+                if inner_renderer.hover_viewport(x, y) {
+                    inner_renderer.active_viewport_state()
+                } else {
+                    None
+                }
+            };
+            if let Some((camera_view, camera_mode, shading)) = changed_pane {
+                inner_editor.imp().sync_viewport_buttons(camera_view, camera_mode, shading);
+            }
+            let mut inner_renderer = renderer.lock().unwrap();
+            let (x, y) = inner_renderer.viewport_coords(x, y);
+            inner_renderer.hovered_entity = None;
+            if inner_renderer.gizmo.is_dragging() || inner_renderer.gizmo_under_cursor(&worldmachine.lock().unwrap(), x, y) {
+                return;
+            }
+            worldmachine.lock().unwrap().hover(x, y, &mut inner_renderer);
+        });
+        let renderer = obj.clone().imp().renderer.clone();
+        motion.connect_leave(move |_| {
+            renderer.lock().unwrap().hovered_entity = None;
+        });
+        editor_obj.imp().main_view.add_controller(&motion);
+
+        // autosave every so often, the interval is checked on each tick so that changes in preferences apply straight away
+        // if it fails the user is told once, and not again until an autosave has worked
+        let worldmachine = obj.clone().imp().worldmachine.clone();
+        let last_autosave = Arc::new(Mutex::new(Instant::now()));
+        let autosave_failing = Arc::new(Mutex::new(false));
+        glib::timeout_add_seconds_local(5, clone!(@weak obj => @default-return Continue(false), move || {
+            let interval = settings::autosave_interval();
+            if interval == 0 || recovery::awaiting_recovery() {
+                return Continue(true);
+            }
+            let mut last_autosave = last_autosave.lock().unwrap();
+            if last_autosave.elapsed().as_secs() >= interval as u64 {
+                let result = worldmachine.lock().unwrap().autosave();
+                *last_autosave = Instant::now();
+                let mut autosave_failing = autosave_failing.lock().unwrap();
+                match result {
+                    Ok(_) => *autosave_failing = false,
+                    Err(e) => {
+                        error!("{}", e);
+                        if !*autosave_failing {
+                            show_error_dialog(Some(obj.upcast_ref()), "Autosave Failed", &e);
+                        }
+                        *autosave_failing = true;
+                    }
+                }
+            }
+            Continue(true)
+        }));
+
         editor_obj.show();
 
         self.stack.add_child(editor_obj);
@@ -163,6 +395,62 @@ impl h2eckWindow {
     }
 }
 
+// converts a position on the glarea into pixels of the scene, which is drawn from the bottom left corner in device
+// pixels
+fn scene_coords(main_view: &GLArea, x: f64, y: f64) -> (f32, f32) {
+    let scale = main_view.scale_factor() as f64;
+    ((x * scale) as f32, ((main_view.height() as f64 - y) * scale) as f32)
+}
+
+// if the last session crashed and left an autosave behind, ask whether to load it
+fn offer_recovery(window: &gtk::Window, worldmachine: Arc<Mutex<WorldMachine>>) {
+    let pending = match recovery::pending_recovery() {
+        Some(pending) => pending,
+        None => {
+            recovery::recovery_handled();
+            return;
+        }
+    };
+    let message = match pending.info.original_path.as_ref() {
+        Some(path) => format!("h2eck didn't shut down properly last time. an autosave of {} that's newer than the saved map was found, would you like to recover it?", path),
+        None => String::from("h2eck didn't shut down properly last time. an autosave of an unsaved world was found, would you like to recover it?"),
+    };
+    let dialog = MessageDialog::new(Some(window), DialogFlags::MODAL, MessageType::Question, ButtonsType::None, &message);
+    dialog.set_title(Some("Recover Autosave?"));
+    dialog.add_button("Discard", ResponseType::Reject);
+    dialog.add_button("Recover", ResponseType::Accept);
+    dialog.connect_response(move |dialog, response| {
+        match response {
+            ResponseType::Accept => {
+                let result = worldmachine.lock().unwrap().recover_from_autosave(&pending);
+                match result {
+                    Ok(_) => info!("recovered autosave from {}", pending.autosave_path),
+                    Err(e) => {
+                        // leave the autosave where it is, so it can still be dug out by hand
+                        error!("failed to recover autosave: {}", e);
+                        show_error_dialog(dialog.transient_for().as_ref(), e.title(), &e.to_string());
+                    }
+                }
+            }
+            _ => {
+                recovery::discard_autosave();
+            }
+        }
+        recovery::recovery_handled();
+        dialog.destroy();
+    });
+    dialog.show();
+}
+
 impl WidgetImpl for h2eckWindow {}
-impl WindowImpl for h2eckWindow {}
+impl WindowImpl for h2eckWindow {
+    // closing the window goes through app.quit so that unsaved changes get asked about
+    fn close_request(&self, window: &Self::Type) -> Inhibit {
+        if let Some(app) = window.application() {
+            app.activate_action("quit", None);
+            return Inhibit(true);
+        }
+        self.parent_close_request(window)
+    }
+}
 impl ApplicationWindowImpl for h2eckWindow {}
\ No newline at end of file
@@ -4,9 +4,13 @@ pub mod editor;
 pub mod entity_picker;
 pub mod component_picker;
 pub mod entity_namer;
+pub mod entity_exporter;
+pub mod preferences_window;
+pub mod asset_browser;
+pub mod thumbnail_grid;
 
 use glib::Object;
-use gtk::{gio, glib, prelude::*, Application};
+use gtk::{gio, glib, prelude::*, Application, ButtonsType, DialogFlags, MessageDialog, MessageType};
 use gtk::subclass::prelude::ObjectSubclassIsExt;
 
 glib::wrapper! {
@@ -18,4 +22,13 @@ impl h2eckWindow {
     pub fn new(app: &Application) -> Self {
         Object::new(&[("application", app)]).expect("failed to create h2eckWindow")
     }
-}
\ No newline at end of file
+}
+// shows an error the user should know about, e.g. a map that failed to load
+pub fn show_error_dialog(parent: Option<&gtk::Window>, title: &str, message: &str) {
+    let dialog = MessageDialog::new(parent, DialogFlags::MODAL, MessageType::Error, ButtonsType::Ok, message);
+    dialog.set_title(Some(title));
+    dialog.connect_response(|dialog, _| {
+        dialog.destroy();
+    });
+    dialog.show();
+}
@@ -0,0 +1,307 @@
+use std::sync::{Arc, Mutex};
+use glib::subclass::InitializingObject;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gio, glib, Button, ComboBoxText, CompositeTemplate, Entry, Label, MessageDialog, SpinButton, CheckButton, DialogFlags, MessageType, ButtonsType};
+use gtk::gdk::ModifierType;
+use gtk::glib::translate::IntoGlib;
+use gtk::glib::{StaticType, Type};
+use crate::gio::glib::clone;
+use crate::keymap;
+use crate::keymap::{KEY_ACTIONS, KeyAction, Keymap};
+use crate::renderer::H2eckRenderer;
+use crate::settings;
+use crate::settings::{MapCompiler, Setting, SettingSource, StoredSettings};
+use crate::worldmachine::WorldMachine;
+
+
+#[derive(CompositeTemplate, Default)]
+#[template(resource = "/com/realmicrosoft/h2eck/preferences.ui")]
+pub struct PreferencesWindow {
+    pub worldmachine: Arc<Mutex<Option<Arc<Mutex<WorldMachine>>>>>,
+    pub renderer: Arc<Mutex<Option<Arc<Mutex<H2eckRenderer>>>>>,
+
+    // path entries
+    #[template_child]
+    pub data_location_entry: TemplateChild<Entry>,
+    #[template_child]
+    pub binary_location_entry: TemplateChild<Entry>,
+    #[template_child]
+    pub tools_location_entry: TemplateChild<Entry>,
+    #[template_child]
+    pub override_label: TemplateChild<Label>,
+    #[template_child]
+    pub compiler_combo: TemplateChild<ComboBoxText>,
+    #[template_child]
+    pub autosave_interval_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub backup_count_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub compact_maps_check: TemplateChild<CheckButton>,
+    #[template_child]
+    pub grid_size_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub rotation_snap_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub scale_snap_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub fly_speed_spin: TemplateChild<SpinButton>,
+    #[template_child]
+    pub mouse_sensitivity_spin: TemplateChild<SpinButton>,
+
+    // keyboard shortcuts
+    #[template_child]
+    pub keymap_tree: TemplateChild<gtk::TreeView>,
+    #[template_child]
+    pub keymap_action_column: TemplateChild<gtk::TreeViewColumn>,
+    #[template_child]
+    pub keymap_shortcut_column: TemplateChild<gtk::TreeViewColumn>,
+    #[template_child]
+    pub keymap_action_renderer: TemplateChild<gtk::CellRendererText>,
+    #[template_child]
+    pub keymap_shortcut_renderer: TemplateChild<gtk::CellRendererAccel>,
+    #[template_child]
+    pub reset_keymap_button: TemplateChild<Button>,
+    pub keymap_liststore: Arc<Mutex<Option<gtk::ListStore>>>,
+
+    // buttons
+    #[template_child]
+    pub cancel_button: TemplateChild<Button>,
+    #[template_child]
+    pub ok_button: TemplateChild<Button>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for PreferencesWindow {
+    const NAME: &'static str = "h2eckPreferences";
+    type Type = super::PreferencesWindow;
+    type ParentType = gtk::ApplicationWindow;
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.bind_template();
+    }
+
+    fn instance_init(obj: &InitializingObject<Self>) {
+        obj.init_template();
+    }
+}
+
+impl ObjectImpl for PreferencesWindow {
+    fn constructed(&self, obj: &Self::Type) {
+        // call "constructed" on parent
+        self.parent_constructed(obj);
+        self.setup(obj);
+    }
+}
+
+// describes where an overridden setting is coming from, so people don't wonder why their changes aren't doing anything
+fn describe_override(name: &str, setting: &Setting) -> Option<String> {
+    match setting.source {
+        SettingSource::CommandLine => Some(format!("{} is overridden on the command line ({})", name, setting.value)),
+        SettingSource::Environment => Some(format!("{} is overridden by an environment variable ({})", name, setting.value)),
+        _ => None,
+    }
+}
+
+// columns of the keymap list
+const KEYMAP_ID_COLUMN: u32 = 0;
+const KEYMAP_LABEL_COLUMN: u32 = 1;
+const KEYMAP_ACCELERATOR_COLUMN: u32 = 2;
+const KEYMAP_KEY_COLUMN: u32 = 3; // these two are what the accel renderer shows
+const KEYMAP_MODIFIERS_COLUMN: u32 = 4;
+
+fn set_shortcut(model: &gtk::ListStore, iter: &gtk::TreeIter, accelerator: &str) {
+    let (key, modifiers) = keymap::parse(accelerator)
+        .map(|(key, modifiers)| (key.into_glib(), modifiers))
+        .unwrap_or((0, ModifierType::empty()));
+    model.set(iter, &[(KEYMAP_ACCELERATOR_COLUMN, &accelerator), (KEYMAP_KEY_COLUMN, &key), (KEYMAP_MODIFIERS_COLUMN, &modifiers)]);
+}
+
+fn fill_keymap(model: &gtk::ListStore, keymap: &Keymap) {
+    model.clear();
+    for action in KEY_ACTIONS.iter() {
+        let iter = model.append();
+        model.set(&iter, &[(KEYMAP_ID_COLUMN, &action.id()), (KEYMAP_LABEL_COLUMN, &action.label())]);
+        set_shortcut(model, &iter, &keymap.binding(*action));
+    }
+}
+
+// the keymap as edited in the list
+fn keymap_from_list(model: &gtk::ListStore) -> Keymap {
+    let mut keymap = Keymap::default();
+    if let Some(iter) = model.iter_first() {
+        loop {
+            let id = model.get_value(&iter, KEYMAP_ID_COLUMN as i32).get::<String>().unwrap_or_default();
+            let accelerator = model.get_value(&iter, KEYMAP_ACCELERATOR_COLUMN as i32).get::<String>().unwrap_or_default();
+            if let Some(action) = KeyAction::from_id(&id) {
+                keymap.set_binding(action, &accelerator);
+            }
+            if !model.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+    keymap
+}
+
+fn show_save_error(window: &super::PreferencesWindow, message: &str) {
+    error!("{}", message);
+    let dialog = MessageDialog::new(Some(window), DialogFlags::MODAL, MessageType::Error, ButtonsType::Ok, message);
+    dialog.set_title(Some("Failed to Save Settings"));
+    dialog.connect_response(|dialog, _| {
+        dialog.destroy();
+    });
+    dialog.show();
+}
+
+impl PreferencesWindow {
+    pub fn setup(&self, obj: &<PreferencesWindow as ObjectSubclass>::Type) {
+        let mut model = self.keymap_liststore.lock().unwrap();
+        *model = Some(gtk::ListStore::new(&[Type::STRING, Type::STRING, Type::STRING, Type::U32, ModifierType::static_type()]));
+        self.keymap_tree.set_model(Some(model.as_ref().unwrap()));
+        drop(model);
+        self.keymap_action_column.add_attribute(&self.keymap_action_renderer.get(), "text", KEYMAP_LABEL_COLUMN as i32);
+        self.keymap_shortcut_column.add_attribute(&self.keymap_shortcut_renderer.get(), "accel-key", KEYMAP_KEY_COLUMN as i32);
+        self.keymap_shortcut_column.add_attribute(&self.keymap_shortcut_renderer.get(), "accel-mods", KEYMAP_MODIFIERS_COLUMN as i32);
+
+        self.populate();
+
+        // editing shortcuts
+        let keymap_liststore = self.keymap_liststore.clone();
+        self.keymap_shortcut_renderer.connect_accel_edited(move |_, path, key, modifiers, _| {
+            let model = keymap_liststore.lock().unwrap();
+            let model = model.as_ref().unwrap();
+            if let Some(iter) = model.iter_from_string(path) {
+                set_shortcut(model, &iter, &gtk::accelerator_name(key, modifiers));
+            }
+        });
+        let keymap_liststore = self.keymap_liststore.clone();
+        self.keymap_shortcut_renderer.connect_accel_cleared(move |_, path| {
+            let model = keymap_liststore.lock().unwrap();
+            let model = model.as_ref().unwrap();
+            if let Some(iter) = model.iter_from_string(path) {
+                set_shortcut(model, &iter, "");
+            }
+        });
+        let keymap_liststore = self.keymap_liststore.clone();
+        self.reset_keymap_button.connect_clicked(move |_| {
+            let model = keymap_liststore.lock().unwrap();
+            fill_keymap(model.as_ref().unwrap(), &Keymap::default());
+        });
+
+        // cancel button
+        self.cancel_button.connect_clicked(clone!(@weak obj => move |_| {
+            obj.close();
+        }));
+
+        // ok button
+        let worldmachine = self.worldmachine.clone();
+        let renderer = self.renderer.clone();
+        let data_location_entry = self.data_location_entry.get();
+        let binary_location_entry = self.binary_location_entry.get();
+        let tools_location_entry = self.tools_location_entry.get();
+        let compiler_combo = self.compiler_combo.get();
+        let autosave_interval_spin = self.autosave_interval_spin.get();
+        let backup_count_spin = self.backup_count_spin.get();
+        let compact_maps_check = self.compact_maps_check.get();
+        let grid_size_spin = self.grid_size_spin.get();
+        let rotation_snap_spin = self.rotation_snap_spin.get();
+        let scale_snap_spin = self.scale_snap_spin.get();
+        let fly_speed_spin = self.fly_speed_spin.get();
+        let mouse_sensitivity_spin = self.mouse_sensitivity_spin.get();
+        let keymap_liststore = self.keymap_liststore.clone();
+        self.ok_button.connect_clicked(clone!(@weak obj => move |_| {
+            let keymap = keymap_from_list(keymap_liststore.lock().unwrap().as_ref().unwrap());
+            // check before saving anything, so that nothing's half saved
+            if let Some((first, second)) = keymap.conflicts().first() {
+                show_save_error(&obj, &format!("\"{}\" and \"{}\" have the same shortcut", first.label(), second.label()));
+                return;
+            }
+
+            let stored = StoredSettings {
+                data_location: data_location_entry.text().to_string(),
+                binary_location: binary_location_entry.text().to_string(),
+                tools_location: tools_location_entry.text().to_string(),
+                compiler: compiler_combo.active_id().map(|id| MapCompiler::from_id(id.as_str())).unwrap_or(MapCompiler::BuiltIn),
+                autosave_interval: autosave_interval_spin.value_as_int().max(0) as u32,
+                backup_count: backup_count_spin.value_as_int().max(0) as u32,
+                compact_maps: compact_maps_check.is_active(),
+                grid_size: grid_size_spin.value(),
+                rotation_snap: rotation_snap_spin.value(),
+                scale_snap: scale_snap_spin.value(),
+                fly_speed: fly_speed_spin.value(),
+                mouse_sensitivity: mouse_sensitivity_spin.value(),
+            };
+            let res = stored.save();
+            if let Err(e) = res {
+                show_save_error(&obj, &format!("failed to save settings: {}", e));
+                return;
+            }
+            if let Err(e) = keymap.save() {
+                show_save_error(&obj, &format!("failed to save keyboard shortcuts: {}", e));
+                return;
+            }
+            if let Some(app) = gio::Application::default().and_then(|app| app.downcast::<gtk::Application>().ok()) {
+                keymap.apply_accelerators(&app);
+            }
+
+            // tell everyone who cached the data path about the new one
+            let data_location = settings::data_location();
+            if let Some(worldmachine) = worldmachine.lock().unwrap().as_ref() {
+                worldmachine.lock().unwrap().game_data_path = data_location.clone();
+            }
+            if let Some(renderer) = renderer.lock().unwrap().as_ref() {
+                renderer.lock().unwrap().data_dir = data_location;
+            }
+            obj.close();
+        }));
+    }
+
+    pub fn populate(&self) {
+        let current = settings::SETTINGS.lock().unwrap().clone();
+        // show what's stored in gsettings, not the overrides, so that we don't save the overrides by accident
+        let stored = StoredSettings::load().unwrap_or(StoredSettings {
+            data_location: current.data_location.value.clone(),
+            binary_location: current.binary_location.value.clone(),
+            tools_location: current.tools_location.value.clone(),
+            compiler: current.compiler,
+            autosave_interval: current.autosave_interval,
+            backup_count: current.backup_count,
+            compact_maps: current.compact_maps,
+            grid_size: current.grid_size,
+            rotation_snap: current.rotation_snap,
+            scale_snap: current.scale_snap,
+            fly_speed: current.fly_speed,
+            mouse_sensitivity: current.mouse_sensitivity,
+        });
+        self.data_location_entry.set_text(&stored.data_location);
+        self.binary_location_entry.set_text(&stored.binary_location);
+        self.tools_location_entry.set_text(&stored.tools_location);
+        self.compiler_combo.set_active_id(Some(stored.compiler.id()));
+        self.autosave_interval_spin.set_value(stored.autosave_interval as f64);
+        self.backup_count_spin.set_value(stored.backup_count as f64);
+        self.compact_maps_check.set_active(stored.compact_maps);
+        self.grid_size_spin.set_value(stored.grid_size);
+        self.rotation_snap_spin.set_value(stored.rotation_snap);
+        self.scale_snap_spin.set_value(stored.scale_snap);
+        self.fly_speed_spin.set_value(stored.fly_speed);
+        self.mouse_sensitivity_spin.set_value(stored.mouse_sensitivity);
+
+        let overrides = [
+            describe_override("data location", &current.data_location),
+            describe_override("engine binary", &current.binary_location),
+            describe_override("tools location", &current.tools_location),
+        ].into_iter().flatten().collect::<Vec<String>>();
+        let model = self.keymap_liststore.lock().unwrap();
+        if let Some(model) = model.as_ref() {
+            fill_keymap(model, &keymap::KEYMAP.lock().unwrap());
+        }
+
+        self.override_label.set_text(&overrides.join("\n"));
+        self.override_label.set_visible(!overrides.is_empty());
+    }
+}
+
+impl WidgetImpl for PreferencesWindow {}
+impl WindowImpl for PreferencesWindow {}
+impl ApplicationWindowImpl for PreferencesWindow {}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gtk::prelude::*;
+use gtk::{glib, FlowBox};
+use crate::renderer::thumbnails::{ThumbnailReady, THUMBNAIL_SIZE};
+
+// helpers for the grids of thumbnails in the entity picker and asset browser. each tile's widget name is the name
+// of the thing it shows, and the images are kept by name so that thumbnails can be filled in as they're rendered
+
+pub type ThumbnailImages = Arc<Mutex<HashMap<String, gtk::Image>>>;
+
+pub fn clear(flowbox: &FlowBox, images: &ThumbnailImages) {
+    while let Some(child) = flowbox.first_child() {
+        flowbox.remove(&child);
+    }
+    images.lock().unwrap().clear();
+}
+
+pub fn add_tile(flowbox: &FlowBox, images: &ThumbnailImages, name: &str, label: &str, tooltip: &str) {
+    let image = gtk::Image::new();
+    image.set_pixel_size(THUMBNAIL_SIZE);
+    image.set_icon_name(Some("image-loading"));
+    let label = gtk::Label::new(Some(label));
+    label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    label.set_max_width_chars(16);
+    let tile = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    tile.append(&image);
+    tile.append(&label);
+    let child = gtk::FlowBoxChild::new();
+    child.set_child(Some(&tile));
+    child.set_widget_name(name);
+    if !tooltip.is_empty() {
+        child.set_tooltip_text(Some(tooltip));
+    }
+    flowbox.insert(&child, -1);
+    images.lock().unwrap().insert(name.to_string(), image);
+}
+
+pub fn tile_name(child: &gtk::FlowBoxChild) -> String {
+    child.widget_name().to_string()
+}
+
+pub fn selected_tile(flowbox: &FlowBox) -> Option<String> {
+    flowbox.selected_children().first().map(tile_name)
+}
+
+// a sender to hand to the renderer, thumbnails it sends back are shown on the matching tile (if it's still there)
+pub fn thumbnail_sender(images: &ThumbnailImages) -> glib::Sender<ThumbnailReady> {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let images = images.clone();
+    receiver.attach(None, move |ready: ThumbnailReady| {
+        if let Some(image) = images.lock().unwrap().get(&ready.name) {
+            image.set_from_file(Some(&ready.path));
+        }
+        glib::Continue(true)
+    });
+    sender
+}
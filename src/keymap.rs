@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use gtk::gdk::{Key, ModifierType};
+use gtk::glib;
+use gtk::prelude::*;
+
+// keyboard shortcuts. camera keys are held down and read by the renderer every frame, viewport keys only do anything
+// while the viewport has focus, and the rest are accelerators for the app's actions so they work anywhere in the
+// window. bindings are gtk accelerator strings ("w", "<Control>s", "Delete"), and are kept in
+// <user config dir>/h2eck/keymap.yaml, which only lists the ones that have been changed from the defaults.
+// an empty string means the action isn't bound to anything
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyAction {
+    CameraForward,
+    CameraBackward,
+    CameraLeft,
+    CameraRight,
+    CameraUp,
+    CameraDown,
+    CameraFast, // held with the other camera keys to move faster
+    FocusSelection,
+    FrameAll,
+    ViewPerspective,
+    ViewTop,
+    ViewFront,
+    ViewSide,
+    GoToBookmark(usize), // one of the first BOOKMARK_KEYS bookmarks, counting from 0
+    DeleteEntity,
+    DuplicateEntity,
+    NewMap,
+    OpenMap,
+    SaveMap,
+}
+
+// how many bookmarks get a key, ids and labels are below
+pub const BOOKMARK_KEYS: usize = 9;
+const BOOKMARK_IDS: [&str; BOOKMARK_KEYS] = [
+    "bookmark-1", "bookmark-2", "bookmark-3", "bookmark-4", "bookmark-5", "bookmark-6", "bookmark-7", "bookmark-8", "bookmark-9",
+];
+const BOOKMARK_LABELS: [&str; BOOKMARK_KEYS] = [
+    "go to bookmark 1", "go to bookmark 2", "go to bookmark 3", "go to bookmark 4", "go to bookmark 5",
+    "go to bookmark 6", "go to bookmark 7", "go to bookmark 8", "go to bookmark 9",
+];
+const BOOKMARK_BINDINGS: [&str; BOOKMARK_KEYS] = ["1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+// in the order they're shown in the preferences
+pub const KEY_ACTIONS: [KeyAction; 27] = [
+    KeyAction::CameraForward,
+    KeyAction::CameraBackward,
+    KeyAction::CameraLeft,
+    KeyAction::CameraRight,
+    KeyAction::CameraUp,
+    KeyAction::CameraDown,
+    KeyAction::CameraFast,
+    KeyAction::FocusSelection,
+    KeyAction::FrameAll,
+    KeyAction::ViewPerspective,
+    KeyAction::ViewTop,
+    KeyAction::ViewFront,
+    KeyAction::ViewSide,
+    KeyAction::GoToBookmark(0),
+    KeyAction::GoToBookmark(1),
+    KeyAction::GoToBookmark(2),
+    KeyAction::GoToBookmark(3),
+    KeyAction::GoToBookmark(4),
+    KeyAction::GoToBookmark(5),
+    KeyAction::GoToBookmark(6),
+    KeyAction::GoToBookmark(7),
+    KeyAction::GoToBookmark(8),
+    KeyAction::DeleteEntity,
+    KeyAction::DuplicateEntity,
+    KeyAction::NewMap,
+    KeyAction::OpenMap,
+    KeyAction::SaveMap,
+];
+
+impl KeyAction {
+    // what it's called in keymap.yaml
+    pub fn id(&self) -> &'static str {
+        match self {
+            KeyAction::CameraForward => "camera-forward",
+            KeyAction::CameraBackward => "camera-backward",
+            KeyAction::CameraLeft => "camera-left",
+            KeyAction::CameraRight => "camera-right",
+            KeyAction::CameraUp => "camera-up",
+            KeyAction::CameraDown => "camera-down",
+            KeyAction::CameraFast => "camera-fast",
+            KeyAction::FocusSelection => "focus-selection",
+            KeyAction::FrameAll => "frame-all",
+            KeyAction::ViewPerspective => "view-perspective",
+            KeyAction::ViewTop => "view-top",
+            KeyAction::ViewFront => "view-front",
+            KeyAction::ViewSide => "view-side",
+            KeyAction::GoToBookmark(index) => BOOKMARK_IDS[*index],
+            KeyAction::DeleteEntity => "delete-entity",
+            KeyAction::DuplicateEntity => "duplicate-entity",
+            KeyAction::NewMap => "new",
+            KeyAction::OpenMap => "open",
+            KeyAction::SaveMap => "save",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        KEY_ACTIONS.iter().find(|action| action.id() == id).copied()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KeyAction::CameraForward => "camera forward",
+            KeyAction::CameraBackward => "camera backward",
+            KeyAction::CameraLeft => "camera left",
+            KeyAction::CameraRight => "camera right",
+            KeyAction::CameraUp => "camera up",
+            KeyAction::CameraDown => "camera down",
+            KeyAction::CameraFast => "move camera faster (hold)",
+            KeyAction::FocusSelection => "focus on selection",
+            KeyAction::FrameAll => "frame everything",
+            KeyAction::ViewPerspective => "perspective view",
+            KeyAction::ViewTop => "top view",
+            KeyAction::ViewFront => "front view",
+            KeyAction::ViewSide => "side view",
+            KeyAction::GoToBookmark(index) => BOOKMARK_LABELS[*index],
+            KeyAction::DeleteEntity => "delete entity",
+            KeyAction::DuplicateEntity => "duplicate entity",
+            KeyAction::NewMap => "new map",
+            KeyAction::OpenMap => "open map",
+            KeyAction::SaveMap => "save map",
+        }
+    }
+
+    pub fn default_binding(&self) -> &'static str {
+        match self {
+            KeyAction::CameraForward => "w",
+            KeyAction::CameraBackward => "s",
+            KeyAction::CameraLeft => "a",
+            KeyAction::CameraRight => "d",
+            KeyAction::CameraUp => "e",
+            KeyAction::CameraDown => "q",
+            KeyAction::CameraFast => "Shift_L",
+            KeyAction::FocusSelection => "f",
+            KeyAction::FrameAll => "Home",
+            KeyAction::ViewPerspective => "KP_5",
+            KeyAction::ViewTop => "KP_7",
+            KeyAction::ViewFront => "KP_1",
+            KeyAction::ViewSide => "KP_3",
+            KeyAction::GoToBookmark(index) => BOOKMARK_BINDINGS[*index],
+            KeyAction::DeleteEntity => "Delete",
+            KeyAction::DuplicateEntity => "<Control>d",
+            KeyAction::NewMap => "<Control>n",
+            KeyAction::OpenMap => "<Control>o",
+            KeyAction::SaveMap => "<Control>s",
+        }
+    }
+
+    // camera keys do something for as long as they're held, whether or not shift is down
+    pub fn is_held(&self) -> bool {
+        matches!(self, KeyAction::CameraForward | KeyAction::CameraBackward | KeyAction::CameraLeft | KeyAction::CameraRight
+            | KeyAction::CameraUp | KeyAction::CameraDown | KeyAction::CameraFast)
+    }
+
+    // the app action this is an accelerator for, None for keys that only work in the viewport
+    pub fn action_name(&self) -> Option<&'static str> {
+        match self {
+            KeyAction::DuplicateEntity => Some("app.duplicate-entity"),
+            KeyAction::NewMap => Some("app.new"),
+            KeyAction::OpenMap => Some("app.open"),
+            KeyAction::SaveMap => Some("app.save"),
+            _ => None,
+        }
+    }
+}
+
+// the modifiers that matter to a held key, which is all of them but shift
+fn held_modifiers(modifiers: ModifierType) -> ModifierType {
+    modifiers & gtk::accelerator_get_default_mod_mask() & !ModifierType::SHIFT_MASK
+}
+
+// the key and modifiers of an accelerator string, None if it's empty or gtk can't make sense of it
+pub fn parse(accelerator: &str) -> Option<(Key, ModifierType)> {
+    if accelerator.is_empty() {
+        return None;
+    }
+    gtk::accelerator_parse(accelerator)
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: BTreeMap<KeyAction, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: KEY_ACTIONS.iter().map(|action| (*action, action.default_binding().to_string())).collect(),
+        }
+    }
+}
+
+impl Keymap {
+    // the defaults, with whatever's in the keymap file on top
+    pub fn load() -> Self {
+        let mut keymap = Self::default();
+        let path = keymap_path();
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return keymap,
+        };
+        let stored: BTreeMap<String, String> = match serde_yaml::from_str(&contents) {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("failed to parse keymap {}: {}", path.display(), e);
+                return keymap;
+            }
+        };
+        for (id, accelerator) in stored {
+            let action = match KeyAction::from_id(&id) {
+                Some(action) => action,
+                None => {
+                    warn!("unknown action {} in keymap {}", id, path.display());
+                    continue;
+                }
+            };
+            if !accelerator.is_empty() && parse(&accelerator).is_none() {
+                warn!("can't understand shortcut {} for {} in keymap {}", accelerator, id, path.display());
+                continue;
+            }
+            keymap.bindings.insert(action, accelerator);
+        }
+        keymap
+    }
+
+    // writes the bindings that aren't the defaults to the keymap file, and makes this the keymap in use
+    pub fn save(&self) -> Result<(), String> {
+        let changed = self.bindings.iter()
+            .filter(|(action, accelerator)| accelerator.as_str() != action.default_binding())
+            .map(|(action, accelerator)| (action.id().to_string(), accelerator.clone()))
+            .collect::<BTreeMap<String, String>>();
+        let path = keymap_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+        let serialized = serde_yaml::to_string(&changed).map_err(|e| e.to_string())?;
+        std::fs::write(&path, serialized).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        *KEYMAP.lock().unwrap() = self.clone();
+        Ok(())
+    }
+
+    pub fn binding(&self, action: KeyAction) -> String {
+        self.bindings.get(&action).cloned().unwrap_or_default()
+    }
+
+    pub fn set_binding(&mut self, action: KeyAction, accelerator: &str) {
+        self.bindings.insert(action, accelerator.to_string());
+    }
+
+    // pairs of actions bound to the same thing, which the preferences won't save
+    pub fn conflicts(&self) -> Vec<(KeyAction, KeyAction)> {
+        let mut conflicts = Vec::new();
+        let parsed = self.bindings.iter()
+            .filter_map(|(action, accelerator)| parse(accelerator).map(|(key, modifiers)| (*action, key.to_lower(), modifiers)))
+            .collect::<Vec<_>>();
+        for (i, (action, key, modifiers)) in parsed.iter().enumerate() {
+            for (other, other_key, other_modifiers) in parsed.iter().skip(i + 1) {
+                // held keys don't care about shift (see action_for_key), so e.g. w and <Shift>w clash if either of them
+                // is a held action
+                let same_modifiers = if action.is_held() || other.is_held() {
+                    held_modifiers(*modifiers) == held_modifiers(*other_modifiers)
+                } else {
+                    modifiers == other_modifiers
+                };
+                if key == other_key && same_modifiers {
+                    conflicts.push((*action, *other));
+                }
+            }
+        }
+        conflicts
+    }
+
+    // the action a key press is bound to. held keys match whether or not shift is down, so that holding the fast key
+    // doesn't stop the others from working, but not while e.g. control is, so that <Control>s saves without also
+    // moving the camera
+    pub fn action_for_key(&self, key: Key, state: ModifierType) -> Option<KeyAction> {
+        let key = key.to_lower();
+        let state = state & gtk::accelerator_get_default_mod_mask();
+        self.bindings.iter()
+            .filter_map(|(action, accelerator)| parse(accelerator).map(|binding| (*action, binding)))
+            .find(|(action, (bound_key, bound_modifiers))| {
+                bound_key.to_lower() == key && if action.is_held() {
+                    held_modifiers(*bound_modifiers) == held_modifiers(state)
+                } else {
+                    *bound_modifiers == state
+                }
+            })
+            .map(|(action, _)| action)
+    }
+
+    // the held action bound to a key, whatever modifiers it's bound with. for keys being let go of, so that a camera
+    // key let go of after pressing control still stops the camera
+    pub fn held_action_for_key(&self, key: Key) -> Option<KeyAction> {
+        let key = key.to_lower();
+        self.bindings.iter()
+            .filter(|(action, _)| action.is_held())
+            .filter_map(|(action, accelerator)| parse(accelerator).map(|(bound_key, _)| (*action, bound_key)))
+            .find(|(_, bound_key)| bound_key.to_lower() == key)
+            .map(|(action, _)| action)
+    }
+
+    // binds the app actions' accelerators, call again after the keymap changes
+    pub fn apply_accelerators(&self, app: &gtk::Application) {
+        for action in KEY_ACTIONS.iter() {
+            if let Some(action_name) = action.action_name() {
+                let accelerator = self.binding(*action);
+                let accelerators = if parse(&accelerator).is_some() { vec![accelerator.as_str()] } else { Vec::new() };
+                app.set_accels_for_action(action_name, &accelerators);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref KEYMAP: Mutex<Keymap> = Mutex::new(Keymap::load());
+}
+
+pub fn keymap_path() -> PathBuf {
+    glib::user_config_dir().join("h2eck").join("keymap.yaml")
+}
+
+pub fn action_for_key(key: Key, state: ModifierType) -> Option<KeyAction> {
+    KEYMAP.lock().unwrap().action_for_key(key, state)
+}
+
+pub fn held_action_for_key(key: Key) -> Option<KeyAction> {
+    KEYMAP.lock().unwrap().held_action_for_key(key)
+}
@@ -7,11 +7,17 @@ use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, gio, show_about_dialog};
 use gio::prelude::*;
 use gtk::gdk::Display;
+use gtk::glib;
 use gtk::glib::clone;
-use crate::h2eck_window::{about_window, h2eckWindow};
+use gtk::subclass::prelude::ObjectSubclassIsExt;
+use crate::h2eck_window::{about_window, h2eckWindow, preferences_window};
+use crate::worldmachine::recovery;
 
 pub mod h2eck_window;
+pub mod keymap;
+pub mod playtest;
 pub mod renderer;
+pub mod settings;
 pub mod worldmachine;
 
 const APP_ID: &str = "com.realmicrosoft.h2eck";
@@ -39,11 +45,18 @@ fn main() {
         );
 
         build_accelerators(app);
+
+        // only the primary instance gets here, so only it can claim the recovery lock
+        recovery::begin_session();
     });
 
+    build_command_line_options(&app);
+
     app.connect_activate(build_ui);
 
     app.run();
+
+    recovery::end_session();
 }
 
 fn build_ui(app: &Application) {
@@ -63,8 +76,22 @@ fn build_system_menu(app: &Application) {
     let help_menu = gio::Menu::new();
 
     app_menu.append(Some("About"), Some("app.about"));
+    app_menu.append(Some("Preferences"), Some("app.preferences"));
     app_menu.append(Some("Quit"), Some("app.quit"));
 
+    let recent_menu = gio::Menu::new();
+    populate_recent_menu(&recent_menu);
+    // keep the list up to date as maps get opened and saved
+    if let Some(gsettings) = settings::gsettings() {
+        // the closure holds on to gsettings, otherwise it'd be dropped and never tell us anything
+        gsettings.connect_changed(Some(settings::KEY_RECENT_MAPS), clone!(@strong gsettings, @weak recent_menu => move |_, _| {
+            let _ = &gsettings;
+            populate_recent_menu(&recent_menu);
+        }));
+    }
+
+    file_menu.append_submenu(Some("Open Recent"), &recent_menu);
+    file_menu.append(Some("Preferences"), Some("app.preferences"));
     file_menu.append(Some("Quit"), Some("app.quit"));
 
     help_menu.append(Some("About"), Some("app.about"));
@@ -75,6 +102,15 @@ fn build_system_menu(app: &Application) {
     app.set_menubar(Some(&menu_bar));
 }
 
+fn populate_recent_menu(recent_menu: &gio::Menu) {
+    recent_menu.remove_all();
+    for path in settings::recent_maps() {
+        let item = gio::MenuItem::new(Some(&path), None);
+        item.set_action_and_target_value(Some("app.open-recent"), Some(&path.to_variant()));
+        recent_menu.append_item(&item);
+    }
+}
+
 fn build_appwide_actions(app: &Application, window: &h2eckWindow) {
     let about_action = gio::SimpleAction::new("about", None);
     // show the about dialog
@@ -83,14 +119,142 @@ fn build_appwide_actions(app: &Application, window: &h2eckWindow) {
         about_window.show();
     }));
 
+    let preferences_action = gio::SimpleAction::new("preferences", None);
+    // show the preferences window
+    preferences_action.connect_activate(clone!(@strong window => move |_, _| {
+        let preferences_window = preferences_window::PreferencesWindow::new();
+        *preferences_window.imp().worldmachine.lock().unwrap() = Some(window.imp().worldmachine.clone());
+        *preferences_window.imp().renderer.lock().unwrap() = Some(window.imp().renderer.clone());
+        preferences_window.show();
+    }));
+
     let quit_action = gio::SimpleAction::new("quit", None);
-    quit_action.connect_activate(clone!(@strong app => move |_, _| {
-        app.quit();
+    // ask about unsaved changes before quitting
+    quit_action.connect_activate(clone!(@strong app, @strong window => move |_, _| {
+        let editor = window.imp().editor.lock().unwrap().clone();
+        match editor {
+            Some(editor) => editor.confirm_discard_changes(clone!(@strong app => move || {
+                app.quit();
+            })),
+            None => app.quit(),
+        }
+    }));
+
+    let open_recent_action = gio::SimpleAction::new("open-recent", Some(glib::VariantTy::STRING));
+    open_recent_action.connect_activate(clone!(@strong window => move |_, parameter| {
+        let path = match parameter.and_then(|parameter| parameter.get::<String>()) {
+            Some(path) => path,
+            None => return,
+        };
+        let editor = window.imp().editor.lock().unwrap().clone();
+        if let Some(editor) = editor {
+            editor.confirm_discard_changes(clone!(@strong editor => move || {
+                editor.open_world(&path);
+            }));
+        }
+    }));
+
+    // the actions below are mostly here so they can have accelerators, see keymap.rs
+    let new_action = gio::SimpleAction::new("new", None);
+    new_action.connect_activate(clone!(@strong window => move |_, _| {
+        let editor = window.imp().editor.lock().unwrap().clone();
+        if let Some(editor) = editor {
+            editor.imp().new.emit_clicked();
+        }
+    }));
+
+    let open_action = gio::SimpleAction::new("open", None);
+    open_action.connect_activate(clone!(@strong window => move |_, _| {
+        let editor = window.imp().editor.lock().unwrap().clone();
+        if let Some(editor) = editor {
+            editor.imp().open.emit_clicked();
+        }
+    }));
+
+    let save_action = gio::SimpleAction::new("save", None);
+    save_action.connect_activate(clone!(@strong window => move |_, _| {
+        let editor = window.imp().editor.lock().unwrap().clone();
+        if let Some(editor) = editor {
+            editor.imp().save.emit_clicked();
+        }
+    }));
+
+    let duplicate_entity_action = gio::SimpleAction::new("duplicate-entity", None);
+    duplicate_entity_action.connect_activate(clone!(@strong window => move |_, _| {
+        let editor = window.imp().editor.lock().unwrap().clone();
+        if let Some(editor) = editor {
+            editor.imp().duplicate_selected_entity();
+        }
     }));
 
     app.add_action(&about_action);
+    app.add_action(&duplicate_entity_action);
+    app.add_action(&new_action);
+    app.add_action(&open_action);
+    app.add_action(&open_recent_action);
+    app.add_action(&preferences_action);
     app.add_action(&quit_action);
+    app.add_action(&save_action);
+}
+
+// lets the data, binary and tools locations be overridden per launch, these win over gsettings and the environment
+fn build_command_line_options(app: &Application) {
+    app.add_main_option("data-location", glib::Char::from(b'd'), glib::OptionFlags::NONE, glib::OptionArg::String,
+                        "location of the game engine's data files (overrides settings and H2ECK_DATA_LOCATION)", Some("PATH"));
+    app.add_main_option("binary-location", glib::Char::from(b'b'), glib::OptionFlags::NONE, glib::OptionArg::String,
+                        "location of the game engine binary (overrides settings and H2ECK_BINARY_LOCATION)", Some("PATH"));
+    app.add_main_option("tools-location", glib::Char::from(b't'), glib::OptionFlags::NONE, glib::OptionArg::String,
+                        "location of the game engine tools (overrides settings and H2ECK_TOOLS_LOCATION)", Some("PATH"));
+    app.add_main_option("convert", glib::Char::from(0), glib::OptionFlags::NONE, glib::OptionArg::String,
+                        "convert a .map or .edef to the format given by --convert-to's extension, then exit", Some("FILE"));
+    app.add_main_option("convert-to", glib::Char::from(0), glib::OptionFlags::NONE, glib::OptionArg::String,
+                        "where --convert writes to (.json for json, .ron for ron, anything else for yaml)", Some("FILE"));
+    app.add_main_option("generate-thumbnails", glib::Char::from(0), glib::OptionFlags::NONE, glib::OptionArg::None,
+                        "render thumbnails for every entity def and model without opening a window, then exit", None);
+
+    app.connect_handle_local_options(|_, options| {
+        settings::set_command_line_overrides(settings::CommandLineOverrides {
+            data_location: options.lookup::<String>("data-location").ok().flatten(),
+            binary_location: options.lookup::<String>("binary-location").ok().flatten(),
+            tools_location: options.lookup::<String>("tools-location").ok().flatten(),
+        });
+        if let Some(input) = options.lookup::<String>("convert").ok().flatten() {
+            // the worldmachine isn't set up for these, so the component types it would register need registering here
+            worldmachine::components::register_component_types();
+            let output = match options.lookup::<String>("convert-to").ok().flatten() {
+                Some(output) => output,
+                None => {
+                    error!("--convert needs --convert-to");
+                    return 1;
+                }
+            };
+            return match worldmachine::formats::convert(&input, &output) {
+                Ok(()) => 0,
+                Err(e) => {
+                    error!("failed to convert {}: {}", input, e);
+                    1
+                }
+            };
+        }
+        if options.contains("generate-thumbnails") {
+            worldmachine::components::register_component_types();
+            return match renderer::headless::generate_thumbnails() {
+                Ok(0) => 0,
+                Ok(failed) => {
+                    error!("{} thumbnails failed to render", failed);
+                    1
+                }
+                Err(e) => {
+                    error!("failed to generate thumbnails: {}", e);
+                    1
+                }
+            };
+        }
+        // -1 means keep going with the default handling
+        -1
+    });
 }
 
 fn build_accelerators(app: &Application) {
+    keymap::KEYMAP.lock().unwrap().apply_accelerators(app);
 }
\ No newline at end of file
@@ -0,0 +1,134 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use gtk::glib;
+use crate::settings;
+
+// the world gets compiled to this map name before launching the engine, it gets removed again when the engine stops
+pub const PLAYTEST_MAP_NAME: &str = "h2eck_playtest";
+
+#[derive(Clone, Debug)]
+pub enum PlaytestMessage {
+    Stdout(String),
+    Stderr(String),
+    Exited(Option<i32>),
+}
+
+pub struct Playtest {
+    child: Arc<Mutex<Child>>,
+    stopped: Arc<Mutex<bool>>,
+    pub map_name: String,
+}
+
+// forwards every line of a pipe to the main loop until it closes
+fn spawn_line_reader<R: Read + Send + 'static>(pipe: R, sender: glib::Sender<PlaytestMessage>, stderr: bool) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let reader = BufReader::new(pipe);
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("failed to read line from engine output: {}", e);
+                    break;
+                }
+            };
+            let message = if stderr { PlaytestMessage::Stderr(line) } else { PlaytestMessage::Stdout(line) };
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+impl Playtest {
+    // starts the engine binary from settings with the given (already compiled) map
+    pub fn start(map_name: &str, sender: glib::Sender<PlaytestMessage>) -> Result<Self, String> {
+        let binary = settings::binary_location();
+        if !Path::new(&binary).exists() {
+            return Err(format!("engine binary not found at {}, check your preferences", binary));
+        }
+        let mut command = Command::new(&binary);
+        command.arg("--map").arg(map_name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // the engine expects to be run from the directory it lives in
+        if let Some(parent) = Path::new(&binary).parent() {
+            if !parent.as_os_str().is_empty() {
+                command.current_dir(parent);
+            }
+        }
+        let mut child = command.spawn().map_err(|e| format!("failed to start {}: {}", binary, e))?;
+        info!("started playtest of {} with {} (pid {})", map_name, binary, child.id());
+
+        let stdout = child.stdout.take().map(|pipe| spawn_line_reader(pipe, sender.clone(), false));
+        let stderr = child.stderr.take().map(|pipe| spawn_line_reader(pipe, sender.clone(), true));
+
+        let child = Arc::new(Mutex::new(child));
+        let stopped = Arc::new(Mutex::new(false));
+
+        // poll for the process exiting, so that the editor can update its buttons
+        let waiter_child = child.clone();
+        let waiter_stopped = stopped.clone();
+        std::thread::spawn(move || {
+            let code = loop {
+                let status = waiter_child.lock().unwrap().try_wait();
+                match status {
+                    Ok(Some(status)) => break status.code(),
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("failed to wait for engine process: {}", e);
+                        break None;
+                    }
+                }
+                if *waiter_stopped.lock().unwrap() {
+                    // stop() already reaped the process
+                    break None;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            };
+            // the editor stops listening once it hears the engine exited, so let the readers pass on everything the
+            // engine printed (which is what matters most if it crashed) first
+            for reader in [stdout, stderr].into_iter().flatten() {
+                let _ = reader.join();
+            }
+            let _ = sender.send(PlaytestMessage::Exited(code));
+        });
+
+        Ok(Self {
+            child,
+            stopped,
+            map_name: map_name.to_string(),
+        })
+    }
+
+    pub fn stop(&mut self) {
+        let mut stopped = self.stopped.lock().unwrap();
+        if *stopped {
+            return;
+        }
+        let mut child = self.child.lock().unwrap();
+        if let Ok(None) = child.try_wait() {
+            if let Err(e) = child.kill() {
+                error!("failed to stop engine process: {}", e);
+            }
+            let _ = child.wait();
+        }
+        *stopped = true;
+    }
+
+    pub fn is_running(&self) -> bool {
+        if *self.stopped.lock().unwrap() {
+            return false;
+        }
+        matches!(self.child.lock().unwrap().try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Playtest {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,10 +1,12 @@
 use std::ops::Deref;
+use std::time::Instant;
 use gfx_maths::*;
 use crate::renderer::helpers;
 
 #[derive(Clone)]
 pub struct Camera {
     position: Vec3,
+    pivot: Vec3, // what the camera orbits around and zooms towards
     rotation: Quaternion,
     projection: Mat4,
     view: Mat4,
@@ -12,6 +14,20 @@ pub struct Camera {
     fov: f32,
     near: f32,
     far: f32,
+    camera_view: CameraView,
+    ortho_size: f32, // half of how much of the world fits vertically in an orthographic view
+    saved_perspective: Option<(Vec3, Quaternion, Vec3)>, // position, rotation and pivot to go back to
+    transition: Option<CameraTransition>,
+}
+
+// a smooth move from one position and rotation to another, see move_to
+#[derive(Clone)]
+struct CameraTransition {
+    from_position: Vec3,
+    from_rotation: Quaternion,
+    to_position: Vec3,
+    to_rotation: Quaternion,
+    started: Instant,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -24,14 +40,61 @@ pub enum CameraMovement {
     Down,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Fly, // wasd to move, right drag to look around
+    Orbit, // right drag to orbit around the pivot
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraView {
+    Perspective,
+    Top,
+    Front,
+    Side,
+}
+
+impl CameraView {
+    // the way the orthographic views look, None for perspective
+    pub fn direction(&self) -> Option<Vec3> {
+        match self {
+            CameraView::Perspective => None,
+            CameraView::Top => Some(Vec3::new(0.0, -1.0, 0.0)),
+            CameraView::Front => Some(Vec3::new(0.0, 0.0, -1.0)),
+            CameraView::Side => Some(Vec3::new(-1.0, 0.0, 0.0)),
+        }
+    }
+}
+
+// how close orbiting and zooming can get to the pivot
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+// stop just short of straight up or down, where yaw stops meaning anything
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+// how long move_to takes to get there
+const TRANSITION_SECONDS: f32 = 0.4;
+
 fn degrees_to_radians(degrees: f32) -> f32 {
     degrees * std::f32::consts::PI / 180.0
 }
 
+// interpolates between two rotations, going the short way round. close enough to slerp for the small steps of a
+// transition, and doesn't fall apart when the rotations are the same
+fn nlerp(a: Quaternion, b: Quaternion, t: f32) -> Quaternion {
+    let dot = a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w;
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+    let x = a.x + (b.x * sign - a.x) * t;
+    let y = a.y + (b.y * sign - a.y) * t;
+    let z = a.z + (b.z * sign - a.z) * t;
+    let w = a.w + (b.w * sign - a.w) * t;
+    let length = (x * x + y * y + z * z + w * w).sqrt();
+    Quaternion::new(x / length, y / length, z / length, w / length)
+}
+
 impl Camera {
     pub fn new(window_size: Vec2, fov: f32, near: f32, far: f32) -> Camera {
         let mut camera = Camera {
             position: Vec3::new(0.0, 0.0, 0.0),
+            pivot: Vec3::new(0.0, 0.0, 0.0),
             rotation: Quaternion::new(0.0, 0.0, 0.0, 1.0),
             projection: Mat4::identity(),
             view: Mat4::identity(),
@@ -39,6 +102,10 @@ impl Camera {
             fov,
             near,
             far,
+            camera_view: CameraView::Perspective,
+            ortho_size: 10.0,
+            saved_perspective: None,
+            transition: None,
         };
 
         camera.recalculate_projection();
@@ -75,6 +142,11 @@ impl Camera {
         self.window_size
     }
 
+    pub fn set_window_size(&mut self, window_size: Vec2) {
+        self.window_size = window_size;
+        self.recalculate_projection();
+    }
+
     pub fn get_front(&self) -> Vec3 {
         let mut front = Vec3::new(0.0, 0.0, -1.0);
         front = helpers::rotate_vector_by_quaternion(front, self.rotation);
@@ -93,28 +165,203 @@ impl Camera {
         up.normalize().deref().clone()
     }
 
+    // where the camera actually is, the position is stored inverted
+    pub fn get_eye(&self) -> Vec3 {
+        -self.position
+    }
+
+    // the direction the camera is looking in, it looks down -front
+    pub fn get_view_direction(&self) -> Vec3 {
+        -self.get_front()
+    }
+
     // sets rotation to be looking at the target, with the up vector being up, and recalculates the view matrix
     pub fn look_at(&mut self, target: Vec3) {
-        // subtract the target from the camera position and take atan2 of the difference
-        let diff = self.position - target;
-        let yaw = f32::atan2(diff.x, diff.z);
-        let pitch = f32::atan2(diff.y, diff.z);
-        self.rotation = Quaternion::from_euler_angles_zyx(&Vec3::new(pitch, yaw, 0.0));
-        self.recalculate_view();
+        self.look_in_direction(target - self.get_eye());
     }
 
     // sets rotation to be looking in the direction of a vector, with the up vector being up, and recalculates the view matrix
     pub fn look_in_direction(&mut self, direction: Vec3) {
-        // subtract the target from the camera position and take atan2 of the difference
-        let yaw = f32::atan2(direction.x, direction.z);
-        let pitch = f32::atan2(direction.y, direction.z);
-        self.rotation = Quaternion::from_euler_angles_zyx(&Vec3::new(pitch, yaw, 0.0));
+        let length = direction.magnitude();
+        if length < f32::EPSILON {
+            return;
+        }
+        let (yaw, pitch) = direction_yaw_pitch(direction * (1.0 / length));
+        self.set_yaw_pitch(yaw, pitch);
+    }
+
+    fn set_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        self.rotation = yaw_pitch_rotation(yaw, pitch);
+        self.recalculate_view();
+    }
+
+    fn distance_to_pivot(&self) -> f32 {
+        (self.get_eye() - self.pivot).magnitude().max(MIN_ORBIT_DISTANCE)
+    }
+
+    // moves the pivot to be straight ahead, at the same distance as it was, so that orbiting after flying around
+    // doesn't swing the camera around something behind it
+    pub fn recenter_pivot(&mut self) {
+        self.pivot = self.get_eye() + self.get_view_direction() * self.distance_to_pivot();
+    }
+
+    // swings the camera around the pivot by the given angles (in radians), keeping it looking at the pivot
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let distance = self.distance_to_pivot();
+        let direction = (self.pivot - self.get_eye()) * (1.0 / distance);
+        let pitch = (direction.y.clamp(-1.0, 1.0).asin() + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+        let yaw = (-direction.x).atan2(direction.z) + yaw;
+        let direction = Vec3::new(-pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        self.position = -(self.pivot - direction * distance);
+        self.set_yaw_pitch(yaw, pitch);
+    }
+
+    // turns the camera where it stands by the given angles (in radians), for looking around while flying
+    pub fn look_around(&mut self, yaw: f32, pitch: f32) {
+        let direction = self.get_view_direction();
+        let pitch = (direction.y.clamp(-1.0, 1.0).asin() + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+        let yaw = (-direction.x).atan2(direction.z) + yaw;
+        self.set_yaw_pitch(yaw, pitch);
+    }
+
+    // moves the camera towards (factor < 1) or away from (factor > 1) the pivot, or zooms orthographic views
+    pub fn dolly(&mut self, factor: f32) {
+        if self.is_orthographic() {
+            self.ortho_size = (self.ortho_size * factor).max(MIN_ORBIT_DISTANCE);
+            self.recalculate_projection();
+            return;
+        }
+        let distance = (self.distance_to_pivot() * factor).max(MIN_ORBIT_DISTANCE);
+        self.set_position(-(self.pivot - self.get_view_direction() * distance));
+    }
+
+    // moves the camera and the pivot forwards, or backwards if amount is negative
+    pub fn fly_forward(&mut self, amount: f32) {
+        let offset = self.get_view_direction() * amount;
+        self.pivot += offset;
+        self.set_position(self.position - offset);
+    }
+
+    // moves the camera and the pivot sideways and up, in world units
+    pub fn pan(&mut self, right: f32, up: f32) {
+        // same directions as the keyboard uses, so panning and flying agree
+        let offset = self.process_keyboard(CameraMovement::Right, right) + self.process_keyboard(CameraMovement::Up, up);
+        // the position is stored inverted, so the eye moves the other way
+        self.pivot -= offset;
+        self.set_position(self.position + offset);
+    }
+
+    // half of how much of the world fits vertically at the given distance from the camera
+    pub fn visible_half_height(&self, distance: f32) -> f32 {
+        if self.is_orthographic() {
+            return self.ortho_size;
+        }
+        distance * (degrees_to_radians(self.fov) * 0.5).tan()
+    }
+
+    // how many world units a pixel covers at the pivot's distance, for panning
+    pub fn units_per_pixel(&self) -> f32 {
+        2.0 * self.visible_half_height(self.distance_to_pivot()) / self.window_size.y
+    }
+
+    // backs the camera away from a sphere along the way it's looking until it fits in view, and orbits around it
+    pub fn frame(&mut self, center: Vec3, radius: f32) {
+        let distance = radius.max(MIN_ORBIT_DISTANCE) / (degrees_to_radians(self.fov) * 0.5).sin() * 1.1;
+        if self.is_orthographic() {
+            self.ortho_size = radius.max(MIN_ORBIT_DISTANCE) * 1.1;
+            self.recalculate_projection();
+        }
+        self.pivot = center;
+        self.position = -(center - self.get_view_direction() * distance);
+        self.look_at(center);
+    }
+
+    // starts moving smoothly to the given position (stored inverted, like set_position) and rotation, call
+    // update_transition every frame to get there
+    pub fn move_to(&mut self, position: Vec3, rotation: Quaternion) {
+        self.transition = Some(CameraTransition {
+            from_position: self.position,
+            from_rotation: self.rotation,
+            to_position: position,
+            to_rotation: rotation,
+            started: Instant::now(),
+        });
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    // stops a transition where it is, for when the camera is moved by hand
+    pub fn cancel_transition(&mut self) {
+        self.transition = None;
+    }
+
+    pub fn update_transition(&mut self) {
+        let transition = match self.transition.as_ref() {
+            Some(transition) => transition.clone(),
+            None => return,
+        };
+        let t = (transition.started.elapsed().as_secs_f32() / TRANSITION_SECONDS).min(1.0);
+        // ease in and out
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.position = transition.from_position + (transition.to_position - transition.from_position) * eased;
+        self.rotation = nlerp(transition.from_rotation, transition.to_rotation, eased);
         self.recalculate_view();
+        if t >= 1.0 {
+            self.transition = None;
+            self.recenter_pivot();
+        }
+    }
+
+    pub fn get_camera_view(&self) -> CameraView {
+        self.camera_view
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        self.camera_view != CameraView::Perspective
+    }
+
+    // switches to one of the fixed orthographic views, looking at the pivot, or back to how the camera was before
+    // the first orthographic view was picked
+    pub fn set_camera_view(&mut self, camera_view: CameraView) {
+        if camera_view == self.camera_view {
+            return;
+        }
+        match camera_view.direction() {
+            Some(direction) => {
+                if self.camera_view == CameraView::Perspective {
+                    self.saved_perspective = Some((self.position, self.rotation, self.pivot));
+                    // keep about the same amount of the world in view
+                    self.ortho_size = self.visible_half_height(self.distance_to_pivot());
+                }
+                self.camera_view = camera_view;
+                let distance = self.distance_to_pivot();
+                self.position = -(self.pivot - direction * distance);
+                self.look_in_direction(direction);
+            }
+            None => {
+                self.camera_view = camera_view;
+                if let Some((position, rotation, pivot)) = self.saved_perspective.take() {
+                    self.position = position;
+                    self.rotation = rotation;
+                    self.pivot = pivot;
+                }
+                self.recalculate_view();
+            }
+        }
+        self.recalculate_projection();
     }
 
     // calculates the projection matrix from the camera's perspective
     fn recalculate_projection(&mut self) {
         let aspect_ratio = self.window_size.x as f32 / self.window_size.y as f32;
+        if self.is_orthographic() {
+            let half_width = self.ortho_size * aspect_ratio;
+            // things behind the camera are drawn too, so zooming in never cuts anything off
+            self.projection = Mat4::orthographic_opengl(-half_width, half_width, -self.ortho_size, self.ortho_size, -self.far, self.far);
+            return;
+        }
         self.projection = Mat4::perspective_opengl(degrees_to_radians(self.fov), self.near, self.far, aspect_ratio);
     }
 
@@ -133,6 +380,14 @@ impl Camera {
         self.recalculate_view();
     }
 
+    pub fn get_pivot(&self) -> Vec3 {
+        self.pivot
+    }
+
+    pub fn set_pivot(&mut self, pivot: Vec3) {
+        self.pivot = pivot;
+    }
+
     pub fn get_rotation(&self) -> Quaternion {
         self.rotation
     }
@@ -159,4 +414,17 @@ impl Camera {
         self.fov = fov;
         self.recalculate_projection();
     }
-}
\ No newline at end of file
+}
+
+// the yaw and pitch (in radians) that look in a direction, which must be normalised
+pub fn direction_yaw_pitch(direction: Vec3) -> (f32, f32) {
+    ((-direction.x).atan2(direction.z), direction.y.clamp(-1.0, 1.0).asin())
+}
+
+// built by hand rather than with from_euler_angles_zyx so that the view direction comes out as
+// (-cos(pitch) sin(yaw), sin(pitch), cos(pitch) cos(yaw)), which look_in_direction and orbit rely on
+pub fn yaw_pitch_rotation(yaw: f32, pitch: f32) -> Quaternion {
+    let pitch = Quaternion::new((pitch * 0.5).sin(), 0.0, 0.0, (pitch * 0.5).cos());
+    let yaw = Quaternion::new(0.0, (yaw * 0.5).sin(), 0.0, (yaw * 0.5).cos());
+    pitch * yaw
+}
@@ -0,0 +1,413 @@
+use std::ffi::CString;
+use gfx_maths::{Mat4, Quaternion, Vec3, Vec4};
+use glad_gl::gl::*;
+use crate::renderer::camera::Camera;
+use crate::renderer::grid::Grid;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::raycasting::{length, xyz, Ray};
+use crate::worldmachine::WorldMachine;
+
+// the translate, rotate and scale manipulators drawn over the selected entity. they're drawn as lines in world space,
+// sized so that they stay about the same size on screen, and on top of everything else. dragging one only changes
+// the entity in memory (see WorldMachine::preview_transform), the change is committed as one edit when the drag ends
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+// the parameters of a Transform component
+#[derive(Clone, Copy, Debug)]
+pub struct GizmoTransform {
+    pub position: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+// how big the gizmo is compared to its distance from the camera
+const GIZMO_SCREEN_SIZE: f32 = 0.15;
+// how close (compared to the gizmo's size) the cursor has to be to a handle to grab it
+const GIZMO_GRAB_DISTANCE: f32 = 0.08;
+const RING_SEGMENTS: usize = 64;
+const GIZMO_RAY_DISTANCE: f32 = 10000.0;
+
+const AXIS_COLOURS: [Vec3; 3] = [Vec3 { x: 0.9, y: 0.2, z: 0.2 }, Vec3 { x: 0.2, y: 0.9, z: 0.2 }, Vec3 { x: 0.2, y: 0.4, z: 1.0 }];
+const ACTIVE_COLOUR: Vec3 = Vec3 { x: 1.0, y: 0.9, z: 0.1 };
+
+struct Drag {
+    uid: u64,
+    axis: usize,
+    start: GizmoTransform,
+    axes: [Vec3; 3],
+    size: f32,
+    // for translating and scaling, how far along the axis the cursor started. for rotating, the direction from the
+    // centre to where the cursor started on the ring's plane
+    start_offset: f32,
+    start_direction: Vec3,
+}
+
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    drag: Option<Drag>,
+    vao: GLuint,
+    vbo: GLuint,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            drag: None,
+            vao: 0,
+            vbo: 0,
+        }
+    }
+}
+
+fn normalise(v: Vec3) -> Vec3 {
+    v / length(v).max(f32::EPSILON)
+}
+
+// any unit vector at right angles to v
+fn perpendicular(v: Vec3) -> Vec3 {
+    let other = if v.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+    normalise(v.cross(other))
+}
+
+// a rotation of angle radians around axis
+fn axis_angle(axis: Vec3, angle: f32) -> Quaternion {
+    let axis = normalise(axis);
+    let s = (angle * 0.5).sin();
+    Quaternion::new(axis.x * s, axis.y * s, axis.z * s, (angle * 0.5).cos())
+}
+
+// the closest points between the ray and the line through origin along axis, as (distance along the axis, distance
+// along the ray, distance between them). None if they're parallel
+fn closest_to_axis(ray_origin: Vec3, ray_direction: Vec3, origin: Vec3, axis: Vec3) -> Option<(f32, f32, f32)> {
+    let w = origin - ray_origin;
+    let b = axis.dot(ray_direction);
+    let d = axis.dot(w);
+    let e = ray_direction.dot(w);
+    let denominator = 1.0 - b * b;
+    if denominator.abs() < 1e-6 {
+        return None;
+    }
+    let along_axis = (b * e - d) / denominator;
+    let along_ray = (e - b * d) / denominator;
+    let between = length((origin + axis * along_axis) - (ray_origin + ray_direction * along_ray));
+    Some((along_axis, along_ray, between))
+}
+
+fn ray_plane(ray_origin: Vec3, ray_direction: Vec3, point: Vec3, normal: Vec3) -> Option<Vec3> {
+    let denominator = normal.dot(ray_direction);
+    if denominator.abs() < 1e-6 {
+        return None;
+    }
+    let distance = normal.dot(point - ray_origin) / denominator;
+    if distance < 0.0 {
+        return None;
+    }
+    Some(ray_origin + ray_direction * distance)
+}
+
+fn ray_direction(ray: &Ray) -> Vec3 {
+    xyz(ray.direction)
+}
+
+impl Gizmo {
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    // scaling always happens along the entity's own axes
+    fn axes(&self, transform: &GizmoTransform) -> [Vec3; 3] {
+        if self.space == GizmoSpace::World && self.mode != GizmoMode::Scale {
+            return [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+        }
+        let rotation = Mat4::rotate(transform.rotation);
+        [
+            normalise(xyz(rotation * Vec4::new(1.0, 0.0, 0.0, 0.0))),
+            normalise(xyz(rotation * Vec4::new(0.0, 1.0, 0.0, 0.0))),
+            normalise(xyz(rotation * Vec4::new(0.0, 0.0, 1.0, 0.0))),
+        ]
+    }
+
+    fn size(camera: &Camera, center: Vec3) -> f32 {
+        (camera.visible_half_height(length(center - camera.get_eye())) * GIZMO_SCREEN_SIZE).max(0.01)
+    }
+
+    // the axis of the handle under the ray, if there is one
+    pub fn hit_test(&self, ray: &Ray, transform: &GizmoTransform, camera: &Camera) -> Option<usize> {
+        let center = transform.position;
+        let size = Self::size(camera, center);
+        let axes = self.axes(transform);
+        let direction = ray_direction(ray);
+        let mut best: Option<(usize, f32)> = None;
+        for (i, axis) in axes.iter().enumerate() {
+            let distance = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    match closest_to_axis(ray.origin, direction, center, *axis) {
+                        Some((along_axis, along_ray, between)) if along_ray > 0.0 && (0.0..=size).contains(&along_axis) => {
+                            Some(between)
+                        }
+                        _ => None,
+                    }
+                }
+                GizmoMode::Rotate => {
+                    ray_plane(ray.origin, direction, center, *axis).map(|point| (length(point - center) - size).abs())
+                }
+            };
+            if let Some(distance) = distance {
+                if distance < size * GIZMO_GRAB_DISTANCE && best.map_or(true, |(_, best)| distance < best) {
+                    best = Some((i, distance));
+                }
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    // starts dragging if there's a handle under the ray, returns whether there was
+    pub fn begin_drag(&mut self, uid: u64, ray: &Ray, transform: GizmoTransform, camera: &Camera) -> bool {
+        let axis = match self.hit_test(ray, &transform, camera) {
+            Some(axis) => axis,
+            None => return false,
+        };
+        let axes = self.axes(&transform);
+        let size = Self::size(camera, transform.position);
+        let direction = ray_direction(ray);
+        let (start_offset, start_direction) = match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                match closest_to_axis(ray.origin, direction, transform.position, axes[axis]) {
+                    Some((along_axis, _, _)) => (along_axis, Vec3::new(0.0, 0.0, 0.0)),
+                    None => return false,
+                }
+            }
+            GizmoMode::Rotate => {
+                match ray_plane(ray.origin, direction, transform.position, axes[axis]) {
+                    Some(point) => (0.0, normalise(point - transform.position)),
+                    None => return false,
+                }
+            }
+        };
+        self.drag = Some(Drag { uid, axis, start: transform, axes, size, start_offset, start_direction });
+        true
+    }
+
+    // where the entity being dragged should be now that the cursor is along the given ray, snapped if the grid says so
+    pub fn drag(&self, ray: &Ray, grid: &Grid) -> Option<(u64, GizmoTransform)> {
+        let drag = self.drag.as_ref()?;
+        let axis = drag.axes[drag.axis];
+        let direction = ray_direction(ray);
+        let mut transform = drag.start;
+        match self.mode {
+            GizmoMode::Translate => {
+                let (along_axis, _, _) = closest_to_axis(ray.origin, direction, drag.start.position, axis)?;
+                let distance = along_axis - drag.start_offset;
+                if self.space == GizmoSpace::World {
+                    // snap the coordinate itself, so things end up on the grid
+                    transform.position = drag.start.position + axis * distance;
+                    match drag.axis {
+                        0 => transform.position.x = grid.snap_distance(transform.position.x),
+                        1 => transform.position.y = grid.snap_distance(transform.position.y),
+                        _ => transform.position.z = grid.snap_distance(transform.position.z),
+                    }
+                } else {
+                    // the local axes don't line up with the grid, so snap how far it moved instead
+                    transform.position = drag.start.position + axis * grid.snap_distance(distance);
+                }
+            }
+            GizmoMode::Scale => {
+                // dragging the length of the handle adds one to the scale
+                let (along_axis, _, _) = closest_to_axis(ray.origin, direction, drag.start.position, axis)?;
+                let amount = (along_axis - drag.start_offset) / drag.size;
+                match drag.axis {
+                    0 => transform.scale.x = grid.snap_scale(transform.scale.x + amount),
+                    1 => transform.scale.y = grid.snap_scale(transform.scale.y + amount),
+                    _ => transform.scale.z = grid.snap_scale(transform.scale.z + amount),
+                }
+            }
+            GizmoMode::Rotate => {
+                let point = ray_plane(ray.origin, direction, drag.start.position, axis)?;
+                let current = normalise(point - drag.start.position);
+                let angle = axis.dot(drag.start_direction.cross(current)).atan2(drag.start_direction.dot(current));
+                let angle = grid.snap_angle(angle);
+                transform.rotation = axis_angle(axis, angle) * drag.start.rotation;
+            }
+        }
+        Some((drag.uid, transform))
+    }
+
+    // stops dragging, returning where the entity ended up so it can be committed
+    pub fn end_drag(&mut self, ray: Option<&Ray>, grid: &Grid) -> Option<(u64, GizmoTransform)> {
+        let result = match ray {
+            Some(ray) => self.drag(ray, grid),
+            None => None,
+        };
+        let drag = self.drag.take()?;
+        Some(result.unwrap_or((drag.uid, drag.start)))
+    }
+
+    // world space lines as (position, colour) pairs, two vertices per line
+    fn lines(&self, transform: &GizmoTransform, camera: &Camera) -> Vec<(Vec3, Vec3)> {
+        let center = transform.position;
+        let size = Self::size(camera, center);
+        let axes = self.axes(transform);
+        let mut lines = Vec::new();
+        let mut line = |a: Vec3, b: Vec3, colour: Vec3| {
+            lines.push((a, colour));
+            lines.push((b, colour));
+        };
+        for (i, axis) in axes.iter().enumerate() {
+            let colour = match &self.drag {
+                Some(drag) if drag.axis == i => ACTIVE_COLOUR,
+                _ => AXIS_COLOURS[i],
+            };
+            let side = perpendicular(*axis);
+            let up = axis.cross(side);
+            let tip = center + *axis * size;
+            match self.mode {
+                GizmoMode::Translate => {
+                    line(center, tip, colour);
+                    let base = tip - *axis * (size * 0.2);
+                    for offset in [side, -side, up, -up] {
+                        line(tip, base + offset * (size * 0.07), colour);
+                    }
+                }
+                GizmoMode::Scale => {
+                    line(center, tip, colour);
+                    // a little box on the end
+                    let half = size * 0.05;
+                    let corner = |x: f32, y: f32, z: f32| tip + *axis * (x * half) + side * (y * half) + up * (z * half);
+                    for (a, b) in [
+                        ((-1.0, -1.0, -1.0), (1.0, -1.0, -1.0)), ((-1.0, 1.0, -1.0), (1.0, 1.0, -1.0)),
+                        ((-1.0, -1.0, 1.0), (1.0, -1.0, 1.0)), ((-1.0, 1.0, 1.0), (1.0, 1.0, 1.0)),
+                        ((-1.0, -1.0, -1.0), (-1.0, 1.0, -1.0)), ((1.0, -1.0, -1.0), (1.0, 1.0, -1.0)),
+                        ((-1.0, -1.0, 1.0), (-1.0, 1.0, 1.0)), ((1.0, -1.0, 1.0), (1.0, 1.0, 1.0)),
+                        ((-1.0, -1.0, -1.0), (-1.0, -1.0, 1.0)), ((1.0, -1.0, -1.0), (1.0, -1.0, 1.0)),
+                        ((-1.0, 1.0, -1.0), (-1.0, 1.0, 1.0)), ((1.0, 1.0, -1.0), (1.0, 1.0, 1.0)),
+                    ] {
+                        line(corner(a.0, a.1, a.2), corner(b.0, b.1, b.2), colour);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let point = |i: usize| {
+                        let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        center + side * (angle.cos() * size) + up * (angle.sin() * size)
+                    };
+                    for segment in 0..RING_SEGMENTS {
+                        line(point(segment), point(segment + 1), colour);
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl H2eckRenderer {
+    // the selected entity's uid and transform, and a ray through the given pixel (bottom left origin, like pick)
+    fn gizmo_target(&self, worldmachine: &WorldMachine, x: f32, y: f32) -> Option<(u64, GizmoTransform, Ray)> {
+        let uid = worldmachine.selected_entity()?;
+        let transform = worldmachine.entity_transform(uid)?;
+        let ray = self.viewport_ray(x, y, GIZMO_RAY_DISTANCE)?;
+        Some((uid, transform, ray))
+    }
+
+    pub fn gizmo_under_cursor(&self, worldmachine: &WorldMachine, x: f32, y: f32) -> bool {
+        match (self.gizmo_target(worldmachine, x, y), self.camera.as_ref()) {
+            (Some((_, transform, ray)), Some(camera)) => self.gizmo.hit_test(&ray, &transform, camera).is_some(),
+            _ => false,
+        }
+    }
+
+    // returns whether a gizmo handle was grabbed
+    pub fn begin_gizmo_drag(&mut self, worldmachine: &WorldMachine, x: f32, y: f32) -> bool {
+        let (uid, transform, ray) = match self.gizmo_target(worldmachine, x, y) {
+            Some(target) => target,
+            None => return false,
+        };
+        let camera = match self.camera.as_ref() {
+            Some(camera) => camera,
+            None => return false,
+        };
+        self.gizmo.begin_drag(uid, &ray, transform, camera)
+    }
+
+    pub fn drag_gizmo(&mut self, worldmachine: &mut WorldMachine, x: f32, y: f32) {
+        if let Some(ray) = self.viewport_ray(x, y, GIZMO_RAY_DISTANCE) {
+            if let Some((uid, transform)) = self.gizmo.drag(&ray, &self.grid) {
+                worldmachine.preview_transform(uid, transform);
+            }
+        }
+    }
+
+    // commits the drag, returning the uid of the entity that was changed
+    pub fn end_gizmo_drag(&mut self, worldmachine: &mut WorldMachine, x: f32, y: f32) -> Option<u64> {
+        let ray = self.viewport_ray(x, y, GIZMO_RAY_DISTANCE);
+        let (uid, transform) = self.gizmo.end_drag(ray.as_ref(), &self.grid)?;
+        worldmachine.commit_transform(uid, transform);
+        Some(uid)
+    }
+
+    // draws the gizmo over the selected entity (if it has a transform), should be called after the scene is drawn
+    pub fn render_gizmo(&mut self, worldmachine: &WorldMachine) {
+        let transform = match worldmachine.selected_entity().and_then(|uid| worldmachine.entity_transform(uid)) {
+            Some(transform) => transform,
+            None => return,
+        };
+        let shader = match self.shaders.as_ref().unwrap().get("gizmo") {
+            Some(shader) => shader.clone(),
+            None => return,
+        };
+        let camera = match self.camera.as_ref() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let mvp = camera.get_projection() * camera.get_view();
+        let vertices = self.gizmo.lines(&transform, camera).iter()
+            .flat_map(|(position, colour)| [position.x, position.y, position.z, colour.x, colour.y, colour.z])
+            .collect::<Vec<f32>>();
+
+        unsafe {
+            if self.gizmo.vao == 0 {
+                GenVertexArrays(1, &mut self.gizmo.vao);
+                GenBuffers(1, &mut self.gizmo.vbo);
+                BindVertexArray(self.gizmo.vao);
+                BindBuffer(ARRAY_BUFFER, self.gizmo.vbo);
+                let stride = (6 * std::mem::size_of::<GLfloat>()) as GLsizei;
+                VertexAttribPointer(0, 3, FLOAT, FALSE as GLboolean, stride, std::ptr::null());
+                EnableVertexAttribArray(0);
+                VertexAttribPointer(1, 3, FLOAT, FALSE as GLboolean, stride, (3 * std::mem::size_of::<GLfloat>()) as *const GLvoid);
+                EnableVertexAttribArray(1);
+            }
+            BindVertexArray(self.gizmo.vao);
+            BindBuffer(ARRAY_BUFFER, self.gizmo.vbo);
+            BufferData(ARRAY_BUFFER, (vertices.len() * std::mem::size_of::<GLfloat>()) as GLsizeiptr, vertices.as_ptr() as *const GLvoid, STREAM_DRAW);
+
+            UseProgram(shader.program);
+            self.current_shader = Some(shader.name.clone());
+            UniformMatrix4fv(GetUniformLocation(shader.program, CString::new("u_mvp").unwrap().as_ptr()), 1, FALSE as GLboolean, mvp.as_ptr());
+
+            // always on top
+            Disable(DEPTH_TEST);
+            DrawArrays(LINES, 0, (vertices.len() / 6) as GLsizei);
+            Enable(DEPTH_TEST);
+
+            let mut error = GetError();
+            while error != NO_ERROR {
+                error!("OpenGL error while rendering gizmo: {}", error);
+                error = GetError();
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::ffi::CString;
+use gfx_maths::Vec3;
+use glad_gl::gl::*;
+use crate::renderer::camera::CameraView;
+use crate::renderer::H2eckRenderer;
+use crate::settings;
+
+// the reference grid and snapping to it. the grid lies on the ground (y = 0), or facing the camera in the front and
+// side views. the spacing and the snapping increments come from the preferences, whether the grid is shown and
+// whether snapping is on are toggled from the editor's toolbar
+
+// how many lines are drawn either side of the camera
+const GRID_LINES: i32 = 100;
+// every this many lines is drawn brighter
+const GRID_MAJOR_EVERY: i32 = 10;
+
+const MINOR_COLOUR: Vec3 = Vec3 { x: 0.3, y: 0.3, z: 0.3 };
+const MAJOR_COLOUR: Vec3 = Vec3 { x: 0.5, y: 0.5, z: 0.5 };
+const AXIS_COLOURS: [Vec3; 3] = [Vec3 { x: 0.7, y: 0.2, z: 0.2 }, Vec3 { x: 0.2, y: 0.7, z: 0.2 }, Vec3 { x: 0.2, y: 0.3, z: 0.8 }];
+
+pub struct Grid {
+    pub visible: bool,
+    pub snap: bool,
+    vao: GLuint,
+    vbo: GLuint,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            snap: false,
+            vao: 0,
+            vbo: 0,
+        }
+    }
+}
+
+fn component(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// the line at index along the grid, which runs along axis
+fn line_colour(index: i32, axis: usize) -> Vec3 {
+    if index == 0 {
+        AXIS_COLOURS[axis]
+    } else if index % GRID_MAJOR_EVERY == 0 {
+        MAJOR_COLOUR
+    } else {
+        MINOR_COLOUR
+    }
+}
+
+fn snap_to(value: f32, increment: f32) -> f32 {
+    if increment <= 0.0 {
+        return value;
+    }
+    (value / increment).round() * increment
+}
+
+impl Grid {
+    pub fn size(&self) -> f32 {
+        settings::grid_size() as f32
+    }
+
+    // a distance or coordinate, snapped to the grid if snapping is on
+    pub fn snap_distance(&self, distance: f32) -> f32 {
+        if !self.snap {
+            return distance;
+        }
+        snap_to(distance, self.size())
+    }
+
+    pub fn snap_position(&self, position: Vec3) -> Vec3 {
+        Vec3::new(self.snap_distance(position.x), self.snap_distance(position.y), self.snap_distance(position.z))
+    }
+
+    // radians, snapped to the rotation step (which is in degrees)
+    pub fn snap_angle(&self, angle: f32) -> f32 {
+        if !self.snap {
+            return angle;
+        }
+        snap_to(angle, (settings::rotation_snap() as f32).to_radians())
+    }
+
+    pub fn snap_scale(&self, scale: f32) -> f32 {
+        if !self.snap {
+            return scale;
+        }
+        snap_to(scale, settings::scale_snap() as f32)
+    }
+
+    // world space lines as (position, colour) pairs, centred on the grid line nearest the camera so that the grid
+    // seems to go on forever. the grid lies across the two axes that aren't normal
+    fn lines(&self, eye: Vec3, normal: usize) -> Vec<(Vec3, Vec3)> {
+        let (u, v) = match normal {
+            0 => (1, 2),
+            2 => (0, 1),
+            _ => (0, 2),
+        };
+        let point = |a: f32, b: f32| {
+            let mut coordinates = [0.0; 3];
+            coordinates[u] = a;
+            coordinates[v] = b;
+            Vec3::new(coordinates[0], coordinates[1], coordinates[2])
+        };
+        let size = self.size().max(0.001);
+        let center_u = (component(eye, u) / size).round() as i32;
+        let center_v = (component(eye, v) / size).round() as i32;
+        let extent = GRID_LINES as f32 * size;
+        let mut lines = Vec::new();
+        for i in -GRID_LINES..=GRID_LINES {
+            // lines running along v, at a fixed u
+            let line_u = center_u + i;
+            let colour = line_colour(line_u, v);
+            let a = line_u as f32 * size;
+            let b = center_v as f32 * size;
+            lines.push((point(a, b - extent), colour));
+            lines.push((point(a, b + extent), colour));
+
+            // lines running along u, at a fixed v
+            let line_v = center_v + i;
+            let colour = line_colour(line_v, u);
+            let a = center_u as f32 * size;
+            let b = line_v as f32 * size;
+            lines.push((point(a - extent, b), colour));
+            lines.push((point(a + extent, b), colour));
+        }
+        lines
+    }
+}
+
+impl H2eckRenderer {
+    // draws the ground grid, should be called after the scene is drawn so that things in the way hide it
+    pub fn render_grid(&mut self) {
+        if !self.grid.visible {
+            return;
+        }
+        let shader = match self.shaders.as_ref().unwrap().get("gizmo") {
+            Some(shader) => shader.clone(),
+            None => return,
+        };
+        let camera = match self.camera.as_ref() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let mvp = camera.get_projection() * camera.get_view();
+        // face the camera in the front and side views
+        let normal = match camera.get_camera_view() {
+            CameraView::Side => 0,
+            CameraView::Front => 2,
+            _ => 1,
+        };
+        let vertices = self.grid.lines(camera.get_eye(), normal).iter()
+            .flat_map(|(position, colour)| [position.x, position.y, position.z, colour.x, colour.y, colour.z])
+            .collect::<Vec<f32>>();
+
+        unsafe {
+            if self.grid.vao == 0 {
+                GenVertexArrays(1, &mut self.grid.vao);
+                GenBuffers(1, &mut self.grid.vbo);
+                BindVertexArray(self.grid.vao);
+                BindBuffer(ARRAY_BUFFER, self.grid.vbo);
+                let stride = (6 * std::mem::size_of::<GLfloat>()) as GLsizei;
+                VertexAttribPointer(0, 3, FLOAT, FALSE as GLboolean, stride, std::ptr::null());
+                EnableVertexAttribArray(0);
+                VertexAttribPointer(1, 3, FLOAT, FALSE as GLboolean, stride, (3 * std::mem::size_of::<GLfloat>()) as *const GLvoid);
+                EnableVertexAttribArray(1);
+            }
+            BindVertexArray(self.grid.vao);
+            BindBuffer(ARRAY_BUFFER, self.grid.vbo);
+            BufferData(ARRAY_BUFFER, (vertices.len() * std::mem::size_of::<GLfloat>()) as GLsizeiptr, vertices.as_ptr() as *const GLvoid, STREAM_DRAW);
+
+            UseProgram(shader.program);
+            self.current_shader = Some(shader.name.clone());
+            UniformMatrix4fv(GetUniformLocation(shader.program, CString::new("u_mvp").unwrap().as_ptr()), 1, FALSE as GLboolean, mvp.as_ptr());
+
+            // test against the scene, but don't hide things drawn after us
+            DepthMask(FALSE as GLboolean);
+            DrawArrays(LINES, 0, (vertices.len() / 6) as GLsizei);
+            DepthMask(TRUE as GLboolean);
+
+            let mut error = GetError();
+            while error != NO_ERROR {
+                error!("OpenGL error while rendering grid: {}", error);
+                error = GetError();
+            }
+        }
+    }
+}
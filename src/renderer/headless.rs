@@ -0,0 +1,102 @@
+use std::ffi::c_void;
+use glad_gl::gl;
+use khronos_egl as egl;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::thumbnails::ThumbnailSource;
+use crate::settings;
+use crate::worldmachine::library;
+
+// renders thumbnails without a window, for build machines and the like. this uses mesa's surfaceless egl platform,
+// so it works without a display server, and falls back to software rendering when there's no gpu
+
+const EGL_PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+struct HeadlessContext {
+    egl: egl::DynamicInstance<egl::EGL1_5>,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
+
+fn create_context() -> Result<HeadlessContext, String> {
+    // llvmpipe is fine for thumbnails, and means this works on machines without a gpu.
+    // set LIBGL_ALWAYS_SOFTWARE=0 to use the hardware driver instead
+    if std::env::var_os("LIBGL_ALWAYS_SOFTWARE").is_none() {
+        std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+    }
+
+    let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+        .map_err(|e| format!("failed to load libEGL: {}", e))?;
+    let display = unsafe { egl.get_platform_display(EGL_PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE]) }
+        .map_err(|e| format!("failed to get surfaceless egl display: {}", e))?;
+    egl.initialize(display).map_err(|e| format!("failed to initialise egl: {}", e))?;
+    egl.bind_api(egl::OPENGL_API).map_err(|e| format!("failed to bind opengl: {}", e))?;
+
+    let config_attributes = [
+        egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+        egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+        egl::RED_SIZE, 8,
+        egl::GREEN_SIZE, 8,
+        egl::BLUE_SIZE, 8,
+        egl::ALPHA_SIZE, 8,
+        egl::DEPTH_SIZE, 24,
+        egl::NONE,
+    ];
+    let config = egl.choose_first_config(display, &config_attributes)
+        .map_err(|e| format!("failed to choose egl config: {}", e))?
+        .ok_or("no suitable egl config")?;
+
+    let context_attributes = [
+        egl::CONTEXT_MAJOR_VERSION, 3,
+        egl::CONTEXT_MINOR_VERSION, 3,
+        egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+        egl::NONE,
+    ];
+    let context = egl.create_context(display, config, None, &context_attributes)
+        .map_err(|e| format!("failed to create opengl context: {}", e))?;
+    // rendering only ever goes into our own framebuffers, so no surface is needed
+    egl.make_current(display, None, None, Some(context))
+        .map_err(|e| format!("failed to make opengl context current: {}", e))?;
+
+    gl::load(|s| {
+        egl.get_proc_address(s).map(|f| f as *const c_void).unwrap_or(std::ptr::null())
+    });
+
+    Ok(HeadlessContext { egl, display, context })
+}
+
+// renders thumbnails for every edef and model in the data folder, returns how many failed
+pub fn generate_thumbnails() -> Result<usize, String> {
+    let _context = create_context()?;
+
+    let mut renderer = H2eckRenderer::default();
+    renderer.initialise(1280, 720);
+    renderer.initialised = true;
+
+    let data_dir = settings::data_location();
+    let entities_dir = format!("{}/entities", data_dir);
+    let mut sources = library::list_entity_defs(&entities_dir).into_iter()
+        .map(|entry| ThumbnailSource::EntityDef { path: library::entity_def_path(&entities_dir, &entry.name), name: entry.name })
+        .collect::<Vec<_>>();
+    sources.extend(library::list_meshes(&format!("{}/models", data_dir)).into_iter()
+        .map(|(name, path)| ThumbnailSource::Mesh { name, path }));
+
+    let mut failed = 0;
+    for source in sources.iter() {
+        match renderer.thumbnail(source) {
+            Ok(path) => info!("{} -> {}", source.name(), path.display()),
+            Err(e) => {
+                error!("failed to render thumbnail for {}: {}", source.name(), e);
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
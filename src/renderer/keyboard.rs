@@ -1,8 +1,12 @@
+// which of the camera keys are held down, see keymap.rs for what they're bound to
 pub struct KeyboardManager {
     pub forward: bool,
     pub backward: bool,
     pub left: bool,
     pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub fast: bool,
 }
 
 impl Default for KeyboardManager {
@@ -12,6 +16,9 @@ impl Default for KeyboardManager {
             backward: false,
             left: false,
             right: false,
+            up: false,
+            down: false,
+            fast: false,
         }
     }
 }
\ No newline at end of file
@@ -1,14 +1,63 @@
+use std::ffi::CString;
 use gfx_maths::*;
+use glad_gl::gl::*;
+use crate::renderer::{H2eckRenderer, MAX_LIGHTS};
+use crate::renderer::shadows::{MAX_SHADOW_LAYERS, SHADOW_TEXTURE_UNIT};
 use crate::worldmachine::components::COMPONENT_TYPE_LIGHT;
 use crate::worldmachine::ecs::{Component, ParameterValue};
 
+// the "type" parameter of a light component. the numbers match the LIGHT_* defines in the shaders
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    Point,
+    Directional, // shines everywhere in its direction, like the sun. its position is ignored
+    Spot,
+}
+
+impl LightKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "point" => Some(LightKind::Point),
+            "directional" => Some(LightKind::Directional),
+            "spot" => Some(LightKind::Spot),
+            _ => None,
+        }
+    }
+
+    pub fn shader_id(&self) -> i32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Spot => 2,
+        }
+    }
+}
+
 pub struct Light {
     pub position: Vec3,
     pub color: Vec3,
     pub intensity: f64,
+    pub kind: LightKind,
+    pub direction: Vec3, // which way directional and spot lights shine
+    pub angle: f64, // degrees from a spot light's direction to the edge of its cone
+    pub casts_shadows: bool,
+    pub shadow_layer: Option<usize>, // set by render_shadow_maps if this light got a shadow map
 }
 
 impl Light {
+    pub fn new(position: Vec3, color: Vec3, intensity: f64) -> Light {
+        Light {
+            position,
+            color,
+            intensity,
+            kind: LightKind::Point,
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            angle: 30.0,
+            casts_shadows: false,
+            shadow_layer: None,
+        }
+    }
+
     pub fn from_component(component: Component) -> Option<Light> {
         if component.get_type() == COMPONENT_TYPE_LIGHT.clone() {
             let position = component.get_parameter("position").unwrap();
@@ -26,13 +75,73 @@ impl Light {
                 ParameterValue::Float(intensity) => intensity,
                 _ => panic!("Invalid parameter type for intensity"),
             };
-            Some(Light {
-                position,
-                color,
-                intensity,
-            })
+            let mut light = Light::new(position, color, intensity);
+            light.read_options(&component);
+            Some(light)
         } else {
             None
         }
     }
-}
\ No newline at end of file
+
+    // lights in older maps don't have these parameters, so anything missing is left as it was (a point light that
+    // doesn't cast shadows)
+    pub fn read_options(&mut self, component: &Component) {
+        if let Some(ParameterValue::String(name)) = component.get_parameter("type").map(|p| &p.value) {
+            // anything else stays a point light
+            if let Some(kind) = LightKind::from_name(&name.to_lowercase()) {
+                self.kind = kind;
+            }
+        }
+        if let Some(ParameterValue::Vec3(direction)) = component.get_parameter("direction").map(|p| &p.value) {
+            if direction.magnitude() > f32::EPSILON {
+                self.direction = *direction * (1.0 / direction.magnitude());
+            }
+        }
+        if let Some(ParameterValue::Float(angle)) = component.get_parameter("angle").map(|p| &p.value) {
+            self.angle = angle.clamp(1.0, 89.0);
+        }
+        if let Some(ParameterValue::Bool(casts_shadows)) = component.get_parameter("shadows").map(|p| &p.value) {
+            self.casts_shadows = *casts_shadows;
+        }
+    }
+}
+
+impl H2eckRenderer {
+    // sends the lights and their shadow maps to a shader that uses them (basic and terrain), which must be in use
+    pub fn send_lights_to_shader(&self, program: GLuint) {
+        unsafe {
+            let light_count = self.lights.len();
+            let light_count = if light_count > MAX_LIGHTS { MAX_LIGHTS } else { light_count };
+            let light_count_loc = GetUniformLocation(program, CString::new("u_light_count").unwrap().as_ptr());
+            Uniform1i(light_count_loc, light_count as i32);
+            for (i, light) in self.lights.iter().enumerate() {
+                if i >= MAX_LIGHTS { break; }
+                let light_pos = GetUniformLocation(program, CString::new(format!("u_lights[{}].position", i)).unwrap().as_ptr());
+                let light_color = GetUniformLocation(program, CString::new(format!("u_lights[{}].colour", i)).unwrap().as_ptr());
+                let light_intensity = GetUniformLocation(program, CString::new(format!("u_lights[{}].intensity", i)).unwrap().as_ptr());
+                let light_kind = GetUniformLocation(program, CString::new(format!("u_lights[{}].kind", i)).unwrap().as_ptr());
+                let light_direction = GetUniformLocation(program, CString::new(format!("u_lights[{}].direction", i)).unwrap().as_ptr());
+                let light_cos_angle = GetUniformLocation(program, CString::new(format!("u_lights[{}].cos_angle", i)).unwrap().as_ptr());
+                let light_shadow_layer = GetUniformLocation(program, CString::new(format!("u_lights[{}].shadow_layer", i)).unwrap().as_ptr());
+
+                Uniform3f(light_pos, light.position.x, light.position.y, light.position.z);
+                Uniform3f(light_color, light.color.x, light.color.y, light.color.z);
+                Uniform1f(light_intensity, light.intensity as f32);
+                Uniform1i(light_kind, light.kind.shader_id());
+                Uniform3f(light_direction, light.direction.x, light.direction.y, light.direction.z);
+                Uniform1f(light_cos_angle, light.angle.to_radians().cos() as f32);
+                Uniform1i(light_shadow_layer, light.shadow_layer.map(|layer| layer as i32).unwrap_or(-1));
+            }
+
+            // and the shadow maps, which are all layers of the one texture
+            for (i, matrix) in self.shadow_matrices.iter().enumerate().take(MAX_SHADOW_LAYERS) {
+                let matrix_loc = GetUniformLocation(program, CString::new(format!("u_shadow_matrices[{}]", i)).unwrap().as_ptr());
+                UniformMatrix4fv(matrix_loc, 1, FALSE as GLboolean, matrix.as_ptr());
+            }
+            ActiveTexture(TEXTURE0 + SHADOW_TEXTURE_UNIT);
+            BindTexture(TEXTURE_2D_ARRAY, self.framebuffers.depthbuffer_texture as GLuint);
+            Uniform1i(GetUniformLocation(program, CString::new("u_shadow_maps").unwrap().as_ptr()), SHADOW_TEXTURE_UNIT as i32);
+            ActiveTexture(TEXTURE0);
+        }
+    }
+}
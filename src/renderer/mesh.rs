@@ -2,9 +2,11 @@ use std::ffi::CString;
 use std::ptr::null;
 use gfx_maths::*;
 use glad_gl::gl::*;
-use crate::renderer::{H2eckRenderer, helpers, MAX_LIGHTS};
+use crate::renderer::{H2eckRenderer, helpers};
+use crate::renderer::raycasting::MeshGeometry;
 use crate::renderer::shader::Shader;
 use crate::renderer::texture::Texture;
+use crate::renderer::viewports::ShadingMode;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Mesh {
@@ -61,6 +63,7 @@ impl Mesh {
         let mut indices_array = Vec::new();
         let mut uvs_array = Vec::new();
         let mut normals_array = Vec::new();
+        let mut tangents_array = Vec::new();
         for primitive in mesh.primitives() {
             // get the vertex positions
             let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
@@ -80,6 +83,12 @@ impl Mesh {
             let normals = reader.read_normals().ok_or(MeshError::MeshComponentNotFound(MeshComponent::SourceMap))?;
             let normals = normals.collect::<Vec<_>>();
 
+            // get the tangents, for normal mapping. most exporters can write them, but work them out if they didn't
+            let tangents = match reader.read_tangents() {
+                Some(tangents) => tangents.collect::<Vec<_>>(),
+                None => generate_tangents(&positions, &tex_coords, &normals, &indices),
+            };
+
             // add the vertices (with each grouping of three f32s as three separate f32s)
             vertices_array.extend(positions.iter().flat_map(|v| vec![v[0], v[1], v[2]]));
 
@@ -91,6 +100,9 @@ impl Mesh {
 
             // add the normals (with each grouping of three f32s as three separate f32s)
             normals_array.extend(normals.iter().flat_map(|v| vec![v[0], v[1], v[2]]));
+
+            // add the tangents (four f32s each, w is which way the bitangent points)
+            tangents_array.extend(tangents.iter().flat_map(|v| vec![v[0], v[1], v[2], v[3]]));
         }
 
         // get the u32 data from the mesh
@@ -137,6 +149,13 @@ impl Mesh {
             VertexAttribPointer(normal as GLuint, 3, FLOAT, TRUE as GLboolean, 0, null());
             EnableVertexAttribArray(2);
 
+            // tangents, always at location 3 so that shaders without them (like terrain's) can still use the mesh
+            let mut tangentbo = 0 as GLuint;
+            GenBuffers(1, &mut tangentbo);
+            BindBuffer(ARRAY_BUFFER, tangentbo);
+            BufferData(ARRAY_BUFFER, (tangents_array.len() * std::mem::size_of::<GLfloat>()) as GLsizeiptr, tangents_array.as_ptr() as *const GLvoid, STATIC_DRAW);
+            VertexAttribPointer(3, 4, FLOAT, FALSE as GLboolean, 0, null());
+            EnableVertexAttribArray(3);
 
             // now the indices
             GenBuffers(1, &mut ebo);
@@ -156,8 +175,22 @@ impl Mesh {
         // calculate the bounding box
         let mut min = Vec3::new(0.0, 0.0, 0.0);
         let mut max = Vec3::new(0.0, 0.0, 0.0);
+        for (i, vertex) in vertices_array.chunks(3).enumerate() {
+            let vertex = Vec3::new(vertex[0], vertex[1], vertex[2]);
+            if i == 0 {
+                min = vertex;
+                max = vertex;
+                continue;
+            }
+            min = Vec3::new(min.x.min(vertex.x), min.y.min(vertex.y), min.z.min(vertex.z));
+            max = Vec3::new(max.x.max(vertex.x), max.y.max(vertex.y), max.z.max(vertex.z));
+        }
 
-
+        // keep the triangles around for ray picking
+        renderer.mesh_geometry.insert(vao, MeshGeometry {
+            positions: vertices_array.chunks(3).map(|v| Vec3::new(v[0], v[1], v[2])).collect(),
+            indices: indices_array.clone(),
+        });
 
         Ok(Mesh {
             position: Default::default(),
@@ -207,24 +240,17 @@ impl Mesh {
                 ActiveTexture(TEXTURE3);
                 BindTexture(TEXTURE_2D, material.normal_texture);
                 Uniform1i(material_normal, 3);
+                // simple textures (like terrain's and the default one) only have a diffuse map
+                let material_has_maps = GetUniformLocation(shader.program, CString::new("u_material.has_maps").unwrap().as_ptr());
+                Uniform1i(material_has_maps, (material.normal_texture != 0) as i32);
 
             }
 
+            // the unlit and wireframe shading modes skip lighting
+            Uniform1i(GetUniformLocation(shader.program, CString::new("u_unlit").unwrap().as_ptr()), (renderer.shading != ShadingMode::Lit) as i32);
+
             // send the lights to the shader
-            let light_count = renderer.lights.len();
-            let light_count = if light_count > MAX_LIGHTS { MAX_LIGHTS } else { light_count };
-            let light_count_loc = GetUniformLocation(shader.program, CString::new("u_light_count").unwrap().as_ptr());
-            Uniform1i(light_count_loc, light_count as i32);
-            for (i, light) in renderer.lights.iter().enumerate() {
-                if i >= MAX_LIGHTS { break; }
-                let light_pos = GetUniformLocation(shader.program, CString::new(format!("u_lights[{}].position", i)).unwrap().as_ptr());
-                let light_color = GetUniformLocation(shader.program, CString::new(format!("u_lights[{}].colour", i)).unwrap().as_ptr());
-                let light_intensity = GetUniformLocation(shader.program, CString::new(format!("u_lights[{}].intensity", i)).unwrap().as_ptr());
-
-                Uniform3f(light_pos, light.position.x, light.position.y, light.position.z);
-                Uniform3f(light_color, light.color.x, light.color.y, light.color.z);
-                Uniform1f(light_intensity, light.intensity as f32);
-            }
+            renderer.send_lights_to_shader(shader.program);
 
             // transformation time!
             let camera_projection = renderer.camera.as_mut().unwrap().get_projection();
@@ -245,11 +271,10 @@ impl Mesh {
             UniformMatrix4fv(model_loc, 1, FALSE as GLboolean, model_matrix.as_ptr());
 
             // send the camera position to the shader
+            // (the eye, as the camera's position is stored inverted)
             let camera_pos_loc = GetUniformLocation(shader.program, CString::new("u_camera_pos").unwrap().as_ptr());
-            Uniform3f(camera_pos_loc,
-                        renderer.camera.as_mut().unwrap().get_position().x,
-                        renderer.camera.as_mut().unwrap().get_position().y,
-                        renderer.camera.as_mut().unwrap().get_position().z);
+            let eye = renderer.camera.as_ref().unwrap().get_eye();
+            Uniform3f(camera_pos_loc, eye.x, eye.y, eye.z);
 
             DrawElements(TRIANGLES, self.num_indices as GLsizei, UNSIGNED_INT, null());
 
@@ -261,12 +286,78 @@ impl Mesh {
             }
         }
     }
+
+    // draws just the triangles, seen through the given projection * view matrix, for depth only passes like the
+    // shadow maps. the shader must already be in use
+    pub fn render_depth(&self, shader: &Shader, view_projection: Mat4) {
+        unsafe {
+            BindVertexArray(self.vao);
+            let mvp = view_projection * calculate_model_matrix(self.position, self.rotation, self.scale);
+            let mvp_loc = GetUniformLocation(shader.program, CString::new("u_mvp").unwrap().as_ptr());
+            UniformMatrix4fv(mvp_loc, 1, FALSE as GLboolean, mvp.as_ptr());
+            DrawElements(TRIANGLES, self.num_indices as GLsizei, UNSIGNED_INT, null());
+        }
+    }
 }
 
-fn calculate_model_matrix(position: Vec3, rotation: Quaternion, scale: Vec3) -> Mat4 {
+pub fn calculate_model_matrix(position: Vec3, rotation: Quaternion, scale: Vec3) -> Mat4 {
     let mut model_matrix = Mat4::identity();
     model_matrix = model_matrix * Mat4::translate(position);
     model_matrix = model_matrix * Mat4::rotate(rotation);
     model_matrix = model_matrix * Mat4::scale(scale);
     model_matrix
-}
\ No newline at end of file
+}
+
+// works out a tangent for each vertex from the direction its triangles' uvs run in, with w being the handedness of the
+// bitangent (bitangent = cross(normal, tangent) * w), the same as gltf stores them
+fn generate_tangents(positions: &[[f32; 3]], tex_coords: &[[f32; 2]], normals: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 4]> {
+    let sub = |a: [f32; 3], b: [f32; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let dot = |a: [f32; 3], b: [f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let cross = |a: [f32; 3], b: [f32; 3]| [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+
+    // add up each triangle's tangent and bitangent on its corners
+    let mut tangents = vec![[0.0f32; 3]; positions.len()];
+    let mut bitangents = vec![[0.0f32; 3]; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let highest = i0.max(i1).max(i2);
+        if highest >= positions.len() || highest >= tex_coords.len() {
+            continue;
+        }
+        let edge1 = sub(positions[i1], positions[i0]);
+        let edge2 = sub(positions[i2], positions[i0]);
+        let (du1, dv1) = (tex_coords[i1][0] - tex_coords[i0][0], tex_coords[i1][1] - tex_coords[i0][1]);
+        let (du2, dv2) = (tex_coords[i2][0] - tex_coords[i0][0], tex_coords[i2][1] - tex_coords[i0][1]);
+        let determinant = du1 * dv2 - du2 * dv1;
+        // the uvs are squashed into a line, so they don't say anything about direction
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = [(edge1[0] * dv2 - edge2[0] * dv1) * r, (edge1[1] * dv2 - edge2[1] * dv1) * r, (edge1[2] * dv2 - edge2[2] * dv1) * r];
+        let bitangent = [(edge2[0] * du1 - edge1[0] * du2) * r, (edge2[1] * du1 - edge1[1] * du2) * r, (edge2[2] * du1 - edge1[2] * du2) * r];
+        for i in [i0, i1, i2] {
+            for axis in 0..3 {
+                tangents[i][axis] += tangent[axis];
+                bitangents[i][axis] += bitangent[axis];
+            }
+        }
+    }
+
+    // then make each one perpendicular to the normal
+    (0..positions.len()).map(|i| {
+        let normal = normals.get(i).copied().unwrap_or([0.0, 1.0, 0.0]);
+        let tangent = tangents[i];
+        let along_normal = dot(normal, tangent);
+        let mut tangent = [tangent[0] - normal[0] * along_normal, tangent[1] - normal[1] * along_normal, tangent[2] - normal[2] * along_normal];
+        if dot(tangent, tangent) < f32::EPSILON {
+            // no uvs to go by, so anything perpendicular to the normal will do
+            let other = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+            tangent = cross(other, normal);
+        }
+        let length = dot(tangent, tangent).sqrt().max(f32::EPSILON);
+        let tangent = [tangent[0] / length, tangent[1] / length, tangent[2] / length];
+        let handedness = if dot(cross(normal, tangent), bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+        [tangent[0], tangent[1], tangent[2], handedness]
+    }).collect()
+}
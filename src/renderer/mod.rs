@@ -8,45 +8,98 @@ pub mod raycasting;
 pub mod texture;
 pub mod terrain;
 pub mod light;
-
-use std::collections::HashMap;
+pub mod thumbnails;
+pub mod headless;
+pub mod picking;
+pub mod gizmo;
+pub mod grid;
+pub mod outline;
+pub mod viewports;
+pub mod shadows;
+
+use std::collections::{HashMap, VecDeque};
 use std::ffi::c_void;
-use gfx_maths::{Quaternion, Vec2, Vec3};
+use std::time::Instant;
+use gfx_maths::{Mat4, Vec2, Vec3};
 use glad_gl::gl;
-use gtk::gdk::{Key, ModifierType};
+use gtk::gdk::ModifierType;
 use glad_gl::gl::*;
-use crate::renderer::camera::{Camera, CameraMovement};
+use crate::renderer::camera::{Camera, CameraMode, CameraMovement, CameraView};
+use crate::renderer::gizmo::Gizmo;
+use crate::renderer::grid::Grid;
 use crate::renderer::keyboard::KeyboardManager;
 use crate::renderer::light::Light;
 use crate::renderer::mesh::{Mesh, MeshError};
-use crate::renderer::raycasting::Ray;
+use crate::renderer::picking::PickingFramebuffer;
+use crate::renderer::raycasting::{MeshGeometry, Ray};
 use crate::renderer::shader::Shader;
+use crate::renderer::shadows::MAX_SHADOW_LAYERS;
 use crate::renderer::terrain::Terrain;
 use crate::renderer::texture::Texture;
+use crate::renderer::thumbnails::{ThumbnailFramebuffer, ThumbnailRequest};
 use crate::renderer::types::*;
+use crate::renderer::viewports::{ShadingMode, Viewport, ViewportLayout};
+use crate::keymap::KeyAction;
+use crate::settings;
 use crate::worldmachine::{World, WorldMachine};
+use crate::worldmachine::bookmarks::CameraBookmark;
+use crate::worldmachine::spatial::Aabb;
 
 pub static MAX_LIGHTS: usize = 100;
 pub static SHADOW_SIZE: usize = 1024;
 
+// radians the camera turns or orbits per pixel dragged, before the mouse sensitivity setting
+const LOOK_SPEED: f32 = 0.01;
+// each step of the scroll wheel moves the camera this much closer to the pivot when orbiting
+const ZOOM_FACTOR: f32 = 1.1;
+// or this far forwards when flying
+const FLY_ZOOM_STEP: f32 = 1.0;
+// how much faster the camera keys move the camera with the fast key held, the speed itself is a setting
+const FAST_CAMERA_MULTIPLIER: f32 = 4.0;
+// how quickly the camera gets up to speed when a camera key is pressed, and slows down when it's let go of (per second)
+const CAMERA_ACCELERATION: f32 = 10.0;
+const CAMERA_DAMPING: f32 = 8.0;
+// each step of the scroll wheel while right dragging multiplies or divides the fly speed by this
+const FLY_SPEED_STEP: f64 = 1.2;
+// the same range as the fly-speed setting allows
+const MIN_FLY_SPEED: f64 = 0.1;
+const MAX_FLY_SPEED: f64 = 1000.0;
+// longer frames than this (e.g. after the window was hidden) are treated as this long, so the camera doesn't jump
+const MAX_FRAME_TIME: f32 = 0.1;
+
 
 pub struct H2eckRenderer {
     pub state: H2eckState,
     pub data_dir: String,
     pub camera: Option<Camera>,
     pub keyboard: KeyboardManager,
-    pub last_mouse_position: (f32, f32),
     pub camera_can_move: bool,
+    pub camera_mode: CameraMode,
+    camera_drag_offset: (f32, f32), // how far the current orbit, look or pan drag has gone so far
+    camera_velocity: Vec3, // in the same inverted space as the camera's position
+    rotating_camera: bool, // right dragging, when scrolling changes the fly speed
+    last_frame: Option<Instant>,
     pub current_shader: Option<String>,
     pub shaders: Option<HashMap<String, Shader>>,
     pub meshes: Option<HashMap<String, Mesh>>,
+    pub mesh_geometry: HashMap<GLuint, MeshGeometry>, // keyed by vao, which every copy of a mesh shares
     pub textures: Option<HashMap<String, Texture>>,
     pub terrains: Option<HashMap<String, Terrain>>,
     pub lights: Vec<Light>,
+    pub shadow_matrices: Vec<Mat4>, // light space matrix of each shadow map layer in use, see shadows.rs
     pub framebuffers: Framebuffers,
-    pub selected_entity: isize,
+    pub hovered_entity: Option<u64>, // the entity under the cursor, see WorldMachine::hover
     pub initialised: bool,
-    pub shading: bool,
+    pub shading: ShadingMode,
+    pub thumbnail_framebuffer: Option<ThumbnailFramebuffer>,
+    pub thumbnail_requests: VecDeque<ThumbnailRequest>,
+    pub picking_framebuffer: Option<PickingFramebuffer>,
+    pub gizmo: Gizmo,
+    pub grid: Grid,
+    pub viewports: Vec<Viewport>,
+    pub viewport_layout: ViewportLayout,
+    pub active_viewport: usize,
+    pub scene_size: (i32, i32), // what all the panes are drawn into
 }
 
 pub struct Framebuffers {
@@ -56,8 +109,8 @@ pub struct Framebuffers {
     pub postbuffer_texture: usize,
     pub postbuffer_rbuffer: usize,
 
-    pub depthbuffer: usize,
-    pub depthbuffer_texture: usize,
+    pub depthbuffer: usize, // shadow maps are rendered with this
+    pub depthbuffer_texture: usize, // a texture array with a layer for each shadow map
 
     pub screenquad_vao: usize,
 }
@@ -73,14 +126,20 @@ impl Default for H2eckRenderer {
             data_dir: String::new(),
             camera: Option::None,
             keyboard: KeyboardManager::default(),
-            last_mouse_position: (0.0, 0.0),
             camera_can_move: false,
+            camera_mode: CameraMode::Fly,
+            camera_drag_offset: (0.0, 0.0),
+            camera_velocity: Vec3::new(0.0, 0.0, 0.0),
+            rotating_camera: false,
+            last_frame: None,
             current_shader: Option::None,
             shaders: Some(HashMap::new()),
             meshes: Some(HashMap::new()),
+            mesh_geometry: HashMap::new(),
             textures: Some(HashMap::new()),
             terrains: Some(HashMap::new()),
             lights: Vec::new(),
+            shadow_matrices: Vec::new(),
             framebuffers: Framebuffers {
                 original: 0,
                 postbuffer: 0,
@@ -90,9 +149,18 @@ impl Default for H2eckRenderer {
                 depthbuffer_texture: 0,
                 screenquad_vao: 0,
             },
-            selected_entity: -1,
+            hovered_entity: None,
             initialised: false,
-            shading: true,
+            shading: ShadingMode::Lit,
+            thumbnail_framebuffer: None,
+            thumbnail_requests: VecDeque::new(),
+            picking_framebuffer: None,
+            gizmo: Gizmo::default(),
+            grid: Grid::default(),
+            viewports: Vec::new(),
+            viewport_layout: ViewportLayout::Single,
+            active_viewport: 0,
+            scene_size: (0, 0),
         }
     }
 }
@@ -102,9 +170,9 @@ impl H2eckRenderer {
 
         let camera = Camera::new(Vec2::new(width as f32, height as f32), 90.0, 0.1, 10000.0);
         self.camera = Option::Some(camera);
+        self.setup_viewports(width as i32, height as i32);
 
-        // todo! get this from settings
-        self.data_dir = String::from("../huskyTech2/base");
+        self.data_dir = settings::data_location();
 
         unsafe {
             // get the number of the current framebuffer
@@ -173,19 +241,23 @@ impl H2eckRenderer {
             VertexAttribPointer(1, 2, FLOAT, FALSE as GLboolean, 5 * std::mem::size_of::<f32>() as i32, (3 * std::mem::size_of::<f32>()) as *const c_void);
             self.framebuffers.screenquad_vao = screenquad_vao as usize;
 
-            // create the depth framebuffer
+            // create the depth framebuffer, which the shadow maps are rendered with (see shadows.rs). each shadow
+            // map is a layer of the texture, which gets attached in turn
             let mut depthbuffer = 0;
             GenFramebuffers(1, &mut depthbuffer);
             BindFramebuffer(FRAMEBUFFER, depthbuffer);
             let mut depthtexture = 0;
             GenTextures(1, &mut depthtexture);
-            BindTexture(TEXTURE_2D, depthtexture);
-            TexImage2D(TEXTURE_2D, 0, DEPTH_COMPONENT as i32, SHADOW_SIZE as i32, SHADOW_SIZE as i32, 0, DEPTH_COMPONENT, FLOAT, std::ptr::null());
-            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
-            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
-            TexParameteri(TEXTURE_2D, TEXTURE_WRAP_S, REPEAT as i32);
-            TexParameteri(TEXTURE_2D, TEXTURE_WRAP_T, REPEAT as i32);
-            FramebufferTexture2D(FRAMEBUFFER, DEPTH_ATTACHMENT, TEXTURE_2D, depthtexture, 0);
+            BindTexture(TEXTURE_2D_ARRAY, depthtexture);
+            TexImage3D(TEXTURE_2D_ARRAY, 0, DEPTH_COMPONENT24 as i32, SHADOW_SIZE as i32, SHADOW_SIZE as i32, MAX_SHADOW_LAYERS as i32, 0, DEPTH_COMPONENT, FLOAT, std::ptr::null());
+            TexParameteri(TEXTURE_2D_ARRAY, TEXTURE_MIN_FILTER, NEAREST as i32);
+            TexParameteri(TEXTURE_2D_ARRAY, TEXTURE_MAG_FILTER, NEAREST as i32);
+            // anything outside a shadow map isn't in shadow
+            TexParameteri(TEXTURE_2D_ARRAY, TEXTURE_WRAP_S, CLAMP_TO_BORDER as i32);
+            TexParameteri(TEXTURE_2D_ARRAY, TEXTURE_WRAP_T, CLAMP_TO_BORDER as i32);
+            let border: [f32; 4] = [1.0; 4];
+            TexParameterfv(TEXTURE_2D_ARRAY, TEXTURE_BORDER_COLOR, border.as_ptr());
+            FramebufferTextureLayer(FRAMEBUFFER, DEPTH_ATTACHMENT, depthtexture, 0, 0);
             DrawBuffer(NONE);
             ReadBuffer(NONE);
             if CheckFramebufferStatus(FRAMEBUFFER) != FRAMEBUFFER_COMPLETE {
@@ -216,6 +288,10 @@ impl H2eckRenderer {
         Shader::load_shader(self, "basic").expect("failed to load shader");
         Shader::load_shader(self, "terrain").expect("failed to load shader (terrain)");
         Shader::load_shader(self, "viz").expect("failed to load shader (viz)");
+        Shader::load_shader(self, "picking").expect("failed to load shader (picking)");
+        Shader::load_shader(self, "gizmo").expect("failed to load shader (gizmo)");
+        Shader::load_shader(self, "outline").expect("failed to load shader (outline)");
+        Shader::load_shader(self, "shadow").expect("failed to load shader (shadow)");
         Texture::load_texture("default", "default", self, false).expect("failed to load default texture");
         Texture::load_texture("grass1", format!("{}/textures/{}_", self.data_dir,"terrain/grass1").as_str(), self, true).expect("failed to load grass1 texture");
         Texture::load_texture("dirt1", format!("{}/textures/{}_", self.data_dir,"terrain/dirt1").as_str(), self, true).expect("failed to load dirt1 texture");
@@ -251,40 +327,84 @@ impl H2eckRenderer {
         Ok(())
     }
 
-    pub fn process_key(&mut self, key: Key, value: bool) {
-        match key {
-            Key::w => {
+    // action is what the key is bound to, only held actions do anything here
+    pub fn process_key(&mut self, action: Option<KeyAction>, value: bool) {
+        match action {
+            Some(KeyAction::CameraForward) => {
                 self.keyboard.forward = value;
             }
-            Key::s => {
+            Some(KeyAction::CameraBackward) => {
                 self.keyboard.backward = value;
             }
-            Key::a => {
+            Some(KeyAction::CameraLeft) => {
                 self.keyboard.left = value;
             }
-            Key::d => {
+            Some(KeyAction::CameraRight) => {
                 self.keyboard.right = value;
             }
+            Some(KeyAction::CameraUp) => {
+                self.keyboard.up = value;
+            }
+            Some(KeyAction::CameraDown) => {
+                self.keyboard.down = value;
+            }
+            Some(KeyAction::CameraFast) => {
+                self.keyboard.fast = value;
+            }
             _ => {}
         };
     }
 
+    // seconds since the last frame
+    fn frame_time(&mut self) -> f32 {
+        let now = Instant::now();
+        let dt = self.last_frame.map(|last_frame| now.duration_since(last_frame).as_secs_f32()).unwrap_or(0.0);
+        self.last_frame = Some(now);
+        dt.min(MAX_FRAME_TIME)
+    }
+
     pub fn process_inputs(&mut self) {
+        let dt = self.frame_time();
+        self.camera.as_mut().unwrap().update_transition();
         let mut vec = Vec3::new(0.0, 0.0, 0.0);
-        let scale = 0.1;
         if self.keyboard.forward {
-            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Forward, scale);
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Forward, 1.0);
         }
         if self.keyboard.backward {
-            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Backward, scale);
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Backward, 1.0);
         }
         if self.keyboard.left {
-            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Left, scale);
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Left, 1.0);
         }
         if self.keyboard.right {
-            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Right, scale);
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Right, 1.0);
         }
-        self.move_camera(vec);
+        if self.keyboard.up {
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Up, 1.0);
+        }
+        if self.keyboard.down {
+            vec += self.camera.as_mut().unwrap().process_keyboard(CameraMovement::Down, 1.0);
+        }
+        // ease the velocity towards where the keys want to go, so the camera speeds up and slows down smoothly
+        // however fast frames are coming in
+        let (target, rate) = if vec.magnitude() > 0.0 {
+            self.camera.as_mut().unwrap().cancel_transition();
+            let speed = settings::fly_speed() as f32 * if self.keyboard.fast { FAST_CAMERA_MULTIPLIER } else { 1.0 };
+            (vec * (speed / vec.magnitude()), CAMERA_ACCELERATION)
+        } else {
+            (Vec3::new(0.0, 0.0, 0.0), CAMERA_DAMPING)
+        };
+        self.camera_velocity += (target - self.camera_velocity) * (1.0 - (-rate * dt).exp());
+        if target.magnitude() == 0.0 && self.camera_velocity.magnitude() < 0.001 {
+            self.camera_velocity = Vec3::new(0.0, 0.0, 0.0);
+        }
+        if self.camera_velocity.magnitude() > 0.0 {
+            self.move_camera(self.camera_velocity * dt);
+        }
+    }
+
+    pub fn stop_camera(&mut self) {
+        self.camera_velocity = Vec3::new(0.0, 0.0, 0.0);
     }
 
     pub fn move_camera(&mut self, direction: Vec3) {
@@ -293,31 +413,140 @@ impl H2eckRenderer {
     }
 
     pub fn start_rotate_camera(&mut self, mouse_x: f32, mouse_y: f32) {
+        self.camera.as_mut().unwrap().cancel_transition();
         self.camera_can_move = true;
+        self.rotating_camera = true;
+        self.camera_drag_offset = (0.0, 0.0);
     }
 
     pub fn end_rotate_camera(&mut self, mouse_x: f32, mouse_y: f32) {
         self.camera_can_move = false;
-        self.last_mouse_position = (mouse_x, mouse_y);
+        self.rotating_camera = false;
     }
 
     pub fn rotate_camera(&mut self, mouse_x_offset: f32, mouse_y_offset: f32) {
-        let mouse_x_offset = self.last_mouse_position.0 + mouse_x_offset;
-        let mouse_y_offset = self.last_mouse_position.1 + mouse_y_offset;
-        let mut camera = self.camera.as_mut().unwrap();
-        let mut yaw = helpers::get_quaternion_yaw(camera.get_rotation());
-        let mut pitch = helpers::get_quaternion_pitch(camera.get_rotation());
-        yaw += -mouse_x_offset;
-        pitch += -mouse_y_offset;
-        if pitch > 89.0 {
-            pitch = 89.0;
+        // the orthographic views can't be turned, so right drag pans them instead
+        if self.camera.as_ref().unwrap().is_orthographic() {
+            self.pan_camera(mouse_x_offset, mouse_y_offset);
+            return;
         }
-        if pitch < -89.0 {
-            pitch = -89.0;
+        let (x, y) = self.camera_drag_offset;
+        self.camera_drag_offset = (mouse_x_offset, mouse_y_offset);
+        let speed = LOOK_SPEED * settings::mouse_sensitivity() as f32;
+        let (dx, dy) = ((mouse_x_offset - x) * speed, (mouse_y_offset - y) * speed);
+        let camera = self.camera.as_mut().unwrap();
+        if self.camera_mode == CameraMode::Orbit {
+            camera.orbit(-dx, dy);
+        } else {
+            camera.look_around(-dx, -dy);
+        }
+    }
+
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.camera_mode != CameraMode::Orbit {
+            if let Some(camera) = self.camera.as_mut() {
+                camera.recenter_pivot();
+            }
+        }
+        self.camera_mode = mode;
+    }
+
+    pub fn start_pan_camera(&mut self) {
+        self.camera.as_mut().unwrap().cancel_transition();
+        self.camera_can_move = true;
+        self.camera_drag_offset = (0.0, 0.0);
+    }
+
+    pub fn end_pan_camera(&mut self) {
+        self.camera_can_move = false;
+    }
+
+    // offsets are how far the mouse has moved since the pan started, the scene follows the mouse
+    pub fn pan_camera(&mut self, mouse_x_offset: f32, mouse_y_offset: f32) {
+        let (x, y) = self.camera_drag_offset;
+        self.camera_drag_offset = (mouse_x_offset, mouse_y_offset);
+        if let Some(camera) = self.camera.as_mut() {
+            let units = camera.units_per_pixel();
+            camera.pan(-(mouse_x_offset - x) * units, (mouse_y_offset - y) * units);
+        }
+    }
+
+    // steps is how many clicks the scroll wheel moved, positive to zoom out. while right dragging in fly mode it
+    // changes how fast the camera keys move the camera instead
+    pub fn zoom_camera(&mut self, steps: f32) {
+        if self.rotating_camera && self.camera_mode == CameraMode::Fly && !self.camera.as_ref().unwrap().is_orthographic() {
+            self.adjust_fly_speed(steps);
+            return;
+        }
+        let mode = self.camera_mode;
+        if let Some(camera) = self.camera.as_mut() {
+            camera.cancel_transition();
+            if mode == CameraMode::Orbit || camera.is_orthographic() {
+                camera.dolly(ZOOM_FACTOR.powf(steps));
+            } else {
+                camera.fly_forward(-steps * FLY_ZOOM_STEP);
+            }
+        }
+    }
+
+    // multiplies the fly speed for each step the scroll wheel moved, faster when scrolling up
+    pub fn adjust_fly_speed(&mut self, steps: f32) {
+        let fly_speed = (settings::fly_speed() * FLY_SPEED_STEP.powf(-steps as f64)).clamp(MIN_FLY_SPEED, MAX_FLY_SPEED);
+        settings::set_fly_speed(fly_speed);
+    }
+
+    pub fn set_camera_view(&mut self, camera_view: CameraView) {
+        self.stop_camera();
+        if let Some(camera) = self.camera.as_mut() {
+            camera.cancel_transition();
+            camera.set_camera_view(camera_view);
+        }
+    }
+
+    // a bookmark of where the active pane's camera is now
+    pub fn camera_bookmark(&self, name: &str) -> Option<CameraBookmark> {
+        let camera = self.camera.as_ref()?;
+        Some(CameraBookmark {
+            name: name.to_string(),
+            position: camera.get_position(),
+            rotation: camera.get_rotation(),
+        })
+    }
+
+    // moves the active pane's camera smoothly to a bookmark, switching back to perspective first if need be
+    pub fn go_to_bookmark(&mut self, bookmark: &CameraBookmark) {
+        self.stop_camera();
+        if let Some(camera) = self.camera.as_mut() {
+            camera.set_camera_view(CameraView::Perspective);
+            camera.move_to(bookmark.position, bookmark.rotation);
+        }
+    }
+
+    // frames the selected entity's bounds, returns false if nothing's selected or it has no bounds
+    pub fn focus_selection(&mut self, worldmachine: &WorldMachine) -> bool {
+        let bounds = match worldmachine.selected_entity().and_then(|uid| worldmachine.entity_bounds(uid)) {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        self.frame_bounds(&bounds);
+        true
+    }
+
+    // frames everything in the world
+    pub fn frame_all(&mut self, worldmachine: &WorldMachine) -> bool {
+        let bounds = match worldmachine.world_bounds() {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        self.frame_bounds(&bounds);
+        true
+    }
+
+    fn frame_bounds(&mut self, bounds: &Aabb) {
+        if let Some(camera) = self.camera.as_mut() {
+            camera.cancel_transition();
+            camera.frame(bounds.center(), bounds.size().magnitude() * 0.5);
         }
-        let mut rotation = Quaternion::identity();
-        rotation = Quaternion::from_euler_angles_zyx(&Vec3::new(pitch, 0.0, 0.0)) * rotation * Quaternion::from_euler_angles_zyx(&Vec3::new(0.0, yaw, 0.0));
-        camera.set_rotation(rotation);
     }
 
     fn normal_scene_render(&mut self, worldmachine: &mut WorldMachine) {
@@ -342,7 +571,14 @@ impl H2eckRenderer {
             }
              */
 
+            if self.shading == ShadingMode::Wireframe {
+                PolygonMode(FRONT_AND_BACK, LINE);
+            }
             worldmachine.render(self);
+            PolygonMode(FRONT_AND_BACK, FILL);
+            self.render_grid();
+            worldmachine.render_outlines(self);
+            self.render_gizmo(worldmachine);
         }
     }
 
@@ -355,6 +591,8 @@ impl H2eckRenderer {
             self.initialised = true;
         }
 
+        self.process_thumbnail_requests(4);
+
         self.process_inputs();
 
         let lights = worldmachine.send_lights_to_renderer();
@@ -362,13 +600,13 @@ impl H2eckRenderer {
             self.lights = lights;
         }
 
-        unsafe {
-            Viewport(0, 0, self.camera.as_mut().unwrap().get_window_size().x as GLsizei, self.camera.as_mut().unwrap().get_window_size().y as GLsizei);
+        self.render_shadow_maps(worldmachine);
 
+        unsafe {
             // set framebuffer to the post processing framebuffer
             BindFramebuffer(FRAMEBUFFER, self.framebuffers.postbuffer as GLuint);
 
-            self.normal_scene_render(worldmachine);
+            self.render_viewports(worldmachine);
 
             // set framebuffer to the default framebuffer
             BindFramebuffer(FRAMEBUFFER, self.framebuffers.original as GLuint);
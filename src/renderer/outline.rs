@@ -0,0 +1,86 @@
+use std::ffi::CString;
+use gfx_maths::Vec4;
+use glad_gl::gl::*;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::mesh::Mesh;
+
+// outlines around the selected and hovered entities. the entity's meshes are first drawn into the stencil buffer only,
+// then drawn again in a flat colour, nudged a few pixels in each direction, wherever the stencil is still empty. that
+// leaves a ring of colour around the silhouette that's the same width no matter how far away the entity is
+
+pub const SELECTED_OUTLINE_COLOUR: Vec4 = Vec4 { x: 1.0, y: 0.6, z: 0.1, w: 1.0 };
+pub const SELECTED_OUTLINE_WIDTH: f32 = 3.0;
+pub const HOVERED_OUTLINE_COLOUR: Vec4 = Vec4 { x: 1.0, y: 1.0, z: 1.0, w: 0.35 };
+pub const HOVERED_OUTLINE_WIDTH: f32 = 1.0;
+
+const OUTLINE_DIRECTIONS: [(f32, f32); 8] = [
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+    (0.7071, 0.7071), (-0.7071, 0.7071), (0.7071, -0.7071), (-0.7071, -0.7071),
+];
+
+impl H2eckRenderer {
+    // outlines the given meshes (all of one entity) with a ring width pixels wide, drawn over everything else so that
+    // the outline shows through whatever's in front of it. should be called after the scene is drawn
+    pub fn render_outline(&mut self, meshes: &[Mesh], colour: Vec4, width: f32) {
+        if meshes.is_empty() {
+            return;
+        }
+        let shader = match self.shaders.as_ref().unwrap().get("outline") {
+            Some(shader) => shader.clone(),
+            None => return,
+        };
+        let window_size = match self.camera.as_ref() {
+            Some(camera) => camera.get_window_size(),
+            None => return,
+        };
+        unsafe {
+            UseProgram(shader.program);
+            self.current_shader = Some(shader.name.clone());
+            let offset_loc = GetUniformLocation(shader.program, CString::new("u_offset").unwrap().as_ptr());
+            let colour_loc = GetUniformLocation(shader.program, CString::new("u_colour").unwrap().as_ptr());
+
+            // each outline gets its own stencil, so overlapping entities still get a full outline
+            StencilMask(0xFF);
+            Clear(STENCIL_BUFFER_BIT);
+            Disable(DEPTH_TEST);
+            DepthMask(FALSE as GLboolean);
+            Disable(CULL_FACE);
+
+            // mark the silhouette
+            ColorMask(FALSE as GLboolean, FALSE as GLboolean, FALSE as GLboolean, FALSE as GLboolean);
+            StencilFunc(ALWAYS, 1, 0xFF);
+            StencilOp(KEEP, KEEP, REPLACE);
+            Uniform2f(offset_loc, 0.0, 0.0);
+            for mesh in meshes {
+                mesh.render(self, &shader, None);
+            }
+
+            // draw around it. each pixel is only drawn once, so translucent outlines don't build up where the
+            // nudged copies overlap
+            ColorMask(TRUE as GLboolean, TRUE as GLboolean, TRUE as GLboolean, TRUE as GLboolean);
+            StencilFunc(EQUAL, 0, 0xFF);
+            StencilOp(KEEP, KEEP, INCR);
+            Uniform4f(colour_loc, colour.x, colour.y, colour.z, colour.w);
+            for (x, y) in OUTLINE_DIRECTIONS {
+                // pixels to normalised device coordinates, which go from -1 to 1
+                Uniform2f(offset_loc, x * width * 2.0 / window_size.x, y * width * 2.0 / window_size.y);
+                for mesh in meshes {
+                    mesh.render(self, &shader, None);
+                }
+            }
+
+            // back to how normal_scene_render left things
+            StencilFunc(ALWAYS, 0, 0xFF);
+            StencilOp(KEEP, KEEP, KEEP);
+            Enable(CULL_FACE);
+            DepthMask(TRUE as GLboolean);
+            Enable(DEPTH_TEST);
+
+            let mut error = GetError();
+            while error != NO_ERROR {
+                error!("OpenGL error while rendering outline: {}", error);
+                error = GetError();
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::ffi::CString;
+use gfx_maths::Vec2;
+use glad_gl::gl::*;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::mesh::Mesh;
+use crate::renderer::raycasting::Ray;
+use crate::worldmachine::WorldMachine;
+
+// clicking in the viewport renders every entity's index into an integer framebuffer with the picking shader, then
+// reads back the pixel under the cursor. the shader writes the entity (index + 1, so 0 is nothing) into red and the
+// component that drew the pixel into green, so we know whether a mesh, terrain or collider was clicked
+
+#[derive(Clone, Copy, Debug)]
+pub struct PickingFramebuffer {
+    pub framebuffer: GLuint,
+    pub texture: GLuint,
+    pub renderbuffer: GLuint,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl PickingFramebuffer {
+    fn new(width: i32, height: i32) -> Result<Self, String> {
+        unsafe {
+            let mut previous_framebuffer = 0;
+            GetIntegerv(FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            let mut framebuffer = 0;
+            GenFramebuffers(1, &mut framebuffer);
+            BindFramebuffer(FRAMEBUFFER, framebuffer);
+            let mut texture = 0;
+            GenTextures(1, &mut texture);
+            BindTexture(TEXTURE_2D, texture);
+            TexImage2D(TEXTURE_2D, 0, RGB32UI as i32, width, height, 0, RGB_INTEGER, UNSIGNED_INT, std::ptr::null());
+            // integer textures can't be filtered
+            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            FramebufferTexture2D(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, texture, 0);
+            let mut renderbuffer = 0;
+            GenRenderbuffers(1, &mut renderbuffer);
+            BindRenderbuffer(RENDERBUFFER, renderbuffer);
+            RenderbufferStorage(RENDERBUFFER, DEPTH24_STENCIL8, width, height);
+            FramebufferRenderbuffer(FRAMEBUFFER, DEPTH_STENCIL_ATTACHMENT, RENDERBUFFER, renderbuffer);
+            let complete = CheckFramebufferStatus(FRAMEBUFFER) == FRAMEBUFFER_COMPLETE;
+            BindFramebuffer(FRAMEBUFFER, previous_framebuffer as GLuint);
+            if !complete {
+                return Err("picking framebuffer is not complete".to_string());
+            }
+            Ok(Self { framebuffer, texture, renderbuffer, width, height })
+        }
+    }
+
+    fn delete(&self) {
+        unsafe {
+            DeleteFramebuffers(1, &self.framebuffer);
+            DeleteTextures(1, &self.texture);
+            DeleteRenderbuffers(1, &self.renderbuffer);
+        }
+    }
+}
+
+impl H2eckRenderer {
+    // draws a mesh into the picking framebuffer, must be called from WorldMachine::render_ids
+    pub fn render_id(&mut self, mesh: &Mesh, entity_index: usize, component_index: usize) {
+        let shader = match self.shaders.as_ref().unwrap().get("picking") {
+            Some(shader) => shader.clone(),
+            None => return,
+        };
+        unsafe {
+            UseProgram(shader.program);
+            self.current_shader = Some(shader.name.clone());
+            Uniform1ui(GetUniformLocation(shader.program, CString::new("u_entity_id").unwrap().as_ptr()), entity_index as GLuint + 1);
+            Uniform1ui(GetUniformLocation(shader.program, CString::new("u_component_id").unwrap().as_ptr()), component_index as GLuint);
+        }
+        mesh.render(self, &shader, None);
+    }
+
+    // a ray from the camera through the given pixel of the scene, with the origin in the bottom left like pick
+    pub fn viewport_ray(&self, x: f32, y: f32, max_distance: f32) -> Option<Ray> {
+        let camera = self.camera.as_ref()?;
+        let window_size = camera.get_window_size();
+        Some(Ray::from_mouse_coords(Vec2::new(x, window_size.y - y), window_size, camera, max_distance))
+    }
+
+    // the (entity index, component index) drawn at the given pixel of the scene, with the origin in the bottom left
+    pub fn pick(&mut self, worldmachine: &mut WorldMachine, x: i32, y: i32) -> Option<(usize, usize)> {
+        if !self.initialised || self.camera.is_none() {
+            return None;
+        }
+        let window_size = self.camera.as_ref().unwrap().get_window_size();
+        let (width, height) = (window_size.x as i32, window_size.y as i32);
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return None;
+        }
+
+        // the framebuffer follows the size of the scene
+        if let Some(framebuffer) = self.picking_framebuffer {
+            if framebuffer.width != width || framebuffer.height != height {
+                framebuffer.delete();
+                self.picking_framebuffer = None;
+            }
+        }
+        if self.picking_framebuffer.is_none() {
+            match PickingFramebuffer::new(width, height) {
+                Ok(framebuffer) => self.picking_framebuffer = Some(framebuffer),
+                Err(e) => {
+                    error!("{}", e);
+                    return None;
+                }
+            }
+        }
+        let framebuffer = self.picking_framebuffer.unwrap();
+
+        let mut pixel: [GLuint; 3] = [0; 3];
+        unsafe {
+            let mut previous_framebuffer = 0;
+            GetIntegerv(FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            BindFramebuffer(FRAMEBUFFER, framebuffer.framebuffer);
+            Viewport(0, 0, width, height);
+
+            // same state as normal_scene_render, minus anything that doesn't make sense for integers
+            Enable(CULL_FACE);
+            CullFace(FRONT);
+            Enable(DEPTH_TEST);
+            DepthFunc(LESS);
+            Disable(BLEND);
+            Disable(FRAMEBUFFER_SRGB);
+            let clear: [GLuint; 4] = [0; 4];
+            ClearBufferuiv(COLOR, 0, clear.as_ptr());
+            Clear(DEPTH_BUFFER_BIT | STENCIL_BUFFER_BIT);
+
+            worldmachine.render_ids(self);
+
+            ReadPixels(x, y, 1, 1, RGB_INTEGER, UNSIGNED_INT, pixel.as_mut_ptr() as *mut GLvoid);
+
+            Enable(BLEND);
+            BindFramebuffer(FRAMEBUFFER, previous_framebuffer as GLuint);
+
+            let mut error = GetError();
+            while error != NO_ERROR {
+                error!("OpenGL error while picking: {}", error);
+                error = GetError();
+            }
+        }
+
+        if pixel[0] == 0 {
+            return None;
+        }
+        Some((pixel[0] as usize - 1, pixel[1] as usize))
+    }
+}
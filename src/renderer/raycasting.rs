@@ -65,15 +65,19 @@ pub fn length(vec: Vec3) -> f32 {
 impl Ray {
     pub fn from_mouse_coords(mouse_coords: Vec2, window_size: Vec2, camera: &Camera, max_distance: f32) -> Ray {
         let device_coords = convert_screen_coords_to_device_coords(mouse_coords, window_size);
-        let ray_clip = Vec4::new(device_coords.x, device_coords.y, -1.0, 1.0);
-        let mut ray_eye = inverse(camera.get_projection()) * ray_clip;
-        ray_eye = Vec4::new(ray_eye.x, ray_eye.y, -1.0, 0.0);
-        let mut ray_world = inverse(camera.get_view()) * ray_eye;
-        ray_world = Vec4::new(ray_world.x, ray_world.y, ray_world.z, 0.0);
-        let direction = ray_world.normalize();
+        // un-project the cursor onto the near and far planes, the ray goes from one to the other.
+        // (the camera's position is inverted, so it can't be used as the origin directly)
+        let inverse_view_projection = inverse(camera.get_projection() * camera.get_view());
+        let unproject = |z: f32| {
+            let point = inverse_view_projection * Vec4::new(device_coords.x, device_coords.y, z, 1.0);
+            Vec3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        let direction = (far - near) / length(far - near);
         Ray {
-            origin: camera.get_position(),
-            direction: *direction.deref(),
+            origin: near,
+            direction: Vec4::new(direction.x, direction.y, direction.z, 0.0),
             max_distance,
         }
     }
@@ -109,4 +113,79 @@ impl Ray {
         }
         Some(distance_to_intersection)
     }
-}
\ No newline at end of file
+}
+
+// distance along the ray to where it hits the triangle, from either side
+pub fn intersect_triangle(origin: Vec3, direction: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < 1e-8 {
+        return None; // parallel
+    }
+    let inv_det = 1.0 / det;
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(edge1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(q) * inv_det;
+    if distance < 0.0 {
+        return None;
+    }
+    Some(distance)
+}
+
+// a cpu side copy of a mesh's triangles in model space, so rays can be tested against the actual geometry
+#[derive(Clone, Debug, Default)]
+pub struct MeshGeometry {
+    pub positions: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleHit {
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3, // facing back towards the ray
+}
+
+impl MeshGeometry {
+    // the closest triangle the ray hits once the mesh has been put through the model matrix
+    pub fn raycast(&self, model: Mat4, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<TriangleHit> {
+        let positions = self.positions.iter()
+            .map(|p| xyz(model * Vec4::new(p.x, p.y, p.z, 1.0)))
+            .collect::<Vec<_>>();
+        let mut closest: Option<TriangleHit> = None;
+        for triangle in self.indices.chunks_exact(3) {
+            let (a, b, c) = match (positions.get(triangle[0] as usize), positions.get(triangle[1] as usize), positions.get(triangle[2] as usize)) {
+                (Some(a), Some(b), Some(c)) => (*a, *b, *c),
+                _ => continue,
+            };
+            let distance = match intersect_triangle(origin, direction, a, b, c) {
+                Some(distance) => distance,
+                None => continue,
+            };
+            if distance > max_distance || closest.map_or(false, |hit| hit.distance <= distance) {
+                continue;
+            }
+            let normal = (b - a).cross(c - a);
+            let mut normal = normal / length(normal).max(f32::EPSILON);
+            if normal.dot(direction) > 0.0 {
+                normal = -normal;
+            }
+            closest = Some(TriangleHit {
+                distance,
+                point: origin + direction * distance,
+                normal,
+            });
+        }
+        closest
+    }
+}
@@ -0,0 +1,136 @@
+use gfx_maths::*;
+use glad_gl::gl::*;
+use crate::renderer::{H2eckRenderer, MAX_LIGHTS, SHADOW_SIZE};
+use crate::renderer::camera::{direction_yaw_pitch, yaw_pitch_rotation};
+use crate::renderer::light::{Light, LightKind};
+use crate::worldmachine::WorldMachine;
+
+// lights with "shadows" turned on get shadow maps, rendered from the light into the depth framebuffer before the
+// panes are drawn. every shadow map is a layer of framebuffers.depthbuffer_texture, a SHADOW_SIZE texture array:
+// directional and spot lights take one layer each, and point lights take six, one for each face of a cube around
+// them in the order +x -x +y -y +z -z. the shaders pick the face from the direction to the light, and smooth the
+// edges with percentage closer filtering. lights past the last free layer don't cast shadows. the maps are only
+// rendered again when the lights or the bounds of something in the world change
+
+pub const MAX_SHADOW_LAYERS: usize = 12;
+// the texture unit the shadow maps are bound to, after anything the materials or terrain use
+pub const SHADOW_TEXTURE_UNIT: GLuint = 8;
+// pushes the depth written into the shadow maps back a little so that surfaces don't shadow themselves
+const SHADOW_SLOPE_OFFSET: f32 = 2.0;
+const SHADOW_CONSTANT_OFFSET: f32 = 4.0;
+// closest anything can be to a point or spot light and still cast a shadow
+const SHADOW_NEAR: f32 = 0.1;
+
+// the directions the faces of a point light's cube look in
+const CUBE_FACES: [(f32, f32, f32); 6] = [
+    (1.0, 0.0, 0.0),
+    (-1.0, 0.0, 0.0),
+    (0.0, 1.0, 0.0),
+    (0.0, -1.0, 0.0),
+    (0.0, 0.0, 1.0),
+    (0.0, 0.0, -1.0),
+];
+
+// projection * view for something at eye looking in direction, the same way Camera builds its view matrix
+fn view_matrix(eye: Vec3, direction: Vec3) -> Mat4 {
+    let (yaw, pitch) = direction_yaw_pitch(direction);
+    Mat4::rotate(yaw_pitch_rotation(yaw, pitch)) * Mat4::translate(-eye)
+}
+
+// the light space matrices for each layer a light's shadow map needs. the scene is the sphere around everything in
+// the map, which directional lights fit their map to and point and spot lights reach to the far side of
+fn light_matrices(light: &Light, scene_center: Vec3, scene_radius: f32) -> Vec<Mat4> {
+    match light.kind {
+        LightKind::Directional => {
+            let projection = Mat4::orthographic_opengl(-scene_radius, scene_radius, -scene_radius, scene_radius, -scene_radius, scene_radius);
+            vec![projection * view_matrix(scene_center, light.direction)]
+        }
+        LightKind::Spot => {
+            let far = ((light.position - scene_center).magnitude() + scene_radius).max(SHADOW_NEAR * 2.0);
+            // a little wider than the cone so that the soft edge of it is covered too
+            let fov = ((light.angle as f32 + 5.0) * 2.0).min(170.0).to_radians();
+            let projection = Mat4::perspective_opengl(fov, SHADOW_NEAR, far, 1.0);
+            vec![projection * view_matrix(light.position, light.direction)]
+        }
+        LightKind::Point => {
+            let far = ((light.position - scene_center).magnitude() + scene_radius).max(SHADOW_NEAR * 2.0);
+            let projection = Mat4::perspective_opengl(90.0f32.to_radians(), SHADOW_NEAR, far, 1.0);
+            CUBE_FACES.iter()
+                .map(|(x, y, z)| projection * view_matrix(light.position, Vec3::new(*x, *y, *z)))
+                .collect()
+        }
+    }
+}
+
+impl H2eckRenderer {
+    // hands out the shadow map layers and renders into them, should be called after the lights are updated and
+    // before the panes are drawn
+    pub(crate) fn render_shadow_maps(&mut self, worldmachine: &mut WorldMachine) {
+        // whatever was rendered last time is still right
+        if !worldmachine.take_shadows_outdated() {
+            return;
+        }
+        self.shadow_matrices.clear();
+        for light in self.lights.iter_mut() {
+            light.shadow_layer = None;
+        }
+        if !self.lights.iter().take(MAX_LIGHTS).any(|light| light.casts_shadows) {
+            return;
+        }
+        let shader = match self.shaders.as_ref().unwrap().get("shadow") {
+            Some(shader) => shader.clone(),
+            None => return,
+        };
+
+        let (scene_center, scene_radius) = match worldmachine.world_bounds() {
+            Some(bounds) => (bounds.center(), (bounds.size().magnitude() * 0.5).max(1.0)),
+            None => (Vec3::new(0.0, 0.0, 0.0), 1.0),
+        };
+        for light in self.lights.iter_mut().take(MAX_LIGHTS) {
+            if !light.casts_shadows {
+                continue;
+            }
+            let matrices = light_matrices(light, scene_center, scene_radius);
+            // a point light that doesn't fit might still leave room for a spot light after it
+            if self.shadow_matrices.len() + matrices.len() > MAX_SHADOW_LAYERS {
+                warn!("out of shadow map layers, a {:?} light at ({}, {}, {}) won't cast shadows", light.kind, light.position.x, light.position.y, light.position.z);
+                continue;
+            }
+            light.shadow_layer = Some(self.shadow_matrices.len());
+            self.shadow_matrices.extend(matrices);
+        }
+
+        unsafe {
+            let mut previous_framebuffer = 0;
+            GetIntegerv(FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            BindFramebuffer(FRAMEBUFFER, self.framebuffers.depthbuffer as GLuint);
+            Viewport(0, 0, SHADOW_SIZE as i32, SHADOW_SIZE as i32);
+
+            // same culling as normal_scene_render
+            Enable(CULL_FACE);
+            CullFace(FRONT);
+            Enable(DEPTH_TEST);
+            DepthFunc(LESS);
+            Enable(POLYGON_OFFSET_FILL);
+            PolygonOffset(SHADOW_SLOPE_OFFSET, SHADOW_CONSTANT_OFFSET);
+
+            UseProgram(shader.program);
+            self.current_shader = Some(shader.name.clone());
+
+            for (layer, matrix) in self.shadow_matrices.iter().enumerate() {
+                FramebufferTextureLayer(FRAMEBUFFER, DEPTH_ATTACHMENT, self.framebuffers.depthbuffer_texture as GLuint, 0, layer as GLint);
+                Clear(DEPTH_BUFFER_BIT);
+                worldmachine.render_shadow_casters(self, &shader, *matrix);
+            }
+
+            Disable(POLYGON_OFFSET_FILL);
+            BindFramebuffer(FRAMEBUFFER, previous_framebuffer as GLuint);
+
+            let mut error = GetError();
+            while error != NO_ERROR {
+                error!("OpenGL error while rendering shadow maps: {}", error);
+                error = GetError();
+            }
+        }
+    }
+}
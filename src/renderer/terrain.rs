@@ -2,10 +2,11 @@ use std::ffi::CString;
 use std::ptr::null;
 use gfx_maths::{Mat4, Quaternion, Vec3};
 use glad_gl::gl::*;
-use crate::renderer::{H2eckRenderer, MAX_LIGHTS};
+use crate::renderer::H2eckRenderer;
 use crate::renderer::mesh::Mesh;
 use crate::renderer::shader::Shader;
 use crate::renderer::texture::Texture;
+use crate::renderer::viewports::ShadingMode;
 
 #[derive(Clone)]
 pub struct Terrain {
@@ -68,22 +69,11 @@ impl Terrain {
             Uniform1i(GetUniformLocation(self.shader.program, CString::new("tex2").unwrap().as_ptr()), 3);
             Uniform1i(GetUniformLocation(self.shader.program, CString::new("tex3").unwrap().as_ptr()), 4);
 
-            // send the lights to the shader
-            let light_count = renderer.lights.len();
-            let light_count = if light_count > MAX_LIGHTS { MAX_LIGHTS } else { light_count };
-            let light_count_loc = GetUniformLocation(self.shader.program, CString::new("u_light_count").unwrap().as_ptr());
-            Uniform1i(light_count_loc, light_count as i32);
-            for (i, light) in renderer.lights.iter().enumerate() {
-                if i >= MAX_LIGHTS { break; }
-                let light_pos = GetUniformLocation(self.shader.program, CString::new(format!("u_lights[{}].position", i)).unwrap().as_ptr());
-                let light_color = GetUniformLocation(self.shader.program, CString::new(format!("u_lights[{}].colour", i)).unwrap().as_ptr());
-                let light_intensity = GetUniformLocation(self.shader.program, CString::new(format!("u_lights[{}].intensity", i)).unwrap().as_ptr());
-
-                Uniform3f(light_pos, light.position.x, light.position.y, light.position.z);
-                Uniform3f(light_color, light.color.x, light.color.y, light.color.z);
-                Uniform1f(light_intensity, light.intensity as f32);
-            }
+            // the unlit and wireframe shading modes skip lighting
+            Uniform1i(GetUniformLocation(self.shader.program, CString::new("u_unlit").unwrap().as_ptr()), (renderer.shading != ShadingMode::Lit) as i32);
 
+            // send the lights to the shader
+            renderer.send_lights_to_shader(self.shader.program);
 
             // transformation time!
             let camera_projection = renderer.camera.as_mut().unwrap().get_projection();
@@ -105,11 +95,10 @@ impl Terrain {
             UniformMatrix4fv(model_loc, 1, FALSE as GLboolean, model_matrix.as_ptr());
 
             // send the camera position to the shader
+            // (the eye, as the camera's position is stored inverted)
             let camera_pos_loc = GetUniformLocation(self.shader.program, CString::new("u_camera_pos").unwrap().as_ptr());
-            Uniform3f(camera_pos_loc,
-                        renderer.camera.as_mut().unwrap().get_position().x,
-                        renderer.camera.as_mut().unwrap().get_position().y,
-                        renderer.camera.as_mut().unwrap().get_position().z);
+            let eye = renderer.camera.as_ref().unwrap().get_eye();
+            Uniform3f(camera_pos_loc, eye.x, eye.y, eye.z);
 
             DrawElements(TRIANGLES, self.mesh.num_indices as GLsizei, UNSIGNED_INT, null());
 
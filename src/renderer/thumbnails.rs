@@ -0,0 +1,392 @@
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use gfx_maths::{Quaternion, Vec2, Vec3};
+use glad_gl::gl::*;
+use gtk::glib;
+use crate::renderer::camera::Camera;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::light::Light;
+use crate::renderer::viewports::ShadingMode;
+use crate::worldmachine::components::{COMPONENT_TYPE_MESH_RENDERER, COMPONENT_TYPE_TRANSFORM};
+use crate::worldmachine::ecs::{EntityDef, ParameterValue};
+use crate::worldmachine::formats;
+
+// thumbnails are rendered with the normal mesh rendering path into an offscreen framebuffer, so they work the same
+// whether we're drawing into a GtkGLArea or a headless (e.g. software) context. they're cached as pngs in the user's
+// cache directory, named after a hash of the source file's path and its modification time so that editing the source
+// makes a new one
+
+pub const THUMBNAIL_SIZE: i32 = 128;
+
+#[derive(Clone, Debug)]
+pub enum ThumbnailSource {
+    EntityDef { name: String, path: String },
+    Mesh { name: String, path: String },
+}
+
+impl ThumbnailSource {
+    pub fn name(&self) -> &str {
+        match self {
+            ThumbnailSource::EntityDef { name, .. } => name,
+            ThumbnailSource::Mesh { name, .. } => name,
+        }
+    }
+
+    pub fn source_path(&self) -> &str {
+        match self {
+            ThumbnailSource::EntityDef { path, .. } => path,
+            ThumbnailSource::Mesh { path, .. } => path,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ThumbnailSource::EntityDef { .. } => "edef",
+            ThumbnailSource::Mesh { .. } => "mesh",
+        }
+    }
+
+    // everything up to the modification time, used to find older thumbnails of the same source. the name is only
+    // there to make the cache readable, it's the hash of the whole path that keeps sources apart
+    fn cache_prefix(&self) -> String {
+        let name = self.name().replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-', "_");
+        format!("{}_{}_{:016x}_", self.kind(), name, path_hash(self.source_path()))
+    }
+
+    pub fn cache_path(&self) -> Option<PathBuf> {
+        let modified = std::fs::metadata(self.source_path()).ok()?.modified().ok()?;
+        let modified = modified.duration_since(UNIX_EPOCH).ok()?;
+        Some(thumbnail_cache_dir().join(format!("{}{}.{:09}.png", self.cache_prefix(), modified.as_secs(), modified.subsec_nanos())))
+    }
+}
+
+// sent back to whoever asked for a thumbnail once it's been rendered
+#[derive(Clone, Debug)]
+pub struct ThumbnailReady {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub struct ThumbnailRequest {
+    pub source: ThumbnailSource,
+    pub sender: glib::Sender<ThumbnailReady>,
+}
+
+// fnv-1a, which unlike std's hasher is guaranteed to give the same answer in every build, so the cache survives updates
+fn path_hash(path: &str) -> u64 {
+    path.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// whether file_name is "<prefix><secs>.<nanos>.png", as cache_path names them
+fn is_thumbnail_of(file_name: &str, prefix: &str) -> bool {
+    let modified = match file_name.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(".png")) {
+        Some(modified) => modified,
+        None => return false,
+    };
+    match modified.split_once('.') {
+        Some((secs, nanos)) => {
+            !secs.is_empty() && secs.bytes().all(|b| b.is_ascii_digit()) && nanos.len() == 9 && nanos.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+pub fn thumbnail_cache_dir() -> PathBuf {
+    glib::user_cache_dir().join("h2eck").join("thumbnails")
+}
+
+// the thumbnail for source, if it's already been rendered for the current version of the file
+pub fn cached_thumbnail(source: &ThumbnailSource) -> Option<PathBuf> {
+    let path = source.cache_path()?;
+    if path.exists() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+// removes thumbnails of older versions of the source
+fn remove_stale_thumbnails(source: &ThumbnailSource, current: &Path) {
+    let prefix = source.cache_prefix();
+    let entries = match std::fs::read_dir(thumbnail_cache_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if is_thumbnail_of(&file_name, &prefix) && path != current {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// a mesh to draw in a thumbnail, along with the transform it should be drawn with
+struct ThumbnailMesh {
+    mesh: String,
+    shader: String,
+    texture: String,
+    position: Vec3,
+    rotation: Quaternion,
+    scale: Vec3,
+}
+
+fn string_parameter(value: Option<&ParameterValue>) -> Option<String> {
+    match value {
+        Some(ParameterValue::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn meshes_for_source(source: &ThumbnailSource) -> Result<Vec<ThumbnailMesh>, String> {
+    match source {
+        ThumbnailSource::Mesh { name, .. } => Ok(vec![ThumbnailMesh {
+            mesh: name.clone(),
+            shader: "basic".to_string(),
+            texture: "default".to_string(),
+            position: Vec3::zero(),
+            rotation: Quaternion::identity(),
+            scale: Vec3::one(),
+        }]),
+        ThumbnailSource::EntityDef { path, .. } => {
+            let entity_def: EntityDef = formats::read(path).map_err(|e| e.to_string())?;
+            let mut meshes = Vec::new();
+            let transform = entity_def.components.iter().find(|c| c.component_type.name == COMPONENT_TYPE_TRANSFORM.name);
+            for component in entity_def.components.iter().filter(|c| c.component_type.name == COMPONENT_TYPE_MESH_RENDERER.name) {
+                let parameter = |name: &str| component.parameters.get(name).map(|p| &p.value);
+                let mesh = match string_parameter(parameter("mesh")) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+                let mut thumbnail_mesh = ThumbnailMesh {
+                    mesh,
+                    shader: string_parameter(parameter("shader")).unwrap_or_else(|| "basic".to_string()),
+                    texture: string_parameter(parameter("texture")).unwrap_or_else(|| "default".to_string()),
+                    position: Vec3::zero(),
+                    rotation: Quaternion::identity(),
+                    scale: Vec3::one(),
+                };
+                if let Some(transform) = transform {
+                    if let Some(ParameterValue::Vec3(position)) = transform.parameters.get("position").map(|p| &p.value) {
+                        thumbnail_mesh.position = *position;
+                    }
+                    if let Some(ParameterValue::Quaternion(rotation)) = transform.parameters.get("rotation").map(|p| &p.value) {
+                        thumbnail_mesh.rotation = *rotation;
+                    }
+                    if let Some(ParameterValue::Vec3(scale)) = transform.parameters.get("scale").map(|p| &p.value) {
+                        thumbnail_mesh.scale = *scale;
+                    }
+                }
+                meshes.push(thumbnail_mesh);
+            }
+            if meshes.is_empty() {
+                return Err(format!("{} has no meshes to render", path));
+            }
+            Ok(meshes)
+        }
+    }
+}
+
+// offscreen framebuffer the thumbnails get drawn into, created the first time a thumbnail is needed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThumbnailFramebuffer {
+    pub framebuffer: GLuint,
+    pub texture: GLuint,
+    pub renderbuffer: GLuint,
+}
+
+impl ThumbnailFramebuffer {
+    fn new() -> Result<Self, String> {
+        unsafe {
+            let mut previous_framebuffer = 0;
+            GetIntegerv(FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            let mut framebuffer = 0;
+            GenFramebuffers(1, &mut framebuffer);
+            BindFramebuffer(FRAMEBUFFER, framebuffer);
+            let mut texture = 0;
+            GenTextures(1, &mut texture);
+            BindTexture(TEXTURE_2D, texture);
+            TexImage2D(TEXTURE_2D, 0, RGBA8 as i32, THUMBNAIL_SIZE, THUMBNAIL_SIZE, 0, RGBA, UNSIGNED_BYTE, std::ptr::null());
+            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+            FramebufferTexture2D(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, texture, 0);
+            let mut renderbuffer = 0;
+            GenRenderbuffers(1, &mut renderbuffer);
+            BindRenderbuffer(RENDERBUFFER, renderbuffer);
+            RenderbufferStorage(RENDERBUFFER, DEPTH24_STENCIL8, THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+            FramebufferRenderbuffer(FRAMEBUFFER, DEPTH_STENCIL_ATTACHMENT, RENDERBUFFER, renderbuffer);
+            let complete = CheckFramebufferStatus(FRAMEBUFFER) == FRAMEBUFFER_COMPLETE;
+            BindFramebuffer(FRAMEBUFFER, previous_framebuffer as GLuint);
+            if !complete {
+                return Err("thumbnail framebuffer is not complete".to_string());
+            }
+            Ok(Self { framebuffer, texture, renderbuffer })
+        }
+    }
+}
+
+// a camera looking at the given bounds from above and to the side, far enough away that all of it fits
+fn framing_camera(min: Vec3, max: Vec3) -> Camera {
+    let center = (min + max) * 0.5;
+    let radius = ((max - min).magnitude() * 0.5).max(0.01);
+    let fov: f32 = 45.0;
+    let distance = radius / (fov.to_radians() * 0.5).sin() * 1.1;
+    let mut camera = Camera::new(Vec2::new(THUMBNAIL_SIZE as f32, THUMBNAIL_SIZE as f32), fov, distance * 0.01, distance + radius * 4.0);
+    let yaw = 45.0f32.to_radians();
+    let pitch = 30.0f32.to_radians();
+    camera.set_rotation(Quaternion::from_euler_angles_zyx(&Vec3::new(pitch, 0.0, 0.0)) * Quaternion::from_euler_angles_zyx(&Vec3::new(0.0, yaw, 0.0)));
+    // the camera looks down -front, and its position is stored inverted
+    let eye = center + camera.get_front() * distance;
+    camera.set_position(-eye);
+    camera
+}
+
+// puts a capability back how it was, for restoring state read with IsEnabled
+unsafe fn set_enabled(capability: GLenum, enabled: GLboolean) {
+    if enabled == TRUE {
+        Enable(capability);
+    } else {
+        Disable(capability);
+    }
+}
+
+impl H2eckRenderer {
+    // renders source into a png in the cache (if it isn't there already) and returns its path
+    pub fn thumbnail(&mut self, source: &ThumbnailSource) -> Result<PathBuf, String> {
+        let path = source.cache_path().ok_or(format!("{} does not exist", source.source_path()))?;
+        if path.exists() {
+            return Ok(path);
+        }
+        let meshes = meshes_for_source(source)?;
+        let image = self.render_thumbnail(&meshes)?;
+        std::fs::create_dir_all(thumbnail_cache_dir()).map_err(|e| format!("failed to create thumbnail cache: {}", e))?;
+        image.save(&path).map_err(|e| format!("failed to save thumbnail {}: {}", path.display(), e))?;
+        remove_stale_thumbnails(source, &path);
+        debug!("rendered thumbnail for {} to {}", source.name(), path.display());
+        Ok(path)
+    }
+
+    fn render_thumbnail(&mut self, meshes: &[ThumbnailMesh]) -> Result<image::RgbaImage, String> {
+        // make sure everything's loaded and work out how much space it takes up
+        let mut to_render = Vec::new();
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+        for thumbnail_mesh in meshes {
+            self.load_mesh_if_not_already_loaded(&thumbnail_mesh.mesh).map_err(|e| format!("failed to load mesh {}: {:?}", thumbnail_mesh.mesh, e))?;
+            if self.load_texture_if_not_already_loaded(&thumbnail_mesh.texture).is_err() {
+                warn!("failed to load texture {} for thumbnail, using default", thumbnail_mesh.texture);
+            }
+            let mut mesh = *self.meshes.as_ref().unwrap().get(&thumbnail_mesh.mesh).unwrap();
+            mesh.position = thumbnail_mesh.position;
+            mesh.rotation = thumbnail_mesh.rotation;
+            mesh.scale = thumbnail_mesh.scale;
+            // rotation is ignored here, the bounds only need to be roughly right to frame the shot
+            for corner in [mesh.top_left, mesh.bottom_right] {
+                let corner = Vec3::new(corner.x * mesh.scale.x, corner.y * mesh.scale.y, corner.z * mesh.scale.z) + mesh.position;
+                min = Vec3::new(min.x.min(corner.x), min.y.min(corner.y), min.z.min(corner.z));
+                max = Vec3::new(max.x.max(corner.x), max.y.max(corner.y), max.z.max(corner.z));
+            }
+            let texture = self.textures.as_ref().unwrap().get(&thumbnail_mesh.texture)
+                .or_else(|| self.textures.as_ref().unwrap().get("default")).copied();
+            let shader = self.shaders.as_ref().unwrap().get(&thumbnail_mesh.shader)
+                .or_else(|| self.shaders.as_ref().unwrap().get("basic")).cloned()
+                .ok_or(format!("shader {} not loaded", thumbnail_mesh.shader))?;
+            to_render.push((mesh, shader, texture));
+        }
+
+        if self.thumbnail_framebuffer.is_none() {
+            self.thumbnail_framebuffer = Some(ThumbnailFramebuffer::new()?);
+        }
+        let framebuffer = self.thumbnail_framebuffer.unwrap();
+
+        // swap in our own camera and lighting, everything else is shared with the editor view
+        let camera = framing_camera(min, max);
+        let eye = -camera.get_position();
+        let old_camera = std::mem::replace(&mut self.camera, Some(camera));
+        let old_lights = std::mem::replace(&mut self.lights, vec![Light::new(
+            eye + Vec3::new(0.0, (max - min).magnitude(), 0.0),
+            Vec3::one(),
+            1.0,
+        )]);
+        // thumbnails are always lit, whatever the active pane is showing
+        let old_shading = std::mem::replace(&mut self.shading, ShadingMode::Lit);
+
+        let mut pixels = vec![0u8; (THUMBNAIL_SIZE * THUMBNAIL_SIZE * 4) as usize];
+        unsafe {
+            let mut previous_framebuffer = 0;
+            GetIntegerv(FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+            let mut previous_viewport = [0i32; 4];
+            GetIntegerv(VIEWPORT, previous_viewport.as_mut_ptr());
+            // and the rest of the state we change, thumbnails can be rendered in the middle of the editor's frame
+            let previous_cull_face = IsEnabled(CULL_FACE);
+            let mut previous_cull_face_mode = 0;
+            GetIntegerv(CULL_FACE_MODE, &mut previous_cull_face_mode);
+            let previous_depth_test = IsEnabled(DEPTH_TEST);
+            let mut previous_depth_func = 0;
+            GetIntegerv(DEPTH_FUNC, &mut previous_depth_func);
+            let previous_srgb = IsEnabled(FRAMEBUFFER_SRGB);
+            let mut previous_clear_colour = [0.0f32; 4];
+            GetFloatv(COLOR_CLEAR_VALUE, previous_clear_colour.as_mut_ptr());
+
+            BindFramebuffer(FRAMEBUFFER, framebuffer.framebuffer);
+            Viewport(0, 0, THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+            Enable(CULL_FACE);
+            CullFace(FRONT);
+            Enable(DEPTH_TEST);
+            DepthFunc(LESS);
+            Disable(FRAMEBUFFER_SRGB);
+            // transparent background so the thumbnails sit nicely on whatever the ui theme is
+            ClearColor(0.0, 0.0, 0.0, 0.0);
+            Clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT | STENCIL_BUFFER_BIT);
+
+            for (mesh, shader, texture) in to_render.iter() {
+                mesh.render(self, shader, texture.as_ref());
+            }
+
+            ReadPixels(0, 0, THUMBNAIL_SIZE, THUMBNAIL_SIZE, RGBA, UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut _);
+
+            BindFramebuffer(FRAMEBUFFER, previous_framebuffer as GLuint);
+            Viewport(previous_viewport[0], previous_viewport[1], previous_viewport[2], previous_viewport[3]);
+            set_enabled(CULL_FACE, previous_cull_face);
+            CullFace(previous_cull_face_mode as GLenum);
+            set_enabled(DEPTH_TEST, previous_depth_test);
+            DepthFunc(previous_depth_func as GLenum);
+            set_enabled(FRAMEBUFFER_SRGB, previous_srgb);
+            ClearColor(previous_clear_colour[0], previous_clear_colour[1], previous_clear_colour[2], previous_clear_colour[3]);
+        }
+
+        self.camera = old_camera;
+        self.lights = old_lights;
+        self.shading = old_shading;
+
+        // opengl's origin is the bottom left, images start at the top
+        let row = (THUMBNAIL_SIZE * 4) as usize;
+        let flipped = pixels.chunks(row).rev().flatten().copied().collect::<Vec<u8>>();
+        image::RgbaImage::from_raw(THUMBNAIL_SIZE as u32, THUMBNAIL_SIZE as u32, flipped).ok_or("failed to read thumbnail pixels".to_string())
+    }
+
+    // renders a few queued thumbnails per frame so that opening the picker doesn't freeze the editor
+    pub fn process_thumbnail_requests(&mut self, max: usize) {
+        for _ in 0..max {
+            let request = match self.thumbnail_requests.pop_front() {
+                Some(request) => request,
+                None => return,
+            };
+            match self.thumbnail(&request.source) {
+                Ok(path) => {
+                    let _ = request.sender.send(ThumbnailReady { name: request.source.name().to_string(), path });
+                }
+                Err(e) => warn!("failed to render thumbnail for {}: {}", request.source.name(), e),
+            }
+        }
+    }
+
+    // thumbnails that are already cached are sent straight back, the rest get rendered over the next few frames
+    pub fn request_thumbnail(&mut self, source: ThumbnailSource, sender: glib::Sender<ThumbnailReady>) {
+        if let Some(path) = cached_thumbnail(&source) {
+            let _ = sender.send(ThumbnailReady { name: source.name().to_string(), path });
+            return;
+        }
+        self.thumbnail_requests.push_back(ThumbnailRequest { source, sender });
+    }
+}
@@ -0,0 +1,204 @@
+use gfx_maths::Vec2;
+use glad_gl::gl::*;
+use crate::renderer::camera::{Camera, CameraMode, CameraView};
+use crate::renderer::H2eckRenderer;
+use crate::worldmachine::WorldMachine;
+
+// the viewport can be split into panes, each with its own camera, camera mode and shading. they're all drawn into the
+// one glarea (and so the one gl context, which is what lets them share meshes, textures and shaders) by setting the
+// viewport and scissor to each pane in turn.
+//
+// the active pane's state lives in H2eckRenderer::camera, camera_mode and shading, so everything that uses those
+// (input, picking, the gizmo) works on whichever pane the mouse is over. the other panes keep theirs in here
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadingMode {
+    Lit,
+    Unlit, // just the textures
+    Wireframe,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViewportLayout {
+    Single,
+    Split, // two side by side
+    Quad,
+}
+
+impl ViewportLayout {
+    pub fn pane_count(&self) -> usize {
+        match self {
+            ViewportLayout::Single => 1,
+            ViewportLayout::Split => 2,
+            ViewportLayout::Quad => 4,
+        }
+    }
+}
+
+// pixels between panes
+const PANE_GAP: i32 = 2;
+
+// what each pane starts out looking at, the first one is the main camera
+const PANE_VIEWS: [CameraView; 4] = [CameraView::Perspective, CameraView::Top, CameraView::Front, CameraView::Side];
+
+pub struct Viewport {
+    pub camera: Option<Camera>, // None while this is the active pane, its camera is in H2eckRenderer::camera
+    pub camera_mode: CameraMode,
+    pub shading: ShadingMode,
+    // in pixels of the scene, from the bottom left
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x as f32 && y >= self.y as f32 && x < (self.x + self.width) as f32 && y < (self.y + self.height) as f32
+    }
+}
+
+impl H2eckRenderer {
+    // creates the panes, the first of which takes over the renderer's camera. should be called from initialise
+    pub(crate) fn setup_viewports(&mut self, width: i32, height: i32) {
+        self.scene_size = (width, height);
+        self.viewports = PANE_VIEWS.iter().enumerate().map(|(i, camera_view)| {
+            let camera = if i == 0 {
+                None
+            } else {
+                let mut camera = Camera::new(Vec2::new(width as f32, height as f32), 90.0, 0.1, 10000.0);
+                camera.set_camera_view(*camera_view);
+                Some(camera)
+            };
+            Viewport { camera, camera_mode: CameraMode::Fly, shading: self.shading, x: 0, y: 0, width, height }
+        }).collect();
+        self.active_viewport = 0;
+        self.layout_viewports();
+    }
+
+    // swaps the pane's camera, camera mode and shading with the renderer's
+    fn swap_viewport(&mut self, index: usize) {
+        let viewport = &mut self.viewports[index];
+        std::mem::swap(&mut self.camera, &mut viewport.camera);
+        std::mem::swap(&mut self.camera_mode, &mut viewport.camera_mode);
+        std::mem::swap(&mut self.shading, &mut viewport.shading);
+    }
+
+    // works out where each pane goes, and gives its camera the right aspect ratio
+    fn layout_viewports(&mut self) {
+        let (width, height) = self.scene_size;
+        let half_width = (width - PANE_GAP) / 2;
+        let half_height = (height - PANE_GAP) / 2;
+        let rects = match self.viewport_layout {
+            ViewportLayout::Single => vec![(0, 0, width, height)],
+            ViewportLayout::Split => vec![(0, 0, half_width, height), (half_width + PANE_GAP, 0, width - half_width - PANE_GAP, height)],
+            // the first pane is in the top left
+            ViewportLayout::Quad => vec![
+                (0, half_height + PANE_GAP, half_width, height - half_height - PANE_GAP),
+                (half_width + PANE_GAP, half_height + PANE_GAP, width - half_width - PANE_GAP, height - half_height - PANE_GAP),
+                (0, 0, half_width, half_height),
+                (half_width + PANE_GAP, 0, width - half_width - PANE_GAP, half_height),
+            ],
+        };
+        for (i, (x, y, width, height)) in rects.into_iter().enumerate() {
+            let size = Vec2::new(width as f32, height as f32);
+            let viewport = &mut self.viewports[i];
+            viewport.x = x;
+            viewport.y = y;
+            viewport.width = width;
+            viewport.height = height;
+            match viewport.camera.as_mut() {
+                Some(camera) => camera.set_window_size(size),
+                None => self.camera.as_mut().unwrap().set_window_size(size),
+            }
+        }
+    }
+
+    pub fn set_viewport_layout(&mut self, layout: ViewportLayout) {
+        if self.viewports.is_empty() {
+            self.viewport_layout = layout;
+            return;
+        }
+        if self.active_viewport >= layout.pane_count() {
+            self.set_active_viewport(0);
+        }
+        self.viewport_layout = layout;
+        self.layout_viewports();
+    }
+
+    // returns whether the active pane changed
+    pub fn set_active_viewport(&mut self, index: usize) -> bool {
+        if index == self.active_viewport || index >= self.viewports.len() {
+            return false;
+        }
+        let active = self.active_viewport;
+        self.stop_camera();
+        self.swap_viewport(active);
+        self.swap_viewport(index);
+        self.active_viewport = index;
+        true
+    }
+
+    // the pane at the given pixel of the scene, with the origin in the bottom left
+    pub fn viewport_at(&self, x: f32, y: f32) -> Option<usize> {
+        self.viewports.iter().take(self.viewport_layout.pane_count()).position(|viewport| viewport.contains(x, y))
+    }
+
+    // makes the pane under the mouse the active one, unless something's being dragged in the current one. returns
+    // whether the active pane changed
+    pub fn hover_viewport(&mut self, x: f32, y: f32) -> bool {
+        if self.camera_can_move || self.gizmo.is_dragging() {
+            return false;
+        }
+        match self.viewport_at(x, y) {
+            Some(index) => self.set_active_viewport(index),
+            None => false,
+        }
+    }
+
+    // turns a pixel of the scene into a pixel of the active pane, which is what picking and rays work in
+    pub fn viewport_coords(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.viewports.get(self.active_viewport) {
+            Some(viewport) => (x - viewport.x as f32, y - viewport.y as f32),
+            None => (x, y),
+        }
+    }
+
+    // the active pane's view, camera mode and shading, for the toolbar
+    pub fn active_viewport_state(&self) -> Option<(CameraView, CameraMode, ShadingMode)> {
+        let camera = self.camera.as_ref()?;
+        Some((camera.get_camera_view(), self.camera_mode, self.shading))
+    }
+
+    // draws every visible pane into whatever framebuffer is bound
+    pub(crate) fn render_viewports(&mut self, worldmachine: &mut WorldMachine) {
+        let (width, height) = self.scene_size;
+        unsafe {
+            // the gaps between panes
+            Viewport(0, 0, width, height);
+            ClearColor(0.0, 0.0, 0.0, 1.0);
+            Clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT | STENCIL_BUFFER_BIT);
+        }
+        // put the active pane back with the others, then take each one out in turn
+        let active = self.active_viewport;
+        self.swap_viewport(active);
+        for i in 0..self.viewport_layout.pane_count() {
+            self.swap_viewport(i);
+            let viewport = &self.viewports[i];
+            unsafe {
+                Viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+                Enable(SCISSOR_TEST);
+                Scissor(viewport.x, viewport.y, viewport.width, viewport.height);
+            }
+            self.normal_scene_render(worldmachine);
+            unsafe {
+                Disable(SCISSOR_TEST);
+            }
+            self.swap_viewport(i);
+        }
+        self.swap_viewport(active);
+        unsafe {
+            Viewport(0, 0, width, height);
+        }
+    }
+}
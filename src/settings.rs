@@ -0,0 +1,320 @@
+use std::sync::Mutex;
+use gtk::gio;
+use gtk::gio::prelude::*;
+
+// note: the schema id has always been spelled like this, don't "fix" it or everyone's settings will disappear
+pub const SETTINGS_SCHEMA_ID: &str = "com.realmicrosft.h2eck";
+
+pub const KEY_DATA_LOCATION: &str = "data-location";
+pub const KEY_BINARY_LOCATION: &str = "binary-location";
+pub const KEY_TOOLS_LOCATION: &str = "tools-location";
+pub const KEY_COMPILER: &str = "compiler";
+pub const KEY_AUTOSAVE_INTERVAL: &str = "autosave-interval";
+pub const KEY_BACKUP_COUNT: &str = "backup-count";
+pub const KEY_RECENT_MAPS: &str = "recent-maps";
+pub const KEY_COMPACT_MAPS: &str = "compact-maps";
+pub const KEY_GRID_SIZE: &str = "grid-size";
+pub const KEY_ROTATION_SNAP: &str = "rotation-snap";
+pub const KEY_SCALE_SNAP: &str = "scale-snap";
+pub const KEY_FLY_SPEED: &str = "fly-speed";
+pub const KEY_MOUSE_SENSITIVITY: &str = "mouse-sensitivity";
+
+pub const ENV_DATA_LOCATION: &str = "H2ECK_DATA_LOCATION";
+pub const ENV_BINARY_LOCATION: &str = "H2ECK_BINARY_LOCATION";
+pub const ENV_TOOLS_LOCATION: &str = "H2ECK_TOOLS_LOCATION";
+
+// used if the schema isn't installed (e.g. compile_settings_schema.bat hasn't been run yet)
+const DEFAULT_DATA_LOCATION: &str = "/opt/huskyTech2/base";
+const DEFAULT_BINARY_LOCATION: &str = "/opt/huskyTech2/huskyTech2";
+const DEFAULT_TOOLS_LOCATION: &str = "/opt/huskyTech2/tools";
+const DEFAULT_AUTOSAVE_INTERVAL: u32 = 120;
+const DEFAULT_BACKUP_COUNT: u32 = 3;
+const DEFAULT_GRID_SIZE: f64 = 1.0;
+const DEFAULT_ROTATION_SNAP: f64 = 15.0;
+const DEFAULT_SCALE_SNAP: f64 = 0.25;
+const DEFAULT_FLY_SPEED: f64 = 5.0;
+const DEFAULT_MOUSE_SENSITIVITY: f64 = 1.0;
+
+const MAX_RECENT_MAPS: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapCompiler {
+    BuiltIn,
+    External, // mapc from tools-location
+}
+
+impl MapCompiler {
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "mapc" => MapCompiler::External,
+            _ => MapCompiler::BuiltIn,
+        }
+    }
+
+    // the value stored in gsettings (and the id used in the preferences window)
+    pub fn id(&self) -> &'static str {
+        match self {
+            MapCompiler::BuiltIn => "builtin",
+            MapCompiler::External => "mapc",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SettingSource {
+    Schema,
+    Default,
+    Environment,
+    CommandLine,
+}
+
+#[derive(Clone, Debug)]
+pub struct Setting {
+    pub value: String,
+    pub source: SettingSource,
+}
+
+#[derive(Clone, Debug)]
+pub struct H2eckSettings {
+    pub data_location: Setting,
+    pub binary_location: Setting,
+    pub tools_location: Setting,
+    pub compiler: MapCompiler,
+    pub autosave_interval: u32, // seconds, 0 turns autosave off
+    pub backup_count: u32,
+    pub compact_maps: bool, // write new maps in the compact layout
+    pub grid_size: f64, // spacing of the grid lines, and what positions snap to
+    pub rotation_snap: f64, // degrees
+    pub scale_snap: f64,
+    pub fly_speed: f64, // units per second
+    pub mouse_sensitivity: f64, // multiplies how far dragging turns the camera
+}
+
+// what the preferences window edits, exactly as stored in gsettings (no overrides applied)
+#[derive(Clone, Debug)]
+pub struct StoredSettings {
+    pub data_location: String,
+    pub binary_location: String,
+    pub tools_location: String,
+    pub compiler: MapCompiler,
+    pub autosave_interval: u32,
+    pub backup_count: u32,
+    pub compact_maps: bool,
+    pub grid_size: f64,
+    pub rotation_snap: f64,
+    pub scale_snap: f64,
+    pub fly_speed: f64,
+    pub mouse_sensitivity: f64,
+}
+
+// values passed on the command line, these win over everything else
+#[derive(Clone, Debug, Default)]
+pub struct CommandLineOverrides {
+    pub data_location: Option<String>,
+    pub binary_location: Option<String>,
+    pub tools_location: Option<String>,
+}
+
+lazy_static! {
+    static ref COMMAND_LINE_OVERRIDES: Mutex<CommandLineOverrides> = Mutex::new(CommandLineOverrides::default());
+    pub static ref SETTINGS: Mutex<H2eckSettings> = Mutex::new(H2eckSettings::load());
+}
+
+// returns None if the schema isn't installed, as gio::Settings::new would abort the whole program
+pub fn gsettings() -> Option<gio::Settings> {
+    let source = gio::SettingsSchemaSource::default()?;
+    let schema = source.lookup(SETTINGS_SCHEMA_ID, true)?;
+    Some(gio::Settings::new_full(&schema, None::<&gio::SettingsBackend>, None))
+}
+
+fn resolve(key: &str, env: &str, default: &str, command_line: Option<String>, gsettings: Option<&gio::Settings>) -> Setting {
+    if let Some(value) = command_line {
+        return Setting { value, source: SettingSource::CommandLine };
+    }
+    if let Ok(value) = std::env::var(env) {
+        if !value.is_empty() {
+            return Setting { value, source: SettingSource::Environment };
+        }
+    }
+    if let Some(gsettings) = gsettings {
+        return Setting { value: gsettings.string(key).to_string(), source: SettingSource::Schema };
+    }
+    Setting { value: default.to_string(), source: SettingSource::Default }
+}
+
+impl H2eckSettings {
+    pub fn load() -> Self {
+        let gsettings = gsettings();
+        if gsettings.is_none() {
+            warn!("settings schema {} is not installed, using default paths", SETTINGS_SCHEMA_ID);
+        }
+        let overrides = COMMAND_LINE_OVERRIDES.lock().unwrap().clone();
+        Self {
+            data_location: resolve(KEY_DATA_LOCATION, ENV_DATA_LOCATION, DEFAULT_DATA_LOCATION, overrides.data_location, gsettings.as_ref()),
+            binary_location: resolve(KEY_BINARY_LOCATION, ENV_BINARY_LOCATION, DEFAULT_BINARY_LOCATION, overrides.binary_location, gsettings.as_ref()),
+            tools_location: resolve(KEY_TOOLS_LOCATION, ENV_TOOLS_LOCATION, DEFAULT_TOOLS_LOCATION, overrides.tools_location, gsettings.as_ref()),
+            compiler: gsettings.as_ref().map(|g| MapCompiler::from_id(g.string(KEY_COMPILER).as_str())).unwrap_or(MapCompiler::BuiltIn),
+            autosave_interval: gsettings.as_ref().map(|g| g.uint(KEY_AUTOSAVE_INTERVAL)).unwrap_or(DEFAULT_AUTOSAVE_INTERVAL),
+            backup_count: gsettings.as_ref().map(|g| g.uint(KEY_BACKUP_COUNT)).unwrap_or(DEFAULT_BACKUP_COUNT),
+            compact_maps: gsettings.as_ref().map(|g| g.boolean(KEY_COMPACT_MAPS)).unwrap_or(false),
+            grid_size: gsettings.as_ref().map(|g| g.double(KEY_GRID_SIZE)).unwrap_or(DEFAULT_GRID_SIZE),
+            rotation_snap: gsettings.as_ref().map(|g| g.double(KEY_ROTATION_SNAP)).unwrap_or(DEFAULT_ROTATION_SNAP),
+            scale_snap: gsettings.as_ref().map(|g| g.double(KEY_SCALE_SNAP)).unwrap_or(DEFAULT_SCALE_SNAP),
+            fly_speed: gsettings.as_ref().map(|g| g.double(KEY_FLY_SPEED)).unwrap_or(DEFAULT_FLY_SPEED),
+            mouse_sensitivity: gsettings.as_ref().map(|g| g.double(KEY_MOUSE_SENSITIVITY)).unwrap_or(DEFAULT_MOUSE_SENSITIVITY),
+        }
+    }
+}
+
+impl StoredSettings {
+    pub fn load() -> Option<Self> {
+        let gsettings = gsettings()?;
+        Some(Self {
+            data_location: gsettings.string(KEY_DATA_LOCATION).to_string(),
+            binary_location: gsettings.string(KEY_BINARY_LOCATION).to_string(),
+            tools_location: gsettings.string(KEY_TOOLS_LOCATION).to_string(),
+            compiler: MapCompiler::from_id(gsettings.string(KEY_COMPILER).as_str()),
+            autosave_interval: gsettings.uint(KEY_AUTOSAVE_INTERVAL),
+            backup_count: gsettings.uint(KEY_BACKUP_COUNT),
+            compact_maps: gsettings.boolean(KEY_COMPACT_MAPS),
+            grid_size: gsettings.double(KEY_GRID_SIZE),
+            rotation_snap: gsettings.double(KEY_ROTATION_SNAP),
+            scale_snap: gsettings.double(KEY_SCALE_SNAP),
+            fly_speed: gsettings.double(KEY_FLY_SPEED),
+            mouse_sensitivity: gsettings.double(KEY_MOUSE_SENSITIVITY),
+        })
+    }
+
+    // writes everything to gsettings; paths that are currently overridden are still saved,
+    // they just won't take effect until the override goes away
+    pub fn save(&self) -> Result<(), String> {
+        let gsettings = gsettings().ok_or(format!("settings schema {} is not installed", SETTINGS_SCHEMA_ID))?;
+        gsettings.set_string(KEY_DATA_LOCATION, &self.data_location).map_err(|e| e.to_string())?;
+        gsettings.set_string(KEY_BINARY_LOCATION, &self.binary_location).map_err(|e| e.to_string())?;
+        gsettings.set_string(KEY_TOOLS_LOCATION, &self.tools_location).map_err(|e| e.to_string())?;
+        gsettings.set_string(KEY_COMPILER, self.compiler.id()).map_err(|e| e.to_string())?;
+        gsettings.set_uint(KEY_AUTOSAVE_INTERVAL, self.autosave_interval).map_err(|e| e.to_string())?;
+        gsettings.set_uint(KEY_BACKUP_COUNT, self.backup_count).map_err(|e| e.to_string())?;
+        gsettings.set_boolean(KEY_COMPACT_MAPS, self.compact_maps).map_err(|e| e.to_string())?;
+        gsettings.set_double(KEY_GRID_SIZE, self.grid_size).map_err(|e| e.to_string())?;
+        gsettings.set_double(KEY_ROTATION_SNAP, self.rotation_snap).map_err(|e| e.to_string())?;
+        gsettings.set_double(KEY_SCALE_SNAP, self.scale_snap).map_err(|e| e.to_string())?;
+        gsettings.set_double(KEY_FLY_SPEED, self.fly_speed).map_err(|e| e.to_string())?;
+        gsettings.set_double(KEY_MOUSE_SENSITIVITY, self.mouse_sensitivity).map_err(|e| e.to_string())?;
+        gio::Settings::sync();
+        reload();
+        Ok(())
+    }
+}
+
+pub fn set_command_line_overrides(overrides: CommandLineOverrides) {
+    *COMMAND_LINE_OVERRIDES.lock().unwrap() = overrides;
+    reload();
+}
+
+pub fn reload() {
+    *SETTINGS.lock().unwrap() = H2eckSettings::load();
+}
+
+pub fn data_location() -> String {
+    SETTINGS.lock().unwrap().data_location.value.clone()
+}
+
+pub fn binary_location() -> String {
+    SETTINGS.lock().unwrap().binary_location.value.clone()
+}
+
+pub fn tools_location() -> String {
+    SETTINGS.lock().unwrap().tools_location.value.clone()
+}
+
+pub fn compiler() -> MapCompiler {
+    SETTINGS.lock().unwrap().compiler
+}
+
+pub fn autosave_interval() -> u32 {
+    SETTINGS.lock().unwrap().autosave_interval
+}
+
+pub fn backup_count() -> u32 {
+    SETTINGS.lock().unwrap().backup_count
+}
+
+pub fn compact_maps() -> bool {
+    SETTINGS.lock().unwrap().compact_maps
+}
+
+pub fn grid_size() -> f64 {
+    SETTINGS.lock().unwrap().grid_size
+}
+
+pub fn rotation_snap() -> f64 {
+    SETTINGS.lock().unwrap().rotation_snap
+}
+
+pub fn scale_snap() -> f64 {
+    SETTINGS.lock().unwrap().scale_snap
+}
+
+pub fn fly_speed() -> f64 {
+    SETTINGS.lock().unwrap().fly_speed
+}
+
+// for changing the fly speed from the viewport, the preferences window saves it along with everything else
+pub fn set_fly_speed(fly_speed: f64) {
+    SETTINGS.lock().unwrap().fly_speed = fly_speed;
+    if let Some(gsettings) = gsettings() {
+        if let Err(e) = gsettings.set_double(KEY_FLY_SPEED, fly_speed) {
+            error!("failed to save fly speed: {}", e);
+        }
+    }
+}
+
+pub fn mouse_sensitivity() -> f64 {
+    SETTINGS.lock().unwrap().mouse_sensitivity
+}
+
+// most recently opened or saved first
+pub fn recent_maps() -> Vec<String> {
+    gsettings().map(|g| g.strv(KEY_RECENT_MAPS).iter().map(|path| path.to_string()).collect()).unwrap_or_default()
+}
+
+fn set_recent_maps(maps: &[String]) {
+    let gsettings = match gsettings() {
+        Some(gsettings) => gsettings,
+        None => return,
+    };
+    let maps = maps.iter().map(|path| path.as_str()).collect::<Vec<&str>>();
+    if let Err(e) = gsettings.set_strv(KEY_RECENT_MAPS, &maps) {
+        error!("failed to save recent maps: {}", e);
+    }
+}
+
+// canonicalise so that the same map opened through different paths only shows up once. a map that no longer exists
+// can't be canonicalised itself, so the folder it was in is instead, and failing that the path is left as it is
+fn recent_map_path(path: &str) -> String {
+    let given = std::path::Path::new(path);
+    std::fs::canonicalize(given).ok()
+        .or_else(|| {
+            let parent = given.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or_else(|| std::path::Path::new("."));
+            Some(std::fs::canonicalize(parent).ok()?.join(given.file_name()?))
+        })
+        .and_then(|path| path.to_str().map(|path| path.to_string()))
+        .unwrap_or_else(|| path.to_string())
+}
+
+pub fn add_recent_map(path: &str) {
+    let path = recent_map_path(path);
+    let mut maps = recent_maps();
+    maps.retain(|map| *map != path);
+    maps.insert(0, path);
+    maps.truncate(MAX_RECENT_MAPS);
+    set_recent_maps(&maps);
+}
+
+pub fn remove_recent_map(path: &str) {
+    let path = recent_map_path(path);
+    let mut maps = recent_maps();
+    maps.retain(|map| *map != path);
+    set_recent_maps(&maps);
+}
@@ -0,0 +1,49 @@
+use gfx_maths::{Quaternion, Vec3};
+use serde::{Deserialize, Serialize};
+use crate::worldmachine::WorldMachine;
+
+// named camera positions saved with the map, like "spawn overview" or "boss arena". they're only for the editor,
+// so compile_map leaves them out of compiled maps. the number keys go to the first nine by
+// default, see KeyAction::GoToBookmark
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub name: String,
+    pub position: Vec3, // stored inverted, the same as Camera::get_position
+    pub rotation: Quaternion,
+}
+
+impl WorldMachine {
+    pub fn bookmarks(&self) -> &[CameraBookmark] {
+        &self.world.bookmarks
+    }
+
+    pub fn get_bookmark(&self, index: usize) -> Option<CameraBookmark> {
+        self.world.bookmarks.get(index).cloned()
+    }
+
+    // returns the index of the new bookmark
+    pub fn add_bookmark(&mut self, bookmark: CameraBookmark) -> usize {
+        self.world.bookmarks.push(bookmark);
+        self.mark_dirty();
+        self.regen_editor();
+        self.world.bookmarks.len() - 1
+    }
+
+    pub fn rename_bookmark(&mut self, index: usize, new_name: &str) {
+        if let Some(bookmark) = self.world.bookmarks.get_mut(index) {
+            if bookmark.name != new_name {
+                bookmark.name = new_name.to_string();
+                self.mark_dirty();
+            }
+        }
+    }
+
+    pub fn remove_bookmark(&mut self, index: usize) {
+        if index < self.world.bookmarks.len() {
+            self.world.bookmarks.remove(index);
+            self.mark_dirty();
+            self.regen_editor();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use gtk::glib;
+use crate::settings;
+
+// runs the external mapc tool and turns its output into something the editor can show
+//
+// mapc prints one message per line, in one of these forms:
+//   progress: <done>/<total> <what it's doing>
+//   <file>:<line>[:<column>]: <severity>: [entity <uid>:] <message>
+//   <severity>: <message>
+// where severity is error, warning, info or note. anything else gets passed through as info
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub entity: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum CompilerMessage {
+    Progress { done: u32, total: u32, stage: String },
+    Diagnostic(Diagnostic),
+    Finished(Result<(), String>),
+}
+
+const SEVERITIES: [(&str, Severity); 4] = [
+    ("error", Severity::Error),
+    ("warning", Severity::Warning),
+    ("info", Severity::Info),
+    ("note", Severity::Info),
+];
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+// splits "<file>:<line>[:<column>]: <rest>" at the first ": " that comes straight after a line number. the file might
+// itself contain colons (windows drive letters), and the message might contain anything, so this is the only safe place
+fn split_location(line: &str) -> Option<(String, u32, Option<u32>, &str)> {
+    for (index, _) in line.match_indices(": ") {
+        if let Some((file, number)) = line[..index].rsplit_once(':') {
+            if file.is_empty() {
+                continue;
+            }
+            if let Ok(number) = number.parse::<u32>() {
+                // two numbers in a row are the line and the column
+                if let Some((file, line_number)) = file.rsplit_once(':') {
+                    if let Ok(line_number) = line_number.parse::<u32>() {
+                        if !file.is_empty() {
+                            return Some((file.to_string(), line_number, Some(number), &line[index + 2..]));
+                        }
+                    }
+                }
+                return Some((file.to_string(), number, None, &line[index + 2..]));
+            }
+        }
+    }
+    None
+}
+
+// "<severity>: <rest>", if line starts with one of the severities
+fn split_severity(line: &str) -> Option<(Severity, &str)> {
+    SEVERITIES.iter().find_map(|(name, severity)| {
+        line.strip_prefix(name).and_then(|rest| rest.strip_prefix(": ")).map(|rest| (*severity, rest))
+    })
+}
+
+// splits "entity <uid>: <message>" into the uid and the message
+fn parse_entity(message: &str) -> (Option<u64>, String) {
+    if let Some(rest) = message.strip_prefix("entity ") {
+        if let Some((uid, rest)) = rest.split_once(": ") {
+            if let Ok(uid) = uid.parse::<u64>() {
+                return (Some(uid), rest.to_string());
+            }
+        }
+    }
+    (None, message.to_string())
+}
+
+pub fn parse_mapc_line(line: &str) -> CompilerMessage {
+    let line = line.trim_end();
+
+    if let Some(progress) = line.strip_prefix("progress: ") {
+        let (counts, stage) = progress.split_once(' ').unwrap_or((progress, ""));
+        if let Some((done, total)) = counts.split_once('/') {
+            if let (Ok(done), Ok(total)) = (done.parse::<u32>(), total.parse::<u32>()) {
+                return CompilerMessage::Progress { done, total, stage: stage.to_string() };
+            }
+        }
+    }
+
+    // "<severity>: <message>" without a location
+    if let Some((severity, message)) = split_severity(line) {
+        let (entity, message) = parse_entity(message);
+        return CompilerMessage::Diagnostic(Diagnostic { severity, file: None, line: None, column: None, entity, message });
+    }
+    // "<file>:<line>: <severity>: <message>", the severity has to come right after the location
+    if let Some((file, line_number, column, rest)) = split_location(line) {
+        if let Some((severity, message)) = split_severity(rest) {
+            let (entity, message) = parse_entity(message);
+            return CompilerMessage::Diagnostic(Diagnostic { severity, file: Some(file), line: Some(line_number), column, entity, message });
+        }
+    }
+
+    CompilerMessage::Diagnostic(Diagnostic {
+        severity: Severity::Info,
+        file: None,
+        line: None,
+        column: None,
+        entity: None,
+        message: line.to_string(),
+    })
+}
+
+pub fn mapc_path() -> PathBuf {
+    let name = if cfg!(target_os = "windows") { "mapc.exe" } else { "mapc" };
+    Path::new(&settings::tools_location()).join(name)
+}
+
+fn spawn_reader<R: Read + Send + 'static>(pipe: R, sender: glib::Sender<CompilerMessage>) -> std::thread::JoinHandle<bool> {
+    std::thread::spawn(move || {
+        let mut had_errors = false;
+        for line in BufReader::new(pipe).lines().flatten() {
+            let message = parse_mapc_line(&line);
+            if let CompilerMessage::Diagnostic(Diagnostic { severity: Severity::Error, .. }) = message {
+                had_errors = true;
+            }
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+        had_errors
+    })
+}
+
+// runs mapc on a saved .map in the background, everything it says (and whether it worked) is sent through sender
+pub fn run_mapc(map_path: &str, output_dir: &str, sender: glib::Sender<CompilerMessage>) -> Result<(), String> {
+    let mapc = mapc_path();
+    if !mapc.exists() {
+        return Err(format!("mapc not found at {}, check your tools location", mapc.display()));
+    }
+    std::fs::create_dir_all(output_dir).map_err(|e| format!("failed to create map directory: {}", e))?;
+
+    let mut child = Command::new(&mapc)
+        .arg(map_path)
+        .arg("-o")
+        .arg(output_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", mapc.display(), e))?;
+    info!("running {} on {}", mapc.display(), map_path);
+
+    let stdout = child.stdout.take().map(|pipe| spawn_reader(pipe, sender.clone()));
+    let stderr = child.stderr.take().map(|pipe| spawn_reader(pipe, sender.clone()));
+
+    std::thread::spawn(move || {
+        // wait for the readers first so that the diagnostics arrive before we say we're done
+        let mut had_errors = false;
+        for reader in [stdout, stderr].into_iter().flatten() {
+            had_errors |= reader.join().unwrap_or(true);
+        }
+        let result = match child.wait() {
+            Ok(status) if status.success() && !had_errors => Ok(()),
+            Ok(status) if status.success() => Err("mapc reported errors".to_string()),
+            Ok(status) => Err(format!("mapc exited with {}", status)),
+            Err(e) => Err(format!("failed to wait for mapc: {}", e)),
+        };
+        let _ = sender.send(CompilerMessage::Finished(result));
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: &str) -> Diagnostic {
+        match parse_mapc_line(line) {
+            CompilerMessage::Diagnostic(diagnostic) => diagnostic,
+            other => panic!("expected a diagnostic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn location_with_column() {
+        let d = diagnostic("maps/test.map:12:5: error: entity 4: missing collider");
+        assert_eq!(d.severity, Severity::Error);
+        assert_eq!(d.file.as_deref(), Some("maps/test.map"));
+        assert_eq!(d.line, Some(12));
+        assert_eq!(d.column, Some(5));
+        assert_eq!(d.entity, Some(4));
+        assert_eq!(d.message, "missing collider");
+    }
+
+    #[test]
+    fn severity_must_follow_location() {
+        // the message mentioning another severity doesn't change this one
+        let d = diagnostic("C:\\maps\\test.map:3: warning: foo: error: bar");
+        assert_eq!(d.severity, Severity::Warning);
+        assert_eq!(d.file.as_deref(), Some("C:\\maps\\test.map"));
+        assert_eq!(d.line, Some(3));
+        assert_eq!(d.column, None);
+        assert_eq!(d.message, "foo: error: bar");
+
+        // and a severity that isn't right after a location is just text
+        let d = diagnostic("something: error: happened");
+        assert_eq!(d.severity, Severity::Info);
+        assert_eq!(d.file, None);
+        assert_eq!(d.message, "something: error: happened");
+    }
+
+    #[test]
+    fn without_location() {
+        let d = diagnostic("note: entity 9: hello");
+        assert_eq!(d.severity, Severity::Info);
+        assert_eq!(d.file, None);
+        assert_eq!(d.entity, Some(9));
+        assert_eq!(d.message, "hello");
+    }
+
+    #[test]
+    fn progress() {
+        match parse_mapc_line("progress: 3/10 baking lights") {
+            CompilerMessage::Progress { done, total, stage } => {
+                assert_eq!((done, total, stage.as_str()), (3, 10, "baking lights"));
+            }
+            other => panic!("expected progress, got {:?}", other),
+        }
+    }
+}
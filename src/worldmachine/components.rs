@@ -75,6 +75,12 @@ impl Light {
         parameters.insert("position".to_string(), Parameter::new("position", ParameterValue::Vec3(position)));
         parameters.insert("colour".to_string(), Parameter::new("colour", ParameterValue::Vec3(colour)));
         parameters.insert("intensity".to_string(), Parameter::new("intensity", ParameterValue::Float(intensity)));
+        // point, directional or spot. direction and angle (degrees from the direction to the edge of the cone) are
+        // only for the last two
+        parameters.insert("type".to_string(), Parameter::new("type", ParameterValue::String("point".to_string())));
+        parameters.insert("direction".to_string(), Parameter::new("direction", ParameterValue::Vec3(Vec3::new(0.0, -1.0, 0.0))));
+        parameters.insert("angle".to_string(), Parameter::new("angle", ParameterValue::Float(30.0)));
+        parameters.insert("shadows".to_string(), Parameter::new("shadows", ParameterValue::Bool(false)));
 
         Component {
             name: "Light".to_string(),
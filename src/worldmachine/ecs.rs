@@ -58,6 +58,8 @@ pub struct System {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EntityDef {
     pub name: String,
+    #[serde(default)]
+    pub description: String, // shown in the entity picker, older edefs don't have one
     pub components: Vec<Component>,
 }
 
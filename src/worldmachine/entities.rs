@@ -40,10 +40,23 @@ impl Entity {
     pub fn to_entity_def(&self) -> EntityDef {
         EntityDef {
             name: self.name.clone(),
+            description: String::new(),
             components: self.components.clone(),
         }
     }
 
+    // a copy of this entity and its children, all with new uids
+    pub fn duplicate(&self, parent: Option<u64>) -> Entity {
+        let uid = ENTITY_ID_MANAGER.lock().unwrap().get_id();
+        Entity {
+            name: self.name.clone(),
+            uid,
+            components: self.components.clone(),
+            children: self.children.iter().map(|child| child.duplicate(Some(uid))).collect(),
+            parent,
+        }
+    }
+
     pub fn from_entity_def(entity_def: &EntityDef) -> Entity {
         Entity {
             name: entity_def.name.clone(),
@@ -0,0 +1,149 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use serde::de::DeserializeOwned;
+use crate::worldmachine::ecs::{Component, COMPONENT_TYPES, Entity};
+
+// errors from reading and writing .map and .edef files (in any of the formats in formats.rs), these get shown to the user instead of taking the editor down
+
+#[derive(Clone, Debug)]
+pub enum WorldFileError {
+    NotFound(String),
+    Io { path: String, message: String },
+    // the file couldn't be written, when saving
+    Write { path: String, message: String },
+    // the file isn't valid yaml
+    Parse { path: String, line: Option<usize>, column: Option<usize>, message: String },
+    // the file is valid yaml, but not something we understand (e.g. written by an older or newer h2eck)
+    SchemaMismatch { path: String, line: Option<usize>, column: Option<usize>, message: String },
+}
+
+// serde reports both syntax errors and "this isn't the structure i wanted" through the same error type,
+// these are the messages serde uses for the latter
+const SCHEMA_ERRORS: [&str; 7] = [
+    "missing field",
+    "unknown field",
+    "unknown variant",
+    "invalid type",
+    "invalid value",
+    "invalid length",
+    "duplicate field",
+];
+
+fn format_location(path: &str, line: Option<usize>, column: Option<usize>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!("{}:{}:{}", path, line, column),
+        (Some(line), None) => format!("{}:{}", path, line),
+        _ => path.to_string(),
+    }
+}
+
+impl Display for WorldFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldFileError::NotFound(path) => write!(f, "{} does not exist", path),
+            WorldFileError::Io { path, message } => write!(f, "failed to read {}: {}", path, message),
+            WorldFileError::Write { path, message } => write!(f, "failed to write {}: {}", path, message),
+            WorldFileError::Parse { path, line, column, message } => {
+                write!(f, "{}: parse error: {}", format_location(path, *line, *column), message)
+            }
+            WorldFileError::SchemaMismatch { path, line, column, message } => {
+                write!(f, "{}: unexpected contents (is it from a different version of h2eck?): {}", format_location(path, *line, *column), message)
+            }
+        }
+    }
+}
+
+impl WorldFileError {
+    // a short description for dialog titles
+    pub fn title(&self) -> &'static str {
+        match self {
+            WorldFileError::NotFound(_) => "File Not Found",
+            WorldFileError::Io { .. } => "Failed to Read File",
+            WorldFileError::Write { .. } => "Failed to Save File",
+            WorldFileError::Parse { .. } => "Failed to Parse File",
+            WorldFileError::SchemaMismatch { .. } => "Unsupported File",
+        }
+    }
+}
+
+pub fn read_file(path: &str) -> Result<String, WorldFileError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            WorldFileError::NotFound(path.to_string())
+        } else {
+            WorldFileError::Io { path: path.to_string(), message: e.to_string() }
+        }
+    })
+}
+
+pub fn parse_yaml<T: DeserializeOwned>(path: &str, contents: &str) -> Result<T, WorldFileError> {
+    serde_yaml::from_str(contents).map_err(|e| {
+        let line = e.location().map(|l| l.line());
+        let column = e.location().map(|l| l.column());
+        let message = e.to_string();
+        if SCHEMA_ERRORS.iter().any(|s| message.contains(s)) {
+            WorldFileError::SchemaMismatch { path: path.to_string(), line, column, message }
+        } else {
+            WorldFileError::Parse { path: path.to_string(), line, column, message }
+        }
+    })
+}
+
+pub fn parse_json<T: DeserializeOwned>(path: &str, contents: &str) -> Result<T, WorldFileError> {
+    serde_json::from_str(contents).map_err(|e| {
+        let line = Some(e.line());
+        let column = Some(e.column());
+        let message = e.to_string();
+        match e.classify() {
+            serde_json::error::Category::Data => WorldFileError::SchemaMismatch { path: path.to_string(), line, column, message },
+            _ => WorldFileError::Parse { path: path.to_string(), line, column, message },
+        }
+    })
+}
+
+pub fn parse_ron<T: DeserializeOwned>(path: &str, contents: &str) -> Result<T, WorldFileError> {
+    ron::from_str(contents).map_err(|e| {
+        let line = Some(e.position.line);
+        let column = Some(e.position.col);
+        let message = e.code.to_string();
+        let schema = match &e.code {
+            ron::Error::MissingStructField { .. }
+            | ron::Error::UnknownField { .. }
+            | ron::Error::DuplicateStructField { .. }
+            | ron::Error::NoSuchEnumVariant { .. }
+            | ron::Error::InvalidValueForType { .. }
+            | ron::Error::ExpectedDifferentLength { .. } => true,
+            ron::Error::Message(m) => SCHEMA_ERRORS.iter().any(|s| m.contains(s)),
+            _ => false,
+        };
+        if schema {
+            WorldFileError::SchemaMismatch { path: path.to_string(), line, column, message }
+        } else {
+            WorldFileError::Parse { path: path.to_string(), line, column, message }
+        }
+    })
+}
+
+// makes sure every component is of a type this version of h2eck knows about
+pub fn check_components(path: &str, components: &[Component]) -> Result<(), WorldFileError> {
+    let component_types = COMPONENT_TYPES.lock().unwrap();
+    for component in components {
+        if !component_types.contains_key(&component.component_type.name) {
+            return Err(WorldFileError::SchemaMismatch {
+                path: path.to_string(),
+                line: None,
+                column: None,
+                message: format!("unknown component type {} on component {}", component.component_type.name, component.name),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn check_entities(path: &str, entities: &[Entity]) -> Result<(), WorldFileError> {
+    for entity in entities {
+        check_components(path, &entity.components)?;
+        check_entities(path, &entity.children)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::worldmachine::errors;
+use crate::worldmachine::errors::WorldFileError;
+use crate::worldmachine::layout::{CompactWorld, LayoutProbe, MapLayout};
+use crate::worldmachine::World;
+use crate::worldmachine::ecs::EntityDef;
+
+// uncompiled maps and edefs can be written as yaml, json or ron. the format is picked from the last extension,
+// so "test.map" and "crate.edef" are yaml (as they always have been), "test.map.json" is json and
+// "crate.edef.ron" is ron
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Yaml,
+    Json,
+    Ron,
+}
+
+pub const FORMATS: [FileFormat; 3] = [FileFormat::Yaml, FileFormat::Json, FileFormat::Ron];
+
+impl FileFormat {
+    pub fn from_path(path: &str) -> FileFormat {
+        match Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+            Some("json") => FileFormat::Json,
+            Some("ron") => FileFormat::Ron,
+            _ => FileFormat::Yaml,
+        }
+    }
+
+    // appended after .map or .edef, yaml doesn't get one so that existing files keep their names
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            FileFormat::Yaml => None,
+            FileFormat::Json => Some("json"),
+            FileFormat::Ron => Some("ron"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Yaml => "YAML",
+            FileFormat::Json => "JSON",
+            FileFormat::Ron => "RON",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, path: &str, contents: &str) -> Result<T, WorldFileError> {
+        match self {
+            FileFormat::Yaml => errors::parse_yaml(path, contents),
+            FileFormat::Json => errors::parse_json(path, contents),
+            FileFormat::Ron => errors::parse_ron(path, contents),
+        }
+    }
+
+    pub fn to_string<T: Serialize>(&self, value: &T) -> Result<String, String> {
+        match self {
+            FileFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            FileFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            FileFormat::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// reads a map or edef in whatever format its extension says
+pub fn read<T: DeserializeOwned>(path: &str) -> Result<T, WorldFileError> {
+    let contents = errors::read_file(path)?;
+    FileFormat::from_path(path).parse(path, &contents)
+}
+
+// serializes a map or edef in whatever format path's extension says
+pub fn to_string<T: Serialize>(path: &str, value: &T) -> Result<String, String> {
+    FileFormat::from_path(path).to_string(value)
+}
+
+// reads a map in either layout (see layout.rs)
+pub fn read_world(path: &str) -> Result<(World, MapLayout), WorldFileError> {
+    let contents = errors::read_file(path)?;
+    let format = FileFormat::from_path(path);
+    let probe: LayoutProbe = format.parse(path, &contents)?;
+    match probe.layout() {
+        MapLayout::Full => Ok((format.parse(path, &contents)?, MapLayout::Full)),
+        MapLayout::Compact => {
+            let compact: CompactWorld = format.parse(path, &contents)?;
+            let world = compact.to_world().map_err(|message| WorldFileError::SchemaMismatch {
+                path: path.to_string(),
+                line: None,
+                column: None,
+                message,
+            })?;
+            Ok((world, MapLayout::Compact))
+        }
+    }
+}
+
+// the layout of the map at path, None if it doesn't exist or can't be read
+pub fn detect_layout(path: &str) -> Option<MapLayout> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let probe: LayoutProbe = FileFormat::from_path(path).parse(path, &contents).ok()?;
+    Some(probe.layout())
+}
+
+pub fn world_to_string(path: &str, world: &World, layout: MapLayout) -> Result<String, String> {
+    match layout {
+        MapLayout::Full => to_string(path, world),
+        MapLayout::Compact => to_string(path, &CompactWorld::from(world)),
+    }
+}
+
+// the kinds of file convert knows about, told apart by their base extension
+enum ConvertedFile {
+    World(World, MapLayout),
+    EntityDef(EntityDef),
+}
+
+fn is_entity_def(path: &str) -> bool {
+    let file_name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let base = match FileFormat::from_path(path).extension() {
+        Some(extension) => file_name.trim_end_matches(&format!(".{}", extension)).to_string(),
+        None => file_name,
+    };
+    base.ends_with(".edef")
+}
+
+// checks that value comes back out of format exactly as it went in, by comparing it with itself after a round trip,
+// both written in the input's format. this catches e.g. json not being able to represent nan
+fn check_round_trip<T: Serialize + DeserializeOwned>(value: &T, input_format: FileFormat, format: FileFormat, output: &str, serialized: &str) -> Result<(), String> {
+    let round_tripped: T = format.parse(output, serialized).map_err(|e| e.to_string())?;
+    let before = input_format.to_string(value)?;
+    let after = input_format.to_string(&round_tripped)?;
+    if before != after {
+        return Err(format!("{} can't represent everything in this file exactly, not converting", format.name()));
+    }
+    Ok(())
+}
+
+// converts a map or edef between formats, the formats are taken from the extensions of input and output.
+// nothing is written unless the conversion is lossless
+pub fn convert(input: &str, output: &str) -> Result<(), String> {
+    let input_format = FileFormat::from_path(input);
+    let output_format = FileFormat::from_path(output);
+    if is_entity_def(input) != is_entity_def(output) {
+        return Err(format!("{} and {} aren't the same kind of file", input, output));
+    }
+    let value = if is_entity_def(input) {
+        let entity_def: EntityDef = read(input).map_err(|e| e.to_string())?;
+        errors::check_components(input, &entity_def.components).map_err(|e| e.to_string())?;
+        ConvertedFile::EntityDef(entity_def)
+    } else {
+        let (world, layout) = read_world(input).map_err(|e| e.to_string())?;
+        errors::check_entities(input, &world.entities).map_err(|e| e.to_string())?;
+        ConvertedFile::World(world, layout)
+    };
+    let serialized = match &value {
+        // maps keep their layout, only the format changes
+        ConvertedFile::World(world, MapLayout::Full) => {
+            let serialized = output_format.to_string(world)?;
+            check_round_trip(world, input_format, output_format, output, &serialized)?;
+            serialized
+        }
+        ConvertedFile::World(world, MapLayout::Compact) => {
+            let compact = CompactWorld::from(world);
+            let serialized = output_format.to_string(&compact)?;
+            check_round_trip(&compact, input_format, output_format, output, &serialized)?;
+            serialized
+        }
+        ConvertedFile::EntityDef(entity_def) => {
+            let serialized = output_format.to_string(entity_def)?;
+            check_round_trip(entity_def, input_format, output_format, output, &serialized)?;
+            serialized
+        }
+    };
+    std::fs::write(output, serialized).map_err(|e| format!("failed to write {}: {}", output, e))?;
+    info!("converted {} ({}) to {} ({})", input, input_format.name(), output, output_format.name());
+    Ok(())
+}
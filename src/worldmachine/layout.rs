@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::worldmachine::bookmarks::CameraBookmark;
+use crate::worldmachine::ecs::{Component, ComponentType, Entity, Parameter, ParameterValue, System};
+use crate::worldmachine::World;
+use gfx_maths::{Quaternion, Vec2, Vec3};
+
+// the compact map layout. the full layout is just World run through serde, which writes every parameter's name
+// twice and every component's type id next to its name. this one writes parameters as `name: value`, component
+// types by name only, vectors as lists, and leaves out anything that's empty, so maps are about half the size and
+// diffs only show what actually changed. it works with all the formats in formats.rs
+
+pub const COMPACT_LAYOUT: &str = "compact";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapLayout {
+    Full,
+    Compact,
+}
+
+// only used to find out which layout a map is in, everything else in the file is ignored.
+// named World so that ron (which writes struct names) accepts either layout
+#[derive(Deserialize)]
+#[serde(rename = "World")]
+pub struct LayoutProbe {
+    #[serde(default)]
+    pub layout: Option<String>,
+}
+
+impl LayoutProbe {
+    pub fn layout(&self) -> MapLayout {
+        match self.layout.as_deref() {
+            Some(COMPACT_LAYOUT) => MapLayout::Compact,
+            _ => MapLayout::Full,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "World")]
+pub struct CompactWorld {
+    pub layout: String, // always COMPACT_LAYOUT
+    pub eid_manager: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub systems: Vec<CompactSystem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<CompactEntity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<CompactBookmark>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompactBookmark {
+    pub name: String,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompactSystem {
+    pub name: String,
+    pub uid: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub affected_entities: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompactEntity {
+    pub name: String,
+    pub uid: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<CompactComponent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<CompactEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompactComponent {
+    #[serde(rename = "type")]
+    pub component_type: String,
+    // only written if it's different to the type, which it almost never is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, CompactValue>,
+}
+
+// same variants as ParameterValue, with vectors as plain lists
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CompactValue {
+    Vec3([f32; 3]),
+    Quaternion([f32; 4]),
+    Vec2([f32; 2]),
+    Float(f64),
+    Int(i32),
+    UnsignedInt(u64),
+    Bool(bool),
+    String(String),
+}
+
+// floats are written with the shortest representation that reads back as the same number (serde does that for us),
+// the only thing left to pin down is -0.0, which would otherwise show up as a change whenever something's sign flips
+fn f32_canonical(v: f32) -> f32 {
+    if v == 0.0 { 0.0 } else { v }
+}
+
+fn f64_canonical(v: f64) -> f64 {
+    if v == 0.0 { 0.0 } else { v }
+}
+
+impl From<&ParameterValue> for CompactValue {
+    fn from(value: &ParameterValue) -> Self {
+        match value {
+            ParameterValue::Vec3(v) => CompactValue::Vec3([f32_canonical(v.x), f32_canonical(v.y), f32_canonical(v.z)]),
+            ParameterValue::Quaternion(q) => CompactValue::Quaternion([f32_canonical(q.x), f32_canonical(q.y), f32_canonical(q.z), f32_canonical(q.w)]),
+            ParameterValue::Vec2(v) => CompactValue::Vec2([f32_canonical(v.x), f32_canonical(v.y)]),
+            ParameterValue::Float(f) => CompactValue::Float(f64_canonical(*f)),
+            ParameterValue::Int(i) => CompactValue::Int(*i),
+            ParameterValue::UnsignedInt(u) => CompactValue::UnsignedInt(*u),
+            ParameterValue::Bool(b) => CompactValue::Bool(*b),
+            ParameterValue::String(s) => CompactValue::String(s.clone()),
+        }
+    }
+}
+
+impl From<&CompactValue> for ParameterValue {
+    fn from(value: &CompactValue) -> Self {
+        match value {
+            CompactValue::Vec3(v) => ParameterValue::Vec3(Vec3::new(v[0], v[1], v[2])),
+            CompactValue::Quaternion(q) => ParameterValue::Quaternion(Quaternion::new(q[0], q[1], q[2], q[3])),
+            CompactValue::Vec2(v) => ParameterValue::Vec2(Vec2::new(v[0], v[1])),
+            CompactValue::Float(f) => ParameterValue::Float(*f),
+            CompactValue::Int(i) => ParameterValue::Int(*i),
+            CompactValue::UnsignedInt(u) => ParameterValue::UnsignedInt(*u),
+            CompactValue::Bool(b) => ParameterValue::Bool(*b),
+            CompactValue::String(s) => ParameterValue::String(s.clone()),
+        }
+    }
+}
+
+impl From<&Component> for CompactComponent {
+    fn from(component: &Component) -> Self {
+        Self {
+            component_type: component.component_type.name.clone(),
+            name: if component.name == component.component_type.name { None } else { Some(component.name.clone()) },
+            parameters: component.parameters.iter().map(|(name, parameter)| (name.clone(), CompactValue::from(&parameter.value))).collect(),
+        }
+    }
+}
+
+impl CompactComponent {
+    // component type ids aren't stored, they're looked up by name like they would be for a new component
+    fn to_component(&self) -> Result<Component, String> {
+        let component_type = ComponentType::get(self.component_type.clone())
+            .ok_or(format!("unknown component type {}", self.component_type))?;
+        Ok(Component {
+            name: self.name.clone().unwrap_or_else(|| self.component_type.clone()),
+            parameters: self.parameters.iter().map(|(name, value)| (name.clone(), Parameter::new(name, ParameterValue::from(value)))).collect(),
+            component_type,
+        })
+    }
+}
+
+impl From<&Entity> for CompactEntity {
+    fn from(entity: &Entity) -> Self {
+        Self {
+            name: entity.name.clone(),
+            uid: entity.uid,
+            parent: entity.parent,
+            components: entity.components.iter().map(CompactComponent::from).collect(),
+            children: entity.children.iter().map(CompactEntity::from).collect(),
+        }
+    }
+}
+
+impl CompactEntity {
+    fn to_entity(&self) -> Result<Entity, String> {
+        Ok(Entity {
+            name: self.name.clone(),
+            uid: self.uid,
+            components: self.components.iter().map(|c| c.to_component()).collect::<Result<Vec<_>, _>>()?,
+            children: self.children.iter().map(|c| c.to_entity()).collect::<Result<Vec<_>, _>>()?,
+            parent: self.parent,
+        })
+    }
+}
+
+impl From<&World> for CompactWorld {
+    fn from(world: &World) -> Self {
+        Self {
+            layout: COMPACT_LAYOUT.to_string(),
+            eid_manager: world.eid_manager,
+            systems: world.systems.iter().map(|s| CompactSystem {
+                name: s.name.clone(),
+                uid: s.uid,
+                affected_entities: s.affected_entities.clone(),
+            }).collect(),
+            entities: world.entities.iter().map(CompactEntity::from).collect(),
+            bookmarks: world.bookmarks.iter().map(|b| CompactBookmark {
+                name: b.name.clone(),
+                position: [f32_canonical(b.position.x), f32_canonical(b.position.y), f32_canonical(b.position.z)],
+                rotation: [f32_canonical(b.rotation.x), f32_canonical(b.rotation.y), f32_canonical(b.rotation.z), f32_canonical(b.rotation.w)],
+            }).collect(),
+        }
+    }
+}
+
+impl CompactWorld {
+    pub fn to_world(&self) -> Result<World, String> {
+        Ok(World {
+            entities: self.entities.iter().map(|e| e.to_entity()).collect::<Result<Vec<_>, _>>()?,
+            systems: self.systems.iter().map(|s| System {
+                name: s.name.clone(),
+                uid: s.uid,
+                affected_entities: s.affected_entities.clone(),
+            }).collect(),
+            eid_manager: self.eid_manager,
+            bookmarks: self.bookmarks.iter().map(|b| CameraBookmark {
+                name: b.name.clone(),
+                position: Vec3::new(b.position[0], b.position[1], b.position[2]),
+                rotation: Quaternion::new(b.rotation[0], b.rotation[1], b.rotation[2], b.rotation[3]),
+            }).collect(),
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::{Component, Path};
+use crate::worldmachine::ecs::EntityDef;
+use crate::worldmachine::formats;
+use crate::worldmachine::formats::FORMATS;
+
+// the entity def library is everything under <data>/entities. edefs can be sorted into subfolders, which the
+// entity picker shows as categories. an edef is referred to by its path relative to the entities folder,
+// without the .edef extension (and the format's extension, if it isn't yaml), e.g. "props/crates/crate.large"
+
+pub const EDEF_EXTENSION: &str = "edef";
+
+#[derive(Clone, Debug)]
+pub struct EntityDefEntry {
+    pub name: String, // relative path without extension, what load_entity_def takes
+    pub description: String,
+}
+
+impl EntityDefEntry {
+    // the folder this edef is in, empty for the top level
+    pub fn category(&self) -> &str {
+        self.name.rsplit_once('/').map(|(category, _)| category).unwrap_or("")
+    }
+
+    // the name without the folder
+    pub fn short_name(&self) -> &str {
+        self.name.rsplit_once('/').map(|(_, name)| name).unwrap_or(&self.name)
+    }
+
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        self.name.to_lowercase().contains(&search) || self.description.to_lowercase().contains(&search)
+    }
+}
+
+// the edef's file in whichever format it exists in, or where a new yaml one would go
+pub fn entity_def_path(entities_dir: &str, name: &str) -> String {
+    let yaml = format!("{}/{}.{}", entities_dir, name, EDEF_EXTENSION);
+    FORMATS.iter()
+        .filter_map(|format| format.extension())
+        .map(|extension| format!("{}.{}", yaml, extension))
+        .find(|path| Path::new(path).exists())
+        .unwrap_or(yaml)
+}
+
+// the edef name for a file name, None if it isn't an edef
+fn strip_edef_extension(file_name: &str) -> Option<&str> {
+    FORMATS.iter().find_map(|format| {
+        let suffix = match format.extension() {
+            Some(extension) => format!(".{}.{}", EDEF_EXTENSION, extension),
+            None => format!(".{}", EDEF_EXTENSION),
+        };
+        file_name.strip_suffix(&suffix)
+    })
+}
+
+// folders are given relative to the entities folder, and must stay inside it
+pub fn validate_folder(folder: &str) -> Result<(), String> {
+    for component in Path::new(folder).components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            _ => return Err(format!("{} is not a folder inside the entity library", folder)),
+        }
+    }
+    Ok(())
+}
+
+// an entity's name becomes its edef's file name, so it has to be a single, ordinary, non-hidden path component
+pub fn validate_entity_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.contains("..") || name.starts_with('.') {
+        return Err(format!("'{}' can't be used as an entity def name", name));
+    }
+    validate_folder(name)?;
+    if Path::new(name).components().count() != 1 {
+        return Err(format!("'{}' can't be used as an entity def name", name));
+    }
+    Ok(())
+}
+
+fn walk(entities_dir: &Path, dir: &Path, entries: &mut Vec<EntityDefEntry>, folders: &mut Vec<String>, read_defs: bool) {
+    let paths = match std::fs::read_dir(dir) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("failed to read entities directory {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for path in paths.flatten() {
+        let path = path.path();
+        let relative = match path.strip_prefix(entities_dir) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        if path.is_dir() {
+            folders.push(relative);
+            walk(entities_dir, &path, entries, folders, read_defs);
+            continue;
+        }
+        if !read_defs {
+            continue;
+        }
+        // only strip the extensions, names are allowed to have dots in them
+        let name = match strip_edef_extension(&relative) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let path_str = path.to_string_lossy().to_string();
+        let description = formats::read::<EntityDef>(&path_str)
+            .map(|def| def.description)
+            .unwrap_or_else(|e| {
+                warn!("{}", e);
+                String::new()
+            });
+        entries.push(EntityDefEntry { name, description });
+    }
+}
+
+// every edef in the library, sorted by name
+pub fn list_entity_defs(entities_dir: &str) -> Vec<EntityDefEntry> {
+    let mut entries = Vec::new();
+    let mut folders = Vec::new();
+    walk(Path::new(entities_dir), Path::new(entities_dir), &mut entries, &mut folders, true);
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    // the same edef in more than one format only shows up once, entity_def_path picks which file gets used
+    entries.dedup_by(|a, b| a.name == b.name);
+    entries
+}
+
+// every subfolder of the library, sorted
+pub fn list_folders(entities_dir: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut folders = Vec::new();
+    walk(Path::new(entities_dir), Path::new(entities_dir), &mut entries, &mut folders, false);
+    folders.sort();
+    folders
+}
+
+// every .glb in the models folder (not recursive, load_mesh_if_not_already_loaded only looks at the top level),
+// returned as (mesh name, path) sorted by name
+pub fn list_meshes(models_dir: &str) -> Vec<(String, String)> {
+    let paths = match std::fs::read_dir(models_dir) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("failed to read models directory {}: {}", models_dir, e);
+            return Vec::new();
+        }
+    };
+    let mut meshes = paths.flatten()
+        .map(|path| path.path())
+        .filter(|path| path.extension().map(|e| e == "glb").unwrap_or(false))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            Some((name, path.to_string_lossy().to_string()))
+        })
+        .collect::<Vec<_>>();
+    meshes.sort();
+    meshes
+}
@@ -1,29 +1,55 @@
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::borrow::{Borrow, BorrowMut};
 use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex, MutexGuard};
-use gfx_maths::{Quaternion, Vec2, Vec3};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use gfx_maths::{Mat4, Quaternion, Vec2, Vec3};
 use gtk::subclass::prelude::ObjectSubclassIsExt;
 use serde::{Deserialize, Serialize};
-use crate::{Cast, renderer};
+use crate::{Cast, renderer, settings};
 use crate::h2eck_window::editor::Editor;
-use crate::renderer::camera::Camera;
 use crate::renderer::H2eckRenderer;
+use crate::renderer::gizmo::GizmoTransform;
+use crate::renderer::mesh::{calculate_model_matrix, Mesh};
+use crate::renderer::outline::{HOVERED_OUTLINE_COLOUR, HOVERED_OUTLINE_WIDTH, SELECTED_OUTLINE_COLOUR, SELECTED_OUTLINE_WIDTH};
 use crate::renderer::raycasting::Ray;
+use crate::renderer::shader::Shader;
+use crate::worldmachine::bookmarks::CameraBookmark;
 use crate::worldmachine::components::{BoxCollider, COMPONENT_TYPE_BOX_COLLIDER, COMPONENT_TYPE_JUKEBOX, COMPONENT_TYPE_LIGHT, COMPONENT_TYPE_MESH_RENDERER, COMPONENT_TYPE_TERRAIN, COMPONENT_TYPE_TRANSFORM, Jukebox, Light, MeshRenderer, Terrain, Transform};
 use crate::worldmachine::ecs::*;
 use crate::worldmachine::entities::new_ht2_entity;
+use crate::worldmachine::errors::WorldFileError;
+use crate::worldmachine::layout::MapLayout;
+use crate::worldmachine::spatial::{Aabb, Frustum, RayHit, SpatialIndex};
 
+pub mod bookmarks;
+pub mod compiler;
 pub mod ecs;
 pub mod components;
 pub mod entities;
+pub mod errors;
+pub mod formats;
+pub mod layout;
 pub mod helpers;
+pub mod library;
+pub mod recovery;
+pub mod spatial;
+
+// how far away new entities can be placed on something
+pub const PLACEMENT_DISTANCE: f32 = 1000.0;
+
+// how many maps have been written for mapc, see write_mapc_input
+static MAPC_INPUT_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Deserialize, Serialize)]
 pub struct World {
     pub entities: Vec<Entity>,
     pub systems: Vec<System>,
     eid_manager: u64,
+    // only written when there are some, so maps without bookmarks (and compiled maps) look the same as before
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<CameraBookmark>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -46,6 +72,7 @@ impl Clone for World {
             entities,
             systems,
             eid_manager: 0,
+            bookmarks: self.bookmarks.clone(),
         }
     }
 }
@@ -57,6 +84,12 @@ pub struct WorldMachine {
     pub editor: Arc<Mutex<Option<Editor>>>,
     pub entities_wanting_to_load_things: Vec<usize>, // index
     lights_changed: bool,
+    shadows_outdated: bool, // the lights or something that casts shadows changed since the shadow maps were rendered
+    dirty: bool, // edits since the last save or load
+    spatial: SpatialIndex, // bounds of every top level entity that draws something
+    entity_indices: HashMap<u64, usize>, // uid -> index in world.entities, see push_entity and rebuild_entity_indices
+    bounds_outdated: HashSet<u64>, // entities whose bounds need recalculating before the next frame
+    selected_entity: Arc<Mutex<Option<u64>>>, // shared with the editor, which does the selecting
 }
 
 impl Default for WorldMachine {
@@ -65,6 +98,7 @@ impl Default for WorldMachine {
             entities: Vec::new(),
             systems: Vec::new(),
             eid_manager: 0,
+            bookmarks: Vec::new(),
         };
         Self {
             world,
@@ -73,20 +107,209 @@ impl Default for WorldMachine {
             editor: Arc::new(Mutex::new(Option::None)),
             entities_wanting_to_load_things: Vec::new(),
             lights_changed: true,
+            shadows_outdated: true,
+            dirty: false,
+            spatial: SpatialIndex::default(),
+            entity_indices: HashMap::new(),
+            bounds_outdated: HashSet::new(),
+            selected_entity: Arc::new(Mutex::new(None)),
         }
     }
 }
 
 impl WorldMachine {
     pub fn initialise(&mut self, editor: Arc<Mutex<Option<Editor>>>) {
-        // todo! get this from settings
-        self.game_data_path = String::from("../huskyTech2/base");
+        self.game_data_path = settings::data_location();
         components::register_component_types();
 
+        if let Some(editor) = editor.lock().unwrap().as_ref() {
+            self.selected_entity = editor.imp().current_entity_id.clone();
+        }
         self.editor = editor;
         self.blank_slate();
     }
 
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // call after anything that changes the world
+    pub fn mark_dirty(&mut self) {
+        if !self.dirty {
+            self.set_dirty(true);
+        }
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+        let editor = self.editor.lock().unwrap();
+        if let Some(editor) = editor.as_ref() {
+            editor.imp().update_title(dirty);
+        }
+    }
+
+    // every entity added to the world goes through here, so that it can be found by uid
+    fn push_entity(&mut self, entity: Entity) -> usize {
+        let index = self.world.entities.len();
+        self.entity_indices.insert(entity.uid, index);
+        self.world.entities.push(entity);
+        index
+    }
+
+    // for when entities have been removed or the whole world has been replaced
+    fn rebuild_entity_indices(&mut self) {
+        self.entity_indices = self.world.entities.iter().enumerate().map(|(index, entity)| (entity.uid, index)).collect();
+    }
+
+    // call after anything that could change where an entity is or what it draws. lights are entities too, so they
+    // get rebuilt as well
+    fn entity_changed(&mut self, uid: u64) {
+        self.bounds_outdated.insert(uid);
+        self.lights_changed = true;
+    }
+
+    // throws the spatial index away and recalculates it on the next frame, for when the whole world changes
+    fn rebuild_spatial_index(&mut self) {
+        self.spatial.clear();
+        self.bounds_outdated = self.world.entities.iter().map(|e| e.uid).collect();
+        self.lights_changed = true;
+    }
+
+    // recalculates the bounds of everything that's changed, must be called after the meshes have been loaded
+    fn update_spatial_index(&mut self, renderer: &H2eckRenderer) {
+        if self.bounds_outdated.is_empty() {
+            return;
+        }
+        // the shadow maps are rendered before this each frame, so they catch up on the next one
+        self.shadows_outdated = true;
+        for entity in self.world.entities.iter() {
+            if !self.bounds_outdated.remove(&entity.uid) {
+                continue;
+            }
+            match spatial::entity_bounds(entity, renderer) {
+                Some(bounds) => self.spatial.update(entity.uid, bounds),
+                None => self.spatial.remove(entity.uid),
+            }
+        }
+        // anything left over has been removed from the world
+        for uid in self.bounds_outdated.drain() {
+            self.spatial.remove(uid);
+        }
+    }
+
+    // entities whose bounds the ray passes through, nearest first
+    pub fn entities_along_ray(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Vec<(u64, f32)> {
+        self.spatial.query_ray(origin, direction, max_distance)
+    }
+
+    pub fn entities_in_frustum(&self, frustum: &Frustum) -> Vec<u64> {
+        self.spatial.query_frustum(frustum)
+    }
+
+    pub fn entities_in_box(&self, aabb: &Aabb) -> Vec<u64> {
+        self.spatial.query_box(aabb)
+    }
+
+    pub fn entity_bounds(&self, uid: u64) -> Option<Aabb> {
+        self.spatial.bounds(uid)
+    }
+
+    pub fn world_bounds(&self) -> Option<Aabb> {
+        self.spatial.total_bounds()
+    }
+
+    pub fn selected_entity(&self) -> Option<u64> {
+        *self.selected_entity.lock().unwrap()
+    }
+
+    // the entity's Transform component, None if it doesn't have one
+    pub fn entity_transform(&self, uid: u64) -> Option<GizmoTransform> {
+        let entity = &self.world.entities[self.get_entity_index(uid)?];
+        let transform = entity.get_component(COMPONENT_TYPE_TRANSFORM.clone())?;
+        let position = match transform.get_parameter("position")?.value {
+            ParameterValue::Vec3(v) => v,
+            _ => return None,
+        };
+        let rotation = match transform.get_parameter("rotation")?.value {
+            ParameterValue::Quaternion(q) => q,
+            _ => return None,
+        };
+        let scale = match transform.get_parameter("scale")?.value {
+            ParameterValue::Vec3(v) => v,
+            _ => return None,
+        };
+        Some(GizmoTransform { position, rotation, scale })
+    }
+
+    // moves the entity while a gizmo is being dragged, without counting it as an edit
+    pub fn preview_transform(&mut self, uid: u64, transform: GizmoTransform) {
+        let index = match self.get_entity_index(uid) {
+            Some(index) => index,
+            None => return,
+        };
+        let entity = &mut self.world.entities[index];
+        entity.set_component_parameter(COMPONENT_TYPE_TRANSFORM.clone(), "position", ParameterValue::Vec3(transform.position));
+        entity.set_component_parameter(COMPONENT_TYPE_TRANSFORM.clone(), "rotation", ParameterValue::Quaternion(transform.rotation));
+        entity.set_component_parameter(COMPONENT_TYPE_TRANSFORM.clone(), "scale", ParameterValue::Vec3(transform.scale));
+        self.lights_changed = true;
+        self.entity_changed(uid);
+    }
+
+    // the end of a gizmo drag, the whole drag is one edit
+    pub fn commit_transform(&mut self, uid: u64, transform: GizmoTransform) {
+        self.preview_transform(uid, transform);
+        self.mark_dirty();
+    }
+
+    // the closest surface the ray hits. candidates come from the spatial index nearest first, and are then tested
+    // against their actual triangles
+    pub fn raycast(&self, ray: &Ray, renderer: &H2eckRenderer) -> Option<RayHit> {
+        let direction = Vec3::new(ray.direction.x, ray.direction.y, ray.direction.z);
+        let mut closest: Option<RayHit> = None;
+        for (uid, bounds_distance) in self.spatial.query_picking_ray(ray) {
+            // everything from here on is further away than what's already been hit
+            if closest.map_or(false, |hit| hit.distance < bounds_distance) {
+                break;
+            }
+            let entity = match self.get_entity_index(uid) {
+                Some(index) => &self.world.entities[index],
+                None => continue,
+            };
+            for (component, mesh) in spatial::entity_meshes(entity, renderer) {
+                let geometry = match renderer.mesh_geometry.get(&mesh.vao) {
+                    Some(geometry) => geometry,
+                    None => continue,
+                };
+                let model = calculate_model_matrix(mesh.position, mesh.rotation, mesh.scale);
+                if let Some(hit) = geometry.raycast(model, ray.origin, direction, ray.max_distance) {
+                    if closest.map_or(true, |closest| hit.distance < closest.distance) {
+                        closest = Some(RayHit { uid, component, distance: hit.distance, point: hit.point, normal: hit.normal });
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    // works out which entity is under the given pixel (bottom left origin, like select) so it can be highlighted
+    pub fn hover(&self, x: f32, y: f32, renderer: &mut H2eckRenderer) {
+        renderer.hovered_entity = renderer.viewport_ray(x, y, PLACEMENT_DISTANCE)
+            .and_then(|ray| self.raycast(&ray, renderer))
+            .map(|hit| hit.uid);
+    }
+
+    // where new entities should go: on whatever's in the middle of the view, or at the camera if there's nothing there.
+    // snapped to the grid if snapping is on
+    pub fn placement_position(&self, renderer: &H2eckRenderer) -> Option<Vec3> {
+        let camera = renderer.camera.as_ref()?;
+        let ray = Ray::from_center_screen(camera.get_window_size(), camera, PLACEMENT_DISTANCE);
+        let position = match self.raycast(&ray, renderer) {
+            Some(hit) => hit.point,
+            None => -camera.get_position(), // camera position is inverted
+        };
+        Some(renderer.grid.snap_position(position))
+    }
+
     fn regen_editor(&mut self) {
         {
             let editor = self.editor.lock().unwrap();
@@ -105,77 +328,119 @@ impl WorldMachine {
         }
         self.world.entities.clear();
         self.world.systems.clear();
+        self.world.bookmarks.clear();
         self.counter = 0.0;
         self.lights_changed = true;
         let mut ht2 = new_ht2_entity();
         ht2.set_component_parameter(COMPONENT_TYPE_TRANSFORM.clone(), "position", ParameterValue::Vec3(Vec3::new(0.0, 0.0, 2.0)));
         let light_component = Light::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 1.0);
         ht2.add_component(light_component);
-        self.world.entities.push(ht2);
+        self.rebuild_entity_indices();
+        self.push_entity(ht2);
+        self.rebuild_spatial_index();
         self.regen_editor();
+        self.set_dirty(false);
     }
 
-    pub fn load_entity_def(&mut self, name: &str, center_at_camera: Option<Camera>) {
+    // position is where to put the entity if it has a transform, see placement_position
+    pub fn load_entity_def(&mut self, name: &str, position: Option<Vec3>) -> Result<(), WorldFileError> {
         debug!("{}, {}", name, self.game_data_path);
-        let path = format!("{}/entities/{}.edef", self.game_data_path, name);
-        let serialization = std::fs::read_to_string(path);
-        if serialization.is_err() {
-            error!("failed to load entity def: {}", name);
-            return;
-        }
-        let serialization = serialization.unwrap();
-        let entity_def: EntityDef = serde_yaml::from_str(&serialization).unwrap();
+        let path = library::entity_def_path(&self.entities_dir(), name);
+        let entity_def: EntityDef = formats::read(&path)?;
+        errors::check_components(&path, &entity_def.components)?;
         let mut entity = Entity::from_entity_def(&entity_def);
-        if let Some(camera) = center_at_camera {
-            let mut position = camera.get_position();
-            position *= -1.0; // camera position is inverted for some reason
+        if let Some(position) = position {
             // if the entity has a transform component, set it's position to the raycast position
             if entity.has_component(COMPONENT_TYPE_TRANSFORM.clone()) {
                 entity.set_component_parameter(COMPONENT_TYPE_TRANSFORM.clone(), "position", ParameterValue::Vec3(position));
             }
         }
-        self.world.entities.push(entity);
-        self.entities_wanting_to_load_things.push(self.world.entities.len() - 1);
+        self.entity_changed(entity.uid);
+        let index = self.push_entity(entity);
+        self.entities_wanting_to_load_things.push(index);
         self.regen_editor();
+        self.mark_dirty();
+        Ok(())
     }
 
     pub fn add_blank_entity(&mut self, name: &str) {
         let entity = Entity::new(name);
-        self.world.entities.push(entity);
+        self.push_entity(entity);
         self.regen_editor();
+        self.mark_dirty();
     }
 
-    pub fn save_entity_def(&mut self, uid: u64) {
-        debug!("{}, {}", uid, self.game_data_path);
-        let entity = self.get_entity(uid).unwrap();
-        let entity = entity.lock().unwrap();
-        let path = format!("{}/entities/{}.edef", self.game_data_path, entity.name);
-        let entity_def = entity.to_entity_def();
-        let serialization = serde_yaml::to_string(&entity_def).unwrap();
-        let res = std::fs::write(path, serialization);
-        if res.is_err() {
-            error!("failed to save entity def: {}", res.err().unwrap());
-        }
+    // a new entity that just renders the given mesh, from the asset browser
+    pub fn add_mesh_entity(&mut self, mesh: &str, position: Option<Vec3>) {
+        let position = position.unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+        let mut entity = Entity::new(mesh);
+        entity.add_component(Transform::new(position, Quaternion::new(0.0, 0.0, 0.0, 1.0), Vec3::new(1.0, 1.0, 1.0)));
+        entity.add_component(MeshRenderer::new(mesh.to_string(), "basic".to_string(), "default".to_string()));
+        self.entity_changed(entity.uid);
+        let index = self.push_entity(entity);
+        self.entities_wanting_to_load_things.push(index);
+        self.regen_editor();
+        self.mark_dirty();
     }
 
-    pub fn list_possible_entities(&self) -> Vec<String> {
-        let mut entities = Vec::new();
-        let paths = std::fs::read_dir(format!("{}/entities", self.game_data_path));
-        if paths.is_err() {
-            error!("failed to read entities directory!");
-            return entities;
+    // copies a top level entity, the copy goes in the same place. returns the copy's uid
+    pub fn duplicate_entity(&mut self, uid: u64) -> Option<u64> {
+        let index = self.get_entity_index(uid)?;
+        let entity = self.world.entities[index].duplicate(None);
+        let new_uid = entity.uid;
+        self.entity_changed(new_uid);
+        let index = self.push_entity(entity);
+        self.entities_wanting_to_load_things.push(index);
+        self.regen_editor();
+        self.mark_dirty();
+        Some(new_uid)
+    }
+
+    pub fn entities_dir(&self) -> String {
+        format!("{}/entities", self.game_data_path)
+    }
+
+    // folder is relative to the entities folder, "" for the top level
+    pub fn save_entity_def(&mut self, uid: u64, folder: &str, description: &str) -> Result<(), String> {
+        debug!("{}, {}, {}", uid, folder, self.game_data_path);
+        library::validate_folder(folder)?;
+        let entity = self.get_entity(uid).ok_or(format!("entity {} not found", uid))?;
+        let entity = entity.lock().unwrap();
+        library::validate_entity_name(&entity.name)?;
+        let folder = folder.trim_matches('/');
+        let name = if folder.is_empty() { entity.name.clone() } else { format!("{}/{}", folder, entity.name) };
+        let path = library::entity_def_path(&self.entities_dir(), &name);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
         }
-        let paths = paths.unwrap();
-        for path in paths {
-            let path = path.unwrap().path();
-            let path = path.to_str().unwrap();
-            let path = path.split("/").collect::<Vec<&str>>();
-            let path = path[path.len() - 1];
-            let path = path.split(".").collect::<Vec<&str>>();
-            let path = path[0];
-            entities.push(String::from(path));
+        let mut entity_def = entity.to_entity_def();
+        entity_def.description = description.to_string();
+        // an existing edef keeps the format it was written in
+        let serialization = formats::to_string(&path, &entity_def).map_err(|e| format!("failed to serialize entity def: {}", e))?;
+        let res = std::fs::write(&path, serialization);
+        if let Err(e) = res {
+            let e = format!("failed to save entity def: {}", e);
+            error!("{}", e);
+            return Err(e);
         }
-        entities
+        info!("saved entity def {}", name);
+        Ok(())
+    }
+
+    pub fn list_possible_entities(&self) -> Vec<library::EntityDefEntry> {
+        library::list_entity_defs(&self.entities_dir())
+    }
+
+    pub fn list_entity_def_folders(&self) -> Vec<String> {
+        library::list_folders(&self.entities_dir())
+    }
+
+    pub fn models_dir(&self) -> String {
+        format!("{}/models", self.game_data_path)
+    }
+
+    pub fn list_meshes(&self) -> Vec<(String, String)> {
+        library::list_meshes(&self.models_dir())
     }
 
     pub fn give_component_to_entity(&mut self, uid: u64, component: Component) {
@@ -187,14 +452,18 @@ impl WorldMachine {
         let index = index.unwrap();
         let entity = self.world.entities.get_mut(index).unwrap();
         entity.add_component(component);
+        self.entity_changed(uid);
         self.regen_editor();
+        self.mark_dirty();
     }
 
     pub fn remove_component_from_entity(&mut self, uid: u64, component_type: ComponentType) {
         let index = self.get_entity_index(uid).unwrap();
         let entity = self.world.entities.get_mut(index).unwrap();
         entity.remove_component(component_type);
+        self.entity_changed(uid);
         self.regen_editor();
+        self.mark_dirty();
     }
 
     pub fn rename_entity(&mut self, uid: u64, new_name: &str) {
@@ -202,6 +471,7 @@ impl WorldMachine {
         let entity = self.world.entities.get_mut(index).unwrap();
         entity.name = String::from(new_name);
         self.regen_editor();
+        self.mark_dirty();
     }
 
     pub fn list_all_component_types(&self) -> Vec<String> {
@@ -243,20 +513,73 @@ impl WorldMachine {
         }
     }
 
-    pub fn save_state_to_file(&mut self, file_path: &str) {
+    // if this fails the map on disk is left as it was, and the world stays dirty
+    pub fn save_state_to_file(&mut self, file_path: &str) -> Result<(), WorldFileError> {
         {
-            let mut eid_manager = ENTITY_ID_MANAGER.lock().unwrap();
+            let eid_manager = ENTITY_ID_MANAGER.lock().unwrap();
             self.world.eid_manager = eid_manager.borrow().id;
-            self.editor.lock().unwrap().as_mut().unwrap().imp().current_world_path.lock().unwrap().replace(String::from(file_path));
         }
-        let serialized = serde_yaml::to_string(&self.world).unwrap();
-        std::fs::write(file_path, serialized).expect("unable to write file");
+        // existing maps stay in whatever layout they're in, new ones use the one from the preferences
+        let layout = formats::detect_layout(file_path).unwrap_or(if settings::compact_maps() { MapLayout::Compact } else { MapLayout::Full });
+        let serialized = formats::world_to_string(file_path, &self.world, layout)
+            .map_err(|message| WorldFileError::Write { path: file_path.to_string(), message })?;
+        // write next to the map first and then move it over, so that failing halfway through doesn't cost the map
+        let tmp = format!("{}.tmp", file_path);
+        if let Err(e) = std::fs::write(&tmp, serialized) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(WorldFileError::Write { path: file_path.to_string(), message: e.to_string() });
+        }
+        recovery::rotate_backups(file_path, settings::backup_count());
+        std::fs::rename(&tmp, file_path)
+            .map_err(|e| WorldFileError::Write { path: file_path.to_string(), message: e.to_string() })?;
+        self.editor.lock().unwrap().as_mut().unwrap().imp().current_world_path.lock().unwrap().replace(String::from(file_path));
+        self.set_dirty(false);
+        settings::add_recent_map(file_path);
+        if let Some(editor) = self.editor.lock().unwrap().as_ref() {
+            editor.imp().save_session();
+        }
+        Ok(())
+    }
+
+    pub fn current_world_path(&self) -> Option<String> {
+        let editor = self.editor.lock().unwrap();
+        let path = editor.as_ref()?.imp().current_world_path.lock().unwrap().clone();
+        path
+    }
+
+    // writes the current world to the recovery location, doesn't touch the map itself
+    pub fn autosave(&mut self) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        {
+            let eid_manager = ENTITY_ID_MANAGER.lock().unwrap();
+            self.world.eid_manager = eid_manager.borrow().id;
+        }
+        recovery::write_autosave(&self.world, self.current_world_path())
+    }
+
+    // loads an autosave left behind by a crash, saving afterwards goes to the map the autosave came from
+    pub fn recover_from_autosave(&mut self, pending: &recovery::PendingRecovery) -> Result<(), WorldFileError> {
+        self.load_state_from_file(&pending.autosave_path)?;
+        {
+            let editor = self.editor.lock().unwrap();
+            if let Some(editor) = editor.as_ref() {
+                *editor.imp().current_world_path.lock().unwrap() = pending.info.original_path.clone();
+            }
+        }
+        // the recovered changes haven't been saved to the map yet
+        self.set_dirty(true);
+        Ok(())
     }
 
-    pub fn load_state_from_file(&mut self, file_path: &str) {
-        let contents = std::fs::read_to_string(file_path).expect("something went wrong reading the file");
-        let world = serde_yaml::from_str(&contents).unwrap();
+    // the current world is only replaced if the whole file loads successfully
+    pub fn load_state_from_file(&mut self, file_path: &str) -> Result<(), WorldFileError> {
+        let (world, _) = formats::read_world(file_path)?;
+        errors::check_entities(file_path, &world.entities)?;
         self.world = world;
+        self.rebuild_entity_indices();
+        self.rebuild_spatial_index();
 
         {
             let mut eid_manager = ENTITY_ID_MANAGER.lock().unwrap();
@@ -268,59 +591,95 @@ impl WorldMachine {
             self.entities_wanting_to_load_things.push(i);
         }
         self.regen_editor();
+        self.set_dirty(false);
+        Ok(())
     }
 
-    pub fn compile_map(&mut self, name: &str) {
+    pub fn compile_map(&mut self, name: &str) -> Result<(), String> {
         // create a directory for the map (if it doesn't exist)
         let map_dir = format!("{}/maps/{}", self.game_data_path, name);
         let res = std::fs::create_dir_all(map_dir.clone());
         if res.is_err() {
-            error!("failed to create map directory: {}", res.err().unwrap());
-            return;
+            let e = format!("failed to create map directory: {}", res.err().unwrap());
+            error!("{}", e);
+            return Err(e);
         }
 
+        let mut world = self.world.clone();
+        // bookmarks are only for the editor
+        world.bookmarks.clear();
         let worlddef = WorldDef {
             name: String::from(name),
-            world: self.world.clone(),
+            world,
         };
 
         let mut serialized = Vec::new();
         let res = worlddef.serialize(&mut rmp_serde::Serializer::new(&mut serialized));
         if res.is_err() {
-            error!("failed to serialize worlddef: {}", res.err().unwrap());
-            return;
+            let e = format!("failed to serialize worlddef: {}", res.err().unwrap());
+            error!("{}", e);
+            return Err(e);
         }
         // write the worlddef to a file
         let res = std::fs::write(format!("{}/worlddef", map_dir), serialized);
         if res.is_err() {
-            error!("failed to write worlddef: {}", res.err().unwrap());
-            return;
+            let e = format!("failed to write worlddef: {}", res.err().unwrap());
+            error!("{}", e);
+            return Err(e);
         }
         info!("wrote worlddef to file");
+        Ok(())
+    }
+
+    // writes the world to a temporary file for mapc, so that compiling doesn't save over the map or rotate its backups.
+    // file_name picks the format the same way the map's own path does when saving. every call gets its own file, so
+    // compiles running at the same time (a bake and a playtest, or another editor) don't overwrite each other's input
+    pub fn write_mapc_input(&mut self, world_name: &str, file_name: &str) -> Result<String, String> {
+        {
+            let eid_manager = ENTITY_ID_MANAGER.lock().unwrap();
+            self.world.eid_manager = eid_manager.borrow().id;
+        }
+        let dir = std::env::temp_dir().join("h2eck-mapc");
+        std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let count = MAPC_INPUT_COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}-{}-{}-{}", std::process::id(), count, world_name, file_name)).to_string_lossy().to_string();
+        let layout = self.current_world_path().and_then(|p| formats::detect_layout(&p))
+            .unwrap_or(if settings::compact_maps() { MapLayout::Compact } else { MapLayout::Full });
+        let mut world = self.world.clone();
+        // bookmarks are only for the editor. World's clone doesn't keep the id counter, mapc needs it
+        world.bookmarks.clear();
+        world.eid_manager = self.world.eid_manager;
+        let serialized = formats::world_to_string(&path, &world, layout)?;
+        std::fs::write(&path, serialized).map_err(|e| format!("failed to write {}: {}", path, e))?;
+        Ok(path)
+    }
+
+    // removes a map created by compile_map, used to clean up after playtesting
+    pub fn remove_compiled_map(&self, name: &str) {
+        let map_dir = format!("{}/maps/{}", self.game_data_path, name);
+        if let Err(e) = std::fs::remove_dir_all(map_dir) {
+            warn!("failed to remove compiled map {}: {}", name, e);
+        }
     }
 
     #[allow(clippy::borrowed_box)]
     pub fn get_entity(&self, entity_id: u64) -> Option<Arc<Mutex<&Entity>>> {
-        for entity in self.world.entities.iter() {
-            if entity.get_id() == entity_id {
-                return Some(Arc::new(Mutex::new(entity)));
-            }
-        }
-        None
+        let index = self.get_entity_index(entity_id)?;
+        Some(Arc::new(Mutex::new(&self.world.entities[index])))
     }
 
     pub fn get_entity_index(&self, entity_id: u64) -> Option<usize> {
-        for (index, entity) in self.world.entities.iter().enumerate() {
-            if entity.get_id() == entity_id {
-                return Some(index);
-            }
-        }
-        None
+        self.entity_indices.get(&entity_id).copied()
     }
 
     pub fn remove_entity_at_index(&mut self, index: usize) {
-        self.world.entities.remove(index);
+        let entity = self.world.entities.remove(index);
+        self.rebuild_entity_indices();
+        self.spatial.remove(entity.uid);
+        self.lights_changed = true;
+        self.shadows_outdated = true;
         self.regen_editor();
+        self.mark_dirty();
     }
 
     pub fn attempt_to_set_component_property(&mut self, entity_id: u64, component_name: String, property_name: String, value: String) {
@@ -414,17 +773,78 @@ impl WorldMachine {
             },
             _ => {
                 error!("attempt_to_set_component_property: unknown type: {:?}", parameter.value);
+                return;
+            }
+        }
+        self.entity_changed(entity_id);
+        self.mark_dirty();
+    }
+
+    // the entity (and the name of the component) drawn at the given pixel of the scene, with the origin in the bottom
+    // left. the editor does the actual selecting, as it can't be told while the worldmachine is locked
+    pub fn select(&mut self, mouse_x: f32, mouse_y: f32, renderer: &mut H2eckRenderer) -> Option<(u64, String)> {
+        let (entity_index, component_index) = renderer.pick(self, mouse_x as i32, mouse_y as i32)?;
+        let entity = self.world.entities.get(entity_index)?;
+        let component = entity.get_components().get(component_index)?;
+        debug!("picked {} ({}) {}", entity.name, entity.uid, component.get_name());
+        Some((entity.uid, component.get_name().to_string()))
+    }
+
+    // outlines the selected entity, and the one under the cursor more faintly
+    pub fn render_outlines(&self, renderer: &mut H2eckRenderer) {
+        let selected = self.selected_entity();
+        let hovered = renderer.hovered_entity.filter(|uid| Some(*uid) != selected);
+        for (uid, colour, width) in [
+            (hovered, HOVERED_OUTLINE_COLOUR, HOVERED_OUTLINE_WIDTH),
+            (selected, SELECTED_OUTLINE_COLOUR, SELECTED_OUTLINE_WIDTH),
+        ] {
+            let entity = match uid.and_then(|uid| self.get_entity_index(uid)) {
+                Some(index) => &self.world.entities[index],
+                None => continue,
+            };
+            let meshes = spatial::entity_meshes(entity, renderer).into_iter().map(|(_, mesh)| mesh).collect::<Vec<Mesh>>();
+            renderer.render_outline(&meshes, colour, width);
+        }
+    }
+
+    // whether the shadow maps need rendering again, and they're assumed to have been after asking
+    pub fn take_shadows_outdated(&mut self) -> bool {
+        std::mem::replace(&mut self.shadows_outdated, false)
+    }
+
+    // draws everything that can cast a shadow into the shadow map being rendered, see H2eckRenderer::render_shadow_maps.
+    // only what the light can see is drawn, and visualised colliders are left out, they're only there to be seen
+    pub fn render_shadow_casters(&self, renderer: &H2eckRenderer, shader: &Shader, light_space: Mat4) {
+        for uid in self.spatial.query_frustum(&Frustum::from_view_projection(light_space)) {
+            let entity = match self.get_entity_index(uid) {
+                Some(index) => &self.world.entities[index],
+                None => continue,
+            };
+            for (component_index, mesh) in spatial::entity_meshes(entity, renderer) {
+                if entity.get_components()[component_index].get_type() == COMPONENT_TYPE_BOX_COLLIDER.clone() {
+                    continue;
+                }
+                mesh.render_depth(shader, light_space);
             }
         }
     }
 
-    pub fn select(&mut self, mouse_x: f32, mouse_y: f32, renderer: &mut H2eckRenderer) {
+    // draws every entity into the picking framebuffer, see renderer/picking.rs
+    pub fn render_ids(&mut self, renderer: &mut H2eckRenderer) {
+        for (entity_index, entity) in self.world.entities.iter().enumerate() {
+            for (component_index, mesh) in spatial::entity_meshes(entity, renderer) {
+                renderer.render_id(&mesh, entity_index, component_index);
+            }
+        }
     }
 
-    pub fn send_lights_to_renderer(&self) -> Option<Vec<renderer::light::Light>> {
+    pub fn send_lights_to_renderer(&mut self) -> Option<Vec<renderer::light::Light>> {
         if !self.lights_changed {
             return Option::None;
         }
+        self.lights_changed = false;
+        // new lights haven't been given shadow maps yet
+        self.shadows_outdated = true;
         let mut lights = Vec::new();
         for entity in &self.world.entities {
             let components = entity.get_components();
@@ -476,11 +896,9 @@ impl WorldMachine {
                     };
                     position = position + trans_position;
                 }
-                lights.push(renderer::light::Light{
-                    position,
-                    color,
-                    intensity,
-                });
+                let mut renderer_light = renderer::light::Light::new(position, color, intensity);
+                renderer_light.read_options(&light);
+                lights.push(renderer_light);
             }
         }
         Some(lights)
@@ -541,7 +959,17 @@ impl WorldMachine {
             }
         }
         self.entities_wanting_to_load_things.clear();
-        for entity in self.world.entities.iter_mut() {
+        self.update_spatial_index(renderer);
+        // only what's in view gets drawn, anything that isn't in the index doesn't draw a mesh anyway
+        let visible = match renderer.camera.as_ref() {
+            Some(camera) => self.spatial.query_frustum(&Frustum::from_camera(camera)),
+            None => self.world.entities.iter().map(|e| e.uid).collect(),
+        };
+        for uid in visible {
+            let entity = match self.get_entity_index(uid) {
+                Some(index) => &self.world.entities[index],
+                None => continue,
+            };
             if let Some(mesh_renderer) = entity.get_component(COMPONENT_TYPE_MESH_RENDERER.clone()) {
                 if let Some(mesh) = mesh_renderer.get_parameter("mesh") {
                     // get the string value of the mesh
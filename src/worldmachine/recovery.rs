@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use crate::worldmachine::World;
+
+// autosaves go into the user's cache directory, along with a lock file that only exists while h2eck is running.
+// if the lock file is still there when we start up, the last session didn't shut down cleanly
+
+const AUTOSAVE_FILE: &str = "autosave.map";
+const AUTOSAVE_INFO_FILE: &str = "autosave.info";
+const SESSION_LOCK_FILE: &str = "session.lock";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AutosaveInfo {
+    // the map this autosave belongs to, None if it was never saved
+    pub original_path: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PendingRecovery {
+    pub autosave_path: String,
+    pub info: AutosaveInfo,
+}
+
+lazy_static! {
+    static ref SESSION_STARTED: Mutex<bool> = Mutex::new(false);
+    static ref UNCLEAN_SHUTDOWN: Mutex<bool> = Mutex::new(false);
+}
+
+pub fn recovery_dir() -> PathBuf {
+    glib::user_cache_dir().join("h2eck").join("recovery")
+}
+
+pub fn autosave_path() -> PathBuf {
+    recovery_dir().join(AUTOSAVE_FILE)
+}
+
+// should be called once on startup, remembers whether the previous session crashed
+pub fn begin_session() {
+    let dir = recovery_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        error!("failed to create recovery directory: {}", e);
+        return;
+    }
+    let lock = dir.join(SESSION_LOCK_FILE);
+    if lock.exists() {
+        warn!("previous session did not shut down cleanly");
+        *UNCLEAN_SHUTDOWN.lock().unwrap() = true;
+    }
+    if let Err(e) = std::fs::write(&lock, std::process::id().to_string()) {
+        error!("failed to write session lock: {}", e);
+        return;
+    }
+    *SESSION_STARTED.lock().unwrap() = true;
+}
+
+// should be called when h2eck exits normally
+pub fn end_session() {
+    let mut started = SESSION_STARTED.lock().unwrap();
+    if !*started {
+        return;
+    }
+    let _ = std::fs::remove_file(recovery_dir().join(SESSION_LOCK_FILE));
+    // a clean exit means the autosave isn't needed anymore
+    discard_autosave();
+    *started = false;
+}
+
+pub fn write_autosave(world: &World, original_path: Option<String>) -> Result<(), String> {
+    let dir = recovery_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create recovery directory: {}", e))?;
+    let serialized = serde_yaml::to_string(world).map_err(|e| format!("failed to serialize autosave: {}", e))?;
+    // write to a temporary file first so that a crash mid-write doesn't eat the previous autosave
+    let tmp = dir.join(format!("{}.tmp", AUTOSAVE_FILE));
+    std::fs::write(&tmp, serialized).map_err(|e| format!("failed to write autosave: {}", e))?;
+    std::fs::rename(&tmp, dir.join(AUTOSAVE_FILE)).map_err(|e| format!("failed to write autosave: {}", e))?;
+    let info = serde_yaml::to_string(&AutosaveInfo { original_path }).map_err(|e| format!("failed to serialize autosave info: {}", e))?;
+    std::fs::write(dir.join(AUTOSAVE_INFO_FILE), info).map_err(|e| format!("failed to write autosave info: {}", e))?;
+    debug!("autosaved to {:?}", dir.join(AUTOSAVE_FILE));
+    Ok(())
+}
+
+pub fn discard_autosave() {
+    let dir = recovery_dir();
+    let _ = std::fs::remove_file(dir.join(AUTOSAVE_FILE));
+    let _ = std::fs::remove_file(dir.join(AUTOSAVE_INFO_FILE));
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+// returns the autosave if the last session crashed and left one behind that's newer than the map it belongs to
+pub fn pending_recovery() -> Option<PendingRecovery> {
+    if !*UNCLEAN_SHUTDOWN.lock().unwrap() {
+        return None;
+    }
+    let autosave = autosave_path();
+    let autosave_modified = modified(&autosave)?;
+    let info = std::fs::read_to_string(recovery_dir().join(AUTOSAVE_INFO_FILE)).ok()
+        .and_then(|info| serde_yaml::from_str::<AutosaveInfo>(&info).ok())
+        .unwrap_or_default();
+    if let Some(original_path) = info.original_path.as_ref() {
+        if let Some(original_modified) = modified(Path::new(original_path)) {
+            if original_modified >= autosave_modified {
+                debug!("autosave is older than {}, not offering recovery", original_path);
+                return None;
+            }
+        }
+    }
+    Some(PendingRecovery {
+        autosave_path: autosave.to_str()?.to_string(),
+        info,
+    })
+}
+
+// autosaving has to wait until the user has decided what to do with the old autosave, or it'd be overwritten
+pub fn awaiting_recovery() -> bool {
+    *UNCLEAN_SHUTDOWN.lock().unwrap()
+}
+
+// only ask once per launch
+pub fn recovery_handled() {
+    *UNCLEAN_SHUTDOWN.lock().unwrap() = false;
+}
+
+fn backup_path(path: &str, index: u32) -> String {
+    format!("{}.bak{}", path, index)
+}
+
+// moves path to path.bak1, path.bak1 to path.bak2 and so on, dropping anything past count
+pub fn rotate_backups(path: &str, count: u32) {
+    if count == 0 || !Path::new(path).exists() {
+        return;
+    }
+    let _ = std::fs::remove_file(backup_path(path, count));
+    for index in (1..count).rev() {
+        let from = backup_path(path, index);
+        if Path::new(&from).exists() {
+            if let Err(e) = std::fs::rename(&from, backup_path(path, index + 1)) {
+                warn!("failed to rotate backup {}: {}", from, e);
+            }
+        }
+    }
+    if let Err(e) = std::fs::copy(path, backup_path(path, 1)) {
+        warn!("failed to back up {}: {}", path, e);
+    }
+}
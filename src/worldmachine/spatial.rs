@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use gfx_maths::{Mat4, Quaternion, Vec3, Vec4};
+use crate::renderer::camera::Camera;
+use crate::renderer::H2eckRenderer;
+use crate::renderer::mesh::{calculate_model_matrix, Mesh};
+use crate::renderer::raycasting::{inverse, Ray};
+use crate::worldmachine::components::{COMPONENT_TYPE_BOX_COLLIDER, COMPONENT_TYPE_MESH_RENDERER, COMPONENT_TYPE_TERRAIN, COMPONENT_TYPE_TRANSFORM};
+use crate::worldmachine::ecs::{Component, Entity, ParameterValue};
+
+// a dynamic bounding volume hierarchy over the world bounds of every top level entity, so that rendering and picking
+// don't have to look at every entity in the world. leaves are stored with some slack around them, so an entity that
+// moves a little (e.g. while being dragged) only updates its own bounds instead of being taken out and put back in
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // a box around the given points, which must not be empty
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let mut aabb = Self::new(points[0], points[0]);
+        for point in &points[1..] {
+            aabb.min = Vec3::new(aabb.min.x.min(point.x), aabb.min.y.min(point.y), aabb.min.z.min(point.z));
+            aabb.max = Vec3::new(aabb.max.x.max(point.x), aabb.max.y.max(point.y), aabb.max.z.max(point.z));
+        }
+        aabb
+    }
+
+    // a box around this one after it's been put through the given model matrix
+    pub fn transformed(&self, model: Mat4) -> Self {
+        let corners = self.corners().map(|corner| {
+            let corner = model * Vec4::new(corner.x, corner.y, corner.z, 1.0);
+            Vec3::new(corner.x, corner.y, corner.z)
+        });
+        Self::from_points(&corners)
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x, a.y, a.z), Vec3::new(b.x, a.y, a.z), Vec3::new(a.x, b.y, a.z), Vec3::new(b.x, b.y, a.z),
+            Vec3::new(a.x, a.y, b.z), Vec3::new(b.x, a.y, b.z), Vec3::new(a.x, b.y, b.z), Vec3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    pub fn expanded(&self, amount: Vec3) -> Aabb {
+        Aabb::new(self.min - amount, self.max + amount)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y && self.max.z >= other.max.z
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    // distance along the ray to where it enters the box (0 if it starts inside), None if it misses
+    pub fn ray_distance(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<f32> {
+        let mut t_min: f32 = 0.0;
+        let mut t_max = max_distance;
+        for (o, d, min, max) in [
+            (origin.x, direction.x, self.min.x, self.max.x),
+            (origin.y, direction.y, self.min.y, self.max.y),
+            (origin.z, direction.z, self.min.z, self.max.z),
+        ] {
+            if d.abs() < f32::EPSILON {
+                if o < min || o > max {
+                    return None;
+                }
+                continue;
+            }
+            let inv = 1.0 / d;
+            let (t0, t1) = if inv >= 0.0 { ((min - o) * inv, (max - o) * inv) } else { ((max - o) * inv, (min - o) * inv) };
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(t_min)
+    }
+}
+
+// a plane is inside when dot(normal, point) + distance >= 0
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    // a plane through three points, facing towards inside_point
+    fn from_points(a: Vec3, b: Vec3, c: Vec3, inside_point: Vec3) -> Plane {
+        let normal = (b - a).cross(c - a);
+        let length = (normal.x * normal.x + normal.y * normal.y + normal.z * normal.z).sqrt().max(f32::EPSILON);
+        let mut normal = normal / length;
+        let mut distance = -normal.dot(a);
+        if normal.dot(inside_point) + distance < 0.0 {
+            normal = -normal;
+            distance = -distance;
+        }
+        Plane { normal, distance }
+    }
+
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    pub fn from_camera(camera: &Camera) -> Frustum {
+        Frustum::from_view_projection(camera.get_projection() * camera.get_view())
+    }
+
+    // works out the corners of the frustum by un-projecting the corners of clip space, so this doesn't care how the
+    // matrix is laid out in memory
+    pub fn from_view_projection(view_projection: Mat4) -> Frustum {
+        let inverse_view_projection = inverse(view_projection);
+        let corner = |x: f32, y: f32, z: f32| {
+            let corner = inverse_view_projection * Vec4::new(x, y, z, 1.0);
+            Vec3::new(corner.x / corner.w, corner.y / corner.w, corner.z / corner.w)
+        };
+        // near then far, each bottom left, bottom right, top right, top left
+        let n = [corner(-1.0, -1.0, -1.0), corner(1.0, -1.0, -1.0), corner(1.0, 1.0, -1.0), corner(-1.0, 1.0, -1.0)];
+        let f = [corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)];
+        let center = Aabb::from_points(&[n[0], n[1], n[2], n[3], f[0], f[1], f[2], f[3]]).center();
+        Frustum {
+            planes: [
+                Plane::from_points(n[0], n[1], n[2], center), // near
+                Plane::from_points(f[0], f[2], f[1], center), // far
+                Plane::from_points(n[0], f[0], n[3], center), // left
+                Plane::from_points(n[1], n[2], f[1], center), // right
+                Plane::from_points(n[0], n[1], f[0], center), // bottom
+                Plane::from_points(n[3], f[3], n[2], center), // top
+            ],
+        }
+    }
+
+    // conservative, may say yes for boxes just outside a corner of the frustum
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        for plane in self.planes.iter() {
+            // the corner of the box furthest along the plane's normal
+            let furthest = Vec3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            if plane.distance_to(furthest) < 0.0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// where a ray hit an entity's geometry, see WorldMachine::raycast
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub uid: u64,
+    pub component: usize, // index of the component that drew what was hit
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+const NULL_NODE: usize = usize::MAX;
+
+#[derive(Clone, Debug)]
+struct Node {
+    fat: Aabb, // for leaves, the entity's bounds with some slack, for branches the union of both children
+    bounds: Aabb, // the entity's actual bounds, only used by leaves
+    parent: usize,
+    left: usize,
+    right: usize,
+    uid: Option<u64>, // Some for leaves
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.uid.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: usize,
+    leaves: HashMap<u64, usize>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NULL_NODE,
+            leaves: HashMap::new(),
+        }
+    }
+}
+
+impl SpatialIndex {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn contains(&self, uid: u64) -> bool {
+        self.leaves.contains_key(&uid)
+    }
+
+    pub fn bounds(&self, uid: u64) -> Option<Aabb> {
+        self.leaves.get(&uid).map(|leaf| self.nodes[*leaf].bounds)
+    }
+
+    // the bounds of everything in the index, None if it's empty
+    pub fn total_bounds(&self) -> Option<Aabb> {
+        self.leaves.values().map(|leaf| self.nodes[*leaf].bounds).reduce(|a, b| a.union(&b))
+    }
+
+    // adds the entity, or moves it if it's already in here
+    pub fn update(&mut self, uid: u64, bounds: Aabb) {
+        if let Some(leaf) = self.leaves.get(&uid).copied() {
+            if self.nodes[leaf].fat.contains(&bounds) {
+                self.nodes[leaf].bounds = bounds;
+                return;
+            }
+            self.remove_leaf(leaf);
+            self.free_node(leaf);
+        }
+        // 10% of the size plus a little, so small moves don't need the tree to change
+        let slack = bounds.size() * 0.1 + Vec3::new(0.1, 0.1, 0.1);
+        let leaf = self.allocate_node(Node {
+            fat: bounds.expanded(slack),
+            bounds,
+            parent: NULL_NODE,
+            left: NULL_NODE,
+            right: NULL_NODE,
+            uid: Some(uid),
+        });
+        self.insert_leaf(leaf);
+        self.leaves.insert(uid, leaf);
+    }
+
+    pub fn remove(&mut self, uid: u64) {
+        if let Some(leaf) = self.leaves.remove(&uid) {
+            self.remove_leaf(leaf);
+            self.free_node(leaf);
+        }
+    }
+
+    // every entity whose bounds the ray passes through, nearest first
+    pub fn query_ray(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Vec<(u64, f32)> {
+        let mut hits = Vec::new();
+        self.traverse(|node| node.fat.ray_distance(origin, direction, max_distance).is_some(), |node| {
+            if let Some(distance) = node.bounds.ray_distance(origin, direction, max_distance) {
+                hits.push((node.uid.unwrap(), distance));
+            }
+        });
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        hits
+    }
+
+    pub fn query_picking_ray(&self, ray: &Ray) -> Vec<(u64, f32)> {
+        let direction = Vec3::new(ray.direction.x, ray.direction.y, ray.direction.z);
+        self.query_ray(ray.origin, direction, ray.max_distance)
+    }
+
+    // every entity whose bounds might be visible
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<u64> {
+        let mut hits = Vec::new();
+        self.traverse(|node| frustum.intersects(&node.fat), |node| {
+            if frustum.intersects(&node.bounds) {
+                hits.push(node.uid.unwrap());
+            }
+        });
+        hits
+    }
+
+    // every entity whose bounds overlap the box
+    pub fn query_box(&self, aabb: &Aabb) -> Vec<u64> {
+        let mut hits = Vec::new();
+        self.traverse(|node| node.fat.intersects(aabb), |node| {
+            if node.bounds.intersects(aabb) {
+                hits.push(node.uid.unwrap());
+            }
+        });
+        hits
+    }
+
+    fn traverse(&self, mut enter: impl FnMut(&Node) -> bool, mut leaf: impl FnMut(&Node)) {
+        if self.root == NULL_NODE {
+            return;
+        }
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !enter(node) {
+                continue;
+            }
+            if node.is_leaf() {
+                leaf(node);
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+    }
+
+    fn allocate_node(&mut self, node: Node) -> usize {
+        if let Some(index) = self.free.pop() {
+            self.nodes[index] = node;
+            index
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+
+    fn free_node(&mut self, index: usize) {
+        self.nodes[index].uid = None;
+        self.nodes[index].parent = NULL_NODE;
+        self.free.push(index);
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL_NODE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL_NODE;
+            return;
+        }
+
+        // walk down to the sibling that grows the least by having the leaf added to it
+        let leaf_aabb = self.nodes[leaf].fat;
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+            let combined_area = node.fat.union(&leaf_aabb).surface_area();
+            // cost of making a new parent for the node and the leaf here
+            let cost = 2.0 * combined_area;
+            // minimum cost of pushing the leaf further down
+            let inheritance_cost = 2.0 * (combined_area - node.fat.surface_area());
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let area = child.fat.union(&leaf_aabb).surface_area();
+                if child.is_leaf() { area + inheritance_cost } else { area - child.fat.surface_area() + inheritance_cost }
+            };
+            let (left, right) = (node.left, node.right);
+            let (cost_left, cost_right) = (child_cost(left), child_cost(right));
+            if cost < cost_left && cost < cost_right {
+                break;
+            }
+            index = if cost_left < cost_right { left } else { right };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate_node(Node {
+            fat: self.nodes[sibling].fat.union(&leaf_aabb),
+            bounds: self.nodes[sibling].fat.union(&leaf_aabb),
+            parent: old_parent,
+            left: sibling,
+            right: leaf,
+            uid: None,
+        });
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+        if old_parent == NULL_NODE {
+            self.root = new_parent;
+        } else if self.nodes[old_parent].left == sibling {
+            self.nodes[old_parent].left = new_parent;
+        } else {
+            self.nodes[old_parent].right = new_parent;
+        }
+        self.refit(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL_NODE;
+            return;
+        }
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf { self.nodes[parent].right } else { self.nodes[parent].left };
+        if grandparent == NULL_NODE {
+            self.root = sibling;
+            self.nodes[sibling].parent = NULL_NODE;
+        } else {
+            if self.nodes[grandparent].left == parent {
+                self.nodes[grandparent].left = sibling;
+            } else {
+                self.nodes[grandparent].right = sibling;
+            }
+            self.nodes[sibling].parent = grandparent;
+        }
+        self.free_node(parent);
+        self.refit(grandparent);
+    }
+
+    // recalculates the bounds of index and everything above it
+    fn refit(&mut self, mut index: usize) {
+        while index != NULL_NODE {
+            let (left, right) = (self.nodes[index].left, self.nodes[index].right);
+            let fat = self.nodes[left].fat.union(&self.nodes[right].fat);
+            self.nodes[index].fat = fat;
+            self.nodes[index].bounds = fat;
+            index = self.nodes[index].parent;
+        }
+    }
+}
+
+fn vec3_parameter(entity: &Entity, component: &Component, name: &str) -> Option<Vec3> {
+    match component.get_parameter(name)?.value {
+        ParameterValue::Vec3(v) => Some(v),
+        _ => {
+            warn!("{} on {} is not a vec3", name, entity.name);
+            None
+        }
+    }
+}
+
+// the entity's transform position, rotation and scale (if it has a transform)
+fn transform(entity: &Entity) -> (Vec3, Quaternion, Option<Vec3>) {
+    let mut position = Vec3::new(0.0, 0.0, 0.0);
+    let mut rotation = Quaternion::identity();
+    let mut scale = None;
+    if let Some(transform) = entity.get_component(COMPONENT_TYPE_TRANSFORM.clone()) {
+        if let Some(p) = vec3_parameter(entity, transform, "position") {
+            position = p;
+        }
+        if let Some(ParameterValue::Quaternion(r)) = transform.get_parameter("rotation").map(|p| &p.value) {
+            rotation = *r;
+        }
+        scale = vec3_parameter(entity, transform, "scale");
+    }
+    (position, rotation, scale)
+}
+
+fn mesh_bounds(mesh: &Mesh) -> Aabb {
+    Aabb::new(mesh.top_left, mesh.bottom_right).transformed(calculate_model_matrix(mesh.position, mesh.rotation, mesh.scale))
+}
+
+// the meshes an entity draws (meshes, terrain and visualised colliders), placed the same way WorldMachine::render
+// places them, along with the index of the component each one belongs to. anything that isn't loaded yet is left out
+pub fn entity_meshes(entity: &Entity, renderer: &H2eckRenderer) -> Vec<(usize, Mesh)> {
+    let (position, rotation, transform_scale) = transform(entity);
+    let mut meshes = Vec::new();
+    for (index, component) in entity.get_components().iter().enumerate() {
+        match component.get_type() {
+            x if x == COMPONENT_TYPE_MESH_RENDERER.clone() => {
+                if let Some(ParameterValue::String(name)) = component.get_parameter("mesh").map(|p| &p.value) {
+                    if let Some(mesh) = renderer.meshes.as_ref().and_then(|m| m.get(name)) {
+                        let mut mesh = *mesh;
+                        mesh.position += position;
+                        mesh.rotation = rotation;
+                        // meshes and terrain add the transform's scale to their own
+                        if let Some(scale) = transform_scale {
+                            mesh.scale += scale;
+                        }
+                        meshes.push((index, mesh));
+                    }
+                }
+            }
+            x if x == COMPONENT_TYPE_TERRAIN.clone() => {
+                if let Some(ParameterValue::String(name)) = component.get_parameter("name").map(|p| &p.value) {
+                    if let Some(terrain) = renderer.terrains.as_ref().and_then(|t| t.get(name)) {
+                        let mut mesh = terrain.mesh;
+                        mesh.position += position;
+                        mesh.rotation = rotation;
+                        if let Some(scale) = transform_scale {
+                            mesh.scale += scale;
+                        }
+                        meshes.push((index, mesh));
+                    }
+                }
+            }
+            x if x == COMPONENT_TYPE_BOX_COLLIDER.clone() => {
+                if let Some(ParameterValue::Bool(true)) = component.get_parameter("visualise").map(|p| &p.value) {
+                    if let Some(mesh) = renderer.meshes.as_ref().and_then(|m| m.get("boxviz")) {
+                        let mut mesh = *mesh;
+                        let collider_position = vec3_parameter(entity, component, "position").unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+                        let collider_size = vec3_parameter(entity, component, "size").unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+                        mesh.position = collider_position + position;
+                        mesh.rotation = rotation;
+                        // but colliders are multiplied by it
+                        mesh.scale = match transform_scale {
+                            Some(s) => Vec3::new(collider_size.x * s.x, collider_size.y * s.y, collider_size.z * s.z),
+                            None => collider_size,
+                        };
+                        meshes.push((index, mesh));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    meshes
+}
+
+// the world space bounds of everything the entity draws, None if it doesn't draw anything or what it draws isn't
+// loaded yet
+pub fn entity_bounds(entity: &Entity, renderer: &H2eckRenderer) -> Option<Aabb> {
+    entity_meshes(entity, renderer).iter()
+        .map(|(_, mesh)| mesh_bounds(mesh))
+        .reduce(|a, b| a.union(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(center: Vec3, half_size: f32) -> Aabb {
+        let half = Vec3::new(half_size, half_size, half_size);
+        Aabb::new(center - half, center + half)
+    }
+
+    fn sorted(mut uids: Vec<u64>) -> Vec<u64> {
+        uids.sort();
+        uids
+    }
+
+    // a camera at the origin looking down -z, like an identity view matrix
+    fn perspective_frustum() -> Frustum {
+        Frustum::from_view_projection(Mat4::perspective_opengl(90.0f32.to_radians(), 0.1, 100.0, 1.0))
+    }
+
+    #[test]
+    fn insert_move_and_remove() {
+        let mut index = SpatialIndex::default();
+        for uid in 0..20 {
+            index.update(uid, cube(Vec3::new(uid as f32 * 10.0, 0.0, 0.0), 1.0));
+        }
+        assert_eq!(index.len(), 20);
+        assert_eq!(index.query_box(&cube(Vec3::new(50.0, 0.0, 0.0), 2.0)), vec![5]);
+
+        // a small move stays inside the slack, a big one doesn't, both have to be found where they went
+        index.update(5, cube(Vec3::new(50.05, 0.0, 0.0), 1.0));
+        assert_eq!(index.bounds(5), Some(cube(Vec3::new(50.05, 0.0, 0.0), 1.0)));
+        index.update(5, cube(Vec3::new(500.0, 0.0, 0.0), 1.0));
+        assert!(index.query_box(&cube(Vec3::new(50.0, 0.0, 0.0), 2.0)).is_empty());
+        assert_eq!(index.query_box(&cube(Vec3::new(500.0, 0.0, 0.0), 2.0)), vec![5]);
+        assert_eq!(index.len(), 20);
+
+        index.remove(5);
+        index.remove(5);
+        assert!(!index.contains(5));
+        assert!(index.query_box(&cube(Vec3::new(500.0, 0.0, 0.0), 2.0)).is_empty());
+        assert_eq!(index.len(), 19);
+        // everything else is still there
+        let everything = cube(Vec3::new(0.0, 0.0, 0.0), 1000.0);
+        assert_eq!(sorted(index.query_box(&everything)), (0..20).filter(|uid| *uid != 5).collect::<Vec<u64>>());
+
+        for uid in 0..20 {
+            index.remove(uid);
+        }
+        assert!(index.is_empty());
+        assert!(index.query_box(&everything).is_empty());
+        assert_eq!(index.total_bounds(), None);
+    }
+
+    #[test]
+    fn total_bounds() {
+        let mut index = SpatialIndex::default();
+        index.update(1, cube(Vec3::new(-5.0, 0.0, 0.0), 1.0));
+        index.update(2, cube(Vec3::new(5.0, 2.0, 0.0), 1.0));
+        assert_eq!(index.total_bounds(), Some(Aabb::new(Vec3::new(-6.0, -1.0, -1.0), Vec3::new(6.0, 3.0, 1.0))));
+    }
+
+    #[test]
+    fn perspective_frustum_in_and_out() {
+        let frustum = perspective_frustum();
+        assert!(frustum.intersects(&cube(Vec3::new(0.0, 0.0, -10.0), 1.0)));
+        // behind, past the far plane, and off to the side
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, 0.0, 10.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, 0.0, -200.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(50.0, 0.0, -10.0), 1.0)));
+        // poking in from the edge counts
+        assert!(frustum.intersects(&cube(Vec3::new(11.5, 0.0, -10.0), 2.0)));
+
+        let mut index = SpatialIndex::default();
+        index.update(1, cube(Vec3::new(0.0, 0.0, -10.0), 1.0));
+        index.update(2, cube(Vec3::new(0.0, 0.0, 10.0), 1.0));
+        index.update(3, cube(Vec3::new(3.0, -2.0, -50.0), 1.0));
+        index.update(4, cube(Vec3::new(50.0, 0.0, -10.0), 1.0));
+        assert_eq!(sorted(index.query_frustum(&frustum)), vec![1, 3]);
+    }
+
+    #[test]
+    fn orthographic_frustum_in_and_out() {
+        // like the frustum a directional light's shadow map uses, a box around the origin
+        let frustum = Frustum::from_view_projection(Mat4::orthographic_opengl(-5.0, 5.0, -5.0, 5.0, -5.0, 5.0));
+        assert!(frustum.intersects(&cube(Vec3::new(0.0, 0.0, 0.0), 1.0)));
+        assert!(frustum.intersects(&cube(Vec3::new(4.0, -4.0, 4.0), 0.5)));
+        assert!(!frustum.intersects(&cube(Vec3::new(20.0, 0.0, 0.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, -20.0, 0.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, 0.0, 20.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, 0.0, -20.0), 1.0)));
+
+        let mut index = SpatialIndex::default();
+        index.update(1, cube(Vec3::new(0.0, 0.0, 0.0), 1.0));
+        index.update(2, cube(Vec3::new(20.0, 0.0, 0.0), 1.0));
+        assert_eq!(index.query_frustum(&frustum), vec![1]);
+    }
+
+    #[test]
+    fn moved_frustum() {
+        // the camera moved to x = 50, views move the world the other way
+        let frustum = Frustum::from_view_projection(Mat4::perspective_opengl(90.0f32.to_radians(), 0.1, 100.0, 1.0) * Mat4::translate(Vec3::new(-50.0, 0.0, 0.0)));
+        assert!(frustum.intersects(&cube(Vec3::new(50.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects(&cube(Vec3::new(0.0, 0.0, -10.0), 1.0)));
+    }
+
+    #[test]
+    fn ray_nearest_first() {
+        let mut index = SpatialIndex::default();
+        // added out of order so that the tree doesn't happen to hand them back sorted
+        index.update(3, cube(Vec3::new(0.0, 0.0, -30.0), 1.0));
+        index.update(1, cube(Vec3::new(0.0, 0.0, -10.0), 1.0));
+        index.update(4, cube(Vec3::new(5.0, 0.0, -15.0), 1.0)); // off to the side of the ray
+        index.update(2, cube(Vec3::new(0.0, 0.0, -20.0), 1.0));
+
+        let hits = index.query_ray(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 100.0);
+        assert_eq!(hits.iter().map(|(uid, _)| *uid).collect::<Vec<u64>>(), vec![1, 2, 3]);
+        assert!((hits[0].1 - 9.0).abs() < 1e-4);
+        assert!((hits[1].1 - 19.0).abs() < 1e-4);
+
+        // not far enough to reach the last one
+        let hits = index.query_ray(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 25.0);
+        assert_eq!(hits.iter().map(|(uid, _)| *uid).collect::<Vec<u64>>(), vec![1, 2]);
+
+        // and pointing away hits nothing
+        assert!(index.query_ray(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 100.0).is_empty());
+    }
+}